@@ -0,0 +1,2336 @@
+//! Core compression pipeline, usable without the Tauri frontend.
+
+use anyhow::anyhow;
+use image::GenericImageView;
+use image::ImageFormat;
+use image::ImageOutputFormat;
+use img_parts::ImageEXIF;
+use imagequant::RGBA as QuantRgba;
+use oxipng::StripChunks;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::{fs, path::Path};
+use uuid::Uuid;
+
+// Global temporary directory tracker to clean up resources
+lazy_static::lazy_static! {
+    static ref TEMP_DIRS: Mutex<HashMap<String, std::time::SystemTime>> = Mutex::new(HashMap::new());
+}
+
+/// Per-write temp directories (non-atomic writes) live under this one in the
+/// system temp dir.
+pub const COMPRESS_TEMP_DIR: &str = "Ximage-compress";
+
+/// Temp directories created so far, with their creation times.
+pub fn tracked_temp_dirs() -> HashMap<String, std::time::SystemTime> {
+    TEMP_DIRS.lock().unwrap().clone()
+}
+
+/// Records `dir` so [`tracked_temp_dirs`] reports it with its creation time.
+pub fn track_temp_dir(dir: &Path) {
+    // The map holds plain data, so a panic elsewhere cannot leave it torn
+    let mut dirs = TEMP_DIRS.lock().unwrap_or_else(|e| e.into_inner());
+    dirs.insert(
+        dir.to_string_lossy().to_string(),
+        std::time::SystemTime::now(),
+    );
+}
+
+pub const DEFAULT_IO_RETRY_ATTEMPTS: u8 = 3;
+pub const DEFAULT_IO_RETRY_DELAY_MS: u64 = 100;
+
+/// Default quality and accepted range for each lossy encoder.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityDefaults {
+    pub jpeg_default: u8,
+    pub jpeg_min: u8,
+    pub jpeg_max: u8,
+    pub webp_default: u8,
+    pub webp_min: u8,
+    pub webp_max: u8,
+    pub png_default: u8,
+    pub png_min: u8,
+    pub png_max: u8,
+}
+
+/// The ranges [`validate_config`] accepts. PNG targets below 10 would only
+/// be clamped by the quantizer; the JPEG candidate search narrows its base
+/// quality further, to [`JPEG_CANDIDATE_QUALITY`].
+pub const QUALITY_DEFAULTS: QualityDefaults = QualityDefaults {
+    jpeg_default: 50,
+    jpeg_min: 1,
+    jpeg_max: 100,
+    webp_default: 65,
+    webp_min: 0,
+    webp_max: 100,
+    png_default: 80,
+    png_min: 10,
+    png_max: 100,
+};
+
+/// WebP encoding mode, overriding the plain `lossless` switch when set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WebpMode {
+    Lossy,
+    Lossless,
+    /// Lossless encoding after lossy preprocessing; `level` 0 is the
+    /// strongest preprocessing, 100 disables it.
+    NearLossless { level: u8 },
+}
+
+/// Dithering applied when PNGs are reduced to a palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PngDitheringMethod {
+    /// Error diffusion by imagequant, at a level that rises as quality drops.
+    #[default]
+    FloydSteinberg,
+    /// Plain nearest-colour remapping.
+    None,
+    /// Bayer-matrix threshold noise; see `png_ordered_dither_size`.
+    Ordered,
+}
+
+/// How the JPEG quality is chosen.
+///
+/// `Direct` performs a single encode, while `MultiCandidate` encodes the base
+/// quality plus up to five neighbours and keeps the best scoring one, so it
+/// costs roughly six times the encode work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum JpegStrategy {
+    /// Score candidates within `quality_range` of the configured quality.
+    MultiCandidate { quality_range: u8 },
+    /// Encode once at the configured quality.
+    Direct,
+}
+
+/// I/O scheduling priority of batch worker threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IoPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// Which encoder writes JPEGs.
+///
+/// `MozJpeg` typically produces files 10-15% smaller than `Default` at the
+/// same quality setting, thanks to trellis quantisation and tuned tables, but
+/// encodes roughly 3-5x slower. It needs the `mozjpeg` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum JpegBackend {
+    /// The built-in path: the `image` encoder, or libjpeg-turbo when
+    /// `jpeg_optimize_huffman` is set.
+    #[default]
+    Default,
+    /// mozjpeg. Without `progressive` its fastest baseline profile is used,
+    /// which also turns trellis quantisation off.
+    MozJpeg {
+        progressive: bool,
+        optimize_coding: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CompressionConfig {
+    pub lossless: bool,
+    pub quality_jpg: u8,
+    pub quality_webp: u8,
+    pub quality_png: u8,
+    pub preserve_exif: bool,
+    pub resize_width: Option<u32>,
+    pub resize_height: Option<u32>,
+    pub png_interlaced: bool,
+    pub io_retry_attempts: u8,
+    pub io_retry_initial_delay_ms: u64,
+    pub webp_mode: Option<WebpMode>,
+    /// Output format name (`"jpeg"`, `"png"`, `"webp"`); defaults to the
+    /// source format, or JPEG for HEIF sources which cannot be written back.
+    pub output_format: Option<String>,
+    /// Carry XMP from JPEG or PNG sources into PNG outputs as an iTXt chunk.
+    pub embed_xmp: bool,
+    /// Quantize PNGs larger than `tile * tile` pixels in tiles of this size
+    /// to bound memory use; see `quantize_tiled`.
+    pub large_image_tile_size: Option<u32>,
+    /// Defaults to `MultiCandidate { quality_range: 10 }`; ignored for
+    /// lossless, which always encodes once at quality 100.
+    pub jpeg_strategy: Option<JpegStrategy>,
+    /// Write outputs via a temp file and rename instead of copying over the
+    /// target, so an interrupted write cannot corrupt it.
+    pub atomic_write: bool,
+    /// Run the full pipeline but write nothing; sizes and the would-be
+    /// output paths are still reported.
+    pub dry_run: bool,
+    /// Guarantee JPEG outputs carry no restart markers (RST0-RST7). The
+    /// built-in encoder never writes a restart interval; output from other
+    /// backends is losslessly re-coded without one when needed.
+    pub jpeg_strip_restart_markers: bool,
+    /// Warn before large batches when the target disk looks too full.
+    pub check_disk_space: bool,
+    /// Give outputs the source file's mode bits instead of the umask
+    /// defaults. Unix only; ignored on Windows.
+    pub preserve_file_permissions: bool,
+    /// Defaults to `FloydSteinberg`.
+    pub png_dithering_method: Option<PngDitheringMethod>,
+    /// Bayer matrix side (2, 4 or 8) for `PngDitheringMethod::Ordered`.
+    pub png_ordered_dither_size: u8,
+    /// Encode JPEGs through libjpeg-turbo with a statistics pass, so the
+    /// Huffman tables fit this image instead of the standard ones. Lossless
+    /// with respect to pixels; typically 3-8% smaller.
+    pub jpeg_optimize_huffman: bool,
+    /// Defaults to `JpegBackend::Default`.
+    pub jpeg_backend: Option<JpegBackend>,
+    /// Start batch files at least this far apart, easing seek contention on
+    /// spinning disks; see [`Pacer`].
+    pub throttle_ms: Option<u64>,
+    /// Defaults to `IoPriority::Normal`; see [`set_thread_io_priority`].
+    pub io_priority: Option<IoPriority>,
+    /// Keep the colour under fully transparent pixels in lossless WebP
+    /// output, so it decodes back exactly. Off by default since libwebp can
+    /// otherwise simplify those pixels for a smaller file.
+    pub webp_exact: bool,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            lossless: false,
+            quality_jpg: QUALITY_DEFAULTS.jpeg_default,
+            quality_webp: QUALITY_DEFAULTS.webp_default,
+            quality_png: QUALITY_DEFAULTS.png_default,
+            preserve_exif: true,
+            resize_width: None,
+            resize_height: None,
+            png_interlaced: false,
+            io_retry_attempts: DEFAULT_IO_RETRY_ATTEMPTS,
+            io_retry_initial_delay_ms: DEFAULT_IO_RETRY_DELAY_MS,
+            webp_mode: None,
+            output_format: None,
+            embed_xmp: false,
+            large_image_tile_size: None,
+            jpeg_strategy: None,
+            atomic_write: true,
+            dry_run: false,
+            jpeg_strip_restart_markers: false,
+            check_disk_space: false,
+            preserve_file_permissions: false,
+            png_dithering_method: None,
+            png_ordered_dither_size: 4,
+            jpeg_optimize_huffman: true,
+            jpeg_backend: None,
+            throttle_ms: None,
+            io_priority: None,
+            webp_exact: false,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Parse the camelCase JSON sent by the frontend; missing fields take
+    /// their defaults. Values are not range-checked, see [`validate_config`].
+    pub fn from_json(s: &str) -> anyhow::Result<Self> {
+        serde_json::from_str(s).map_err(|e| anyhow!("Invalid compression config: {}", e))
+    }
+
+    /// Pretty-printed JSON that [`CompressionConfig::from_json`] reads back.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self)
+            .expect("CompressionConfig is plain data with string keys and always serializes")
+    }
+}
+
+/// One problem found by [`validate_config`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigValidationError {
+    pub field: String,
+    pub value: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}: {}", self.field, self.value, self.message)
+    }
+}
+
+/// Check every field up front and report all problems at once, instead of
+/// failing on the first one deep inside an encoder.
+pub fn validate_config(config: &CompressionConfig) -> Result<(), Vec<ConfigValidationError>> {
+    let mut errors = Vec::new();
+    let mut check = |valid: bool, field: &str, value: String, message: &str| {
+        if !valid {
+            errors.push(ConfigValidationError {
+                field: field.to_string(),
+                value,
+                message: message.to_string(),
+            });
+        }
+    };
+
+    let q = QUALITY_DEFAULTS;
+    for (field, value, min, max) in [
+        ("qualityJpg", config.quality_jpg, q.jpeg_min, q.jpeg_max),
+        ("qualityWebp", config.quality_webp, q.webp_min, q.webp_max),
+        ("qualityPng", config.quality_png, q.png_min, q.png_max),
+    ] {
+        check(
+            (min..=max).contains(&value),
+            field,
+            value.to_string(),
+            &format!("must be between {} and {}", min, max),
+        );
+    }
+    for (field, value) in [
+        ("resizeWidth", config.resize_width),
+        ("resizeHeight", config.resize_height),
+        ("largeImageTileSize", config.large_image_tile_size),
+    ] {
+        if let Some(value) = value {
+            check(
+                value > 0,
+                field,
+                value.to_string(),
+                "must be greater than 0",
+            );
+        }
+    }
+    if let Some(name) = &config.output_format {
+        check(
+            format_from_name(name).is_ok(),
+            "outputFormat",
+            name.clone(),
+            "must be one of png, jpeg, webp",
+        );
+    }
+    if let Some(WebpMode::NearLossless { level }) = config.webp_mode {
+        check(
+            level <= 100,
+            "webpMode.level",
+            level.to_string(),
+            "must be between 0 and 100",
+        );
+    }
+    if let Some(JpegStrategy::MultiCandidate { quality_range }) = config.jpeg_strategy {
+        check(
+            quality_range <= 100,
+            "jpegStrategy.qualityRange",
+            quality_range.to_string(),
+            "must be between 0 and 100",
+        );
+    }
+    if config.png_dithering_method == Some(PngDitheringMethod::Ordered) {
+        check(
+            matches!(config.png_ordered_dither_size, 2 | 4 | 8),
+            "pngOrderedDitherSize",
+            config.png_ordered_dither_size.to_string(),
+            "must be 2, 4 or 8",
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// One top-level field that differs between two configs, see [`config_diff`].
+/// Values are rendered as JSON, except strings which are shown bare.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigFieldChange {
+    pub field_name: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// The camelCase fields whose values differ between `a` and `b`, in key
+/// order. Nested settings such as `webpMode` are compared as a whole.
+pub fn config_diff(a: &CompressionConfig, b: &CompressionConfig) -> Vec<ConfigFieldChange> {
+    let to_map = |config: &CompressionConfig| match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let render = |value: Option<&serde_json::Value>| match value {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    };
+    let (old, new) = (to_map(a), to_map(b));
+    let keys: std::collections::BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| ConfigFieldChange {
+            field_name: key.clone(),
+            old_value: render(old.get(key)),
+            new_value: render(new.get(key)),
+        })
+        .collect()
+}
+
+pub fn detect_image_format(path: &Path) -> anyhow::Result<ImageFormat> {
+    let ext = path
+        .extension()
+        .ok_or(anyhow!("No extension"))?
+        .to_string_lossy()
+        .to_lowercase();
+
+    match ext.as_str() {
+        "png" => Ok(ImageFormat::Png),
+        "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+        "webp" => Ok(ImageFormat::WebP),
+        _ => Err(anyhow!("Unsupported format: {}", ext)),
+    }
+}
+
+/// Identify an image format from its leading magic bytes.
+///
+/// Returns `"jpeg"`, `"png"`, `"webp"`, `"gif"`, `"avif"`, `"heif"`,
+/// `"tiff"`, `"bmp"`, or `"unknown"`; 32 bytes is always enough.
+pub fn detect_format_from_bytes(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => "jpeg",
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => "png",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => "tiff",
+        [b'B', b'M', ..] => "bmp",
+        // ISO base media file: size, "ftyp", then the major brand
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.len() >= 4 => match &brand[..4] {
+            b"avif" | b"avis" => "avif",
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1" => "heif",
+            _ => "unknown",
+        },
+        _ => "unknown",
+    }
+}
+
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::WebP => "webp",
+        _ => "unknown",
+    }
+}
+
+pub fn format_from_name(name: &str) -> anyhow::Result<ImageFormat> {
+    match name.to_lowercase().as_str() {
+        "png" => Ok(ImageFormat::Png),
+        "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+        "webp" => Ok(ImageFormat::WebP),
+        _ => Err(anyhow!("Unsupported output format: {}", name)),
+    }
+}
+
+pub fn mime_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image/png",
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::WebP => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn format_extension(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Jpeg => "jpg",
+        _ => format_name(format),
+    }
+}
+
+pub fn is_heif_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "heic" || ext == "heif"
+        })
+        .unwrap_or(false)
+}
+
+/// Decode the primary image of a HEIF container, returning the raw EXIF
+/// (TIFF) block alongside when present.
+///
+/// Requires the `heif` feature and `libheif` installed as a system library.
+#[cfg(feature = "heif")]
+pub fn decode_heif(bytes: &[u8]) -> anyhow::Result<(image::DynamicImage, Option<Vec<u8>>)> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib_heif = LibHeif::new();
+    let ctx = HeifContext::read_from_bytes(bytes)?;
+    let handle = ctx.primary_image_handle()?;
+    let decoded = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
+
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or(anyhow!("HEIF image has no interleaved RGB plane"))?;
+    let (width, height) = (plane.width, plane.height);
+    let row_bytes = width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_bytes * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+    let rgb = image::RgbImage::from_raw(width, height, pixels)
+        .ok_or(anyhow!("HEIF plane size mismatch"))?;
+
+    // HEIF EXIF items start with a 4-byte offset to the TIFF header
+    let exif = handle
+        .metadata_block_ids(b"Exif")
+        .into_iter()
+        .next()
+        .and_then(|id| handle.metadata(id).ok())
+        .and_then(|block| {
+            let offset = u32::from_be_bytes(block.get(..4)?.try_into().ok()?) as usize;
+            block.get(4 + offset..).map(|tiff| tiff.to_vec())
+        });
+
+    Ok((image::DynamicImage::ImageRgb8(rgb), exif))
+}
+
+#[cfg(not(feature = "heif"))]
+pub fn decode_heif(_bytes: &[u8]) -> anyhow::Result<(image::DynamicImage, Option<Vec<u8>>)> {
+    Err(anyhow!("HEIF support requires building with the `heif` feature"))
+}
+
+/// Where `compress_image` writes its result. A converted image written in
+/// place lands next to the source with the new extension instead of
+/// replacing it.
+pub fn final_output_path(
+    original_path: &Path,
+    output_path: Option<&Path>,
+    output_format: ImageFormat,
+) -> PathBuf {
+    let path = output_path.unwrap_or(original_path);
+    match detect_image_format(path) {
+        Ok(format) if format == output_format => path.to_path_buf(),
+        _ => path.with_extension(format_extension(output_format)),
+    }
+}
+
+pub fn preserve_exif_data(original_path: &Path, compressed_path: &Path, format: ImageFormat) -> anyhow::Result<()> {
+    let original_bytes = fs::read(original_path)?;
+    let compressed_bytes = fs::read(compressed_path)?;
+    
+    let exif_data: Option<img_parts::Bytes> = match format {
+        ImageFormat::Jpeg => {
+            let jpeg = img_parts::jpeg::Jpeg::from_bytes(original_bytes.into())?;
+            jpeg.exif()
+        }
+        _ => None,
+    };
+    
+    if let Some(exif) = exif_data {
+        let new_bytes = match format {
+            ImageFormat::Jpeg => {
+                let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(compressed_bytes.into())?;
+                jpeg.set_exif(Some(exif));
+                let mut output = Vec::new();
+                jpeg.encoder().write_to(&mut output)?;
+                output
+            }
+            _ => compressed_bytes,
+        };
+        fs::write(compressed_path, new_bytes)?;
+    }
+    
+    Ok(())
+}
+
+fn to_quant_pixels(raw: &[u8]) -> Vec<QuantRgba> {
+    raw.chunks_exact(4)
+        .map(|c| QuantRgba::new(c[0], c[1], c[2], c[3]))
+        .collect()
+}
+
+fn nearest_palette_index(palette: &[QuantRgba], color: &QuantRgba) -> u8 {
+    let distance = |p: &QuantRgba| {
+        let dr = p.r as i32 - color.r as i32;
+        let dg = p.g as i32 - color.g as i32;
+        let db = p.b as i32 - color.b as i32;
+        let da = p.a as i32 - color.a as i32;
+        dr * dr + dg * dg + db * db + da * da
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| distance(p))
+        .map(|(index, _)| index as u8)
+        .unwrap_or(0)
+}
+
+// Quantize tile by tile against one palette derived from a downsampled copy,
+// so the full-size pixel vector is never built. Tiles are remapped by
+// imagequant and their colours snapped to the nearest global palette entry,
+// which makes palette consistency across tiles approximate.
+fn quantize_tiled(
+    img: &image::DynamicImage,
+    tile_size: u32,
+    attr: &imagequant::Attributes,
+    dither: f32,
+) -> anyhow::Result<(Vec<QuantRgba>, Vec<u8>)> {
+    let (width, height) = img.dimensions();
+    let preview = img.thumbnail(tile_size, tile_size).to_rgba8();
+    let mut preview_image = attr.new_image(
+        to_quant_pixels(preview.as_raw()),
+        preview.width() as usize,
+        preview.height() as usize,
+        0.0,
+    )?;
+    let mut res = attr.quantize(&mut preview_image)?;
+    res.set_dithering_level(dither)?;
+    let palette = res.palette().to_vec();
+
+    let mut indexed = vec![0u8; width as usize * height as usize];
+    for tile_y in (0..height).step_by(tile_size as usize) {
+        for tile_x in (0..width).step_by(tile_size as usize) {
+            let tile_w = tile_size.min(width - tile_x);
+            let tile_h = tile_size.min(height - tile_y);
+            let tile = img.crop_imm(tile_x, tile_y, tile_w, tile_h).to_rgba8();
+            let mut tile_image = attr.new_image(
+                to_quant_pixels(tile.as_raw()),
+                tile_w as usize,
+                tile_h as usize,
+                0.0,
+            )?;
+            let (tile_palette, tile_indices) = res.remapped(&mut tile_image)?;
+            let lookup: Vec<u8> = tile_palette
+                .iter()
+                .map(|color| nearest_palette_index(&palette, color))
+                .collect();
+
+            for row in 0..tile_h as usize {
+                let dst = (tile_y as usize + row) * width as usize + tile_x as usize;
+                let src = row * tile_w as usize;
+                for col in 0..tile_w as usize {
+                    indexed[dst + col] = lookup[tile_indices[src + col] as usize];
+                }
+            }
+        }
+    }
+
+    Ok((palette, indexed))
+}
+
+// Offsets added to each channel before ordered remapping span roughly one
+// palette step
+const ORDERED_DITHER_SPREAD: f32 = 32.0;
+
+// Bayer threshold matrix of side `size` (a power of two), built by the
+// usual recursive doubling
+fn bayer_matrix(size: usize) -> Vec<Vec<u32>> {
+    let mut matrix = vec![vec![0u32]];
+    while matrix.len() < size {
+        let n = matrix.len();
+        let mut next = vec![vec![0u32; n * 2]; n * 2];
+        for y in 0..n {
+            for x in 0..n {
+                let value = matrix[y][x] * 4;
+                next[y][x] = value;
+                next[y][x + n] = value + 2;
+                next[y + n][x] = value + 3;
+                next[y + n][x + n] = value + 1;
+            }
+        }
+        matrix = next;
+    }
+    matrix
+}
+
+// Remap to `palette` with Bayer noise, in horizontal bands of `band_height`
+// rows so large images are never expanded to RGBA in one piece
+fn ordered_dither_remap(
+    img: &image::DynamicImage,
+    palette: &[QuantRgba],
+    size: u8,
+    band_height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    if !matches!(size, 2 | 4 | 8) {
+        return Err(anyhow!(
+            "Ordered dither size must be 2, 4 or 8, got {}",
+            size
+        ));
+    }
+    let size = size as usize;
+    let thresholds: Vec<Vec<f32>> = bayer_matrix(size)
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&value| {
+                    ((value as f32 + 0.5) / (size * size) as f32 - 0.5) * ORDERED_DITHER_SPREAD
+                })
+                .collect()
+        })
+        .collect();
+
+    let (width, height) = img.dimensions();
+    let band_height = band_height.max(1);
+    let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indexed = Vec::with_capacity(width as usize * height as usize);
+    for band_y in (0..height).step_by(band_height as usize) {
+        let band_h = band_height.min(height - band_y);
+        let band = img.crop_imm(0, band_y, width, band_h).to_rgba8();
+        for (x, y, pixel) in band.enumerate_pixels() {
+            let offset = thresholds[(band_y + y) as usize % size][x as usize % size];
+            let [r, g, b, a] = pixel.0;
+            let shift = |c: u8| (c as f32 + offset).round().clamp(0.0, 255.0) as u8;
+            let key = [shift(r), shift(g), shift(b), a];
+            let index = *cache.entry(key).or_insert_with(|| {
+                nearest_palette_index(palette, &QuantRgba::new(key[0], key[1], key[2], key[3]))
+            });
+            indexed.push(index);
+        }
+    }
+    Ok(indexed)
+}
+
+fn write_indexed_png(
+    width: u32,
+    height: u32,
+    palette: &[QuantRgba],
+    indexed_pixels: &[u8],
+    interlaced: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let mut palette_bytes = Vec::with_capacity(palette.len() * 3);
+    let mut trns = Vec::with_capacity(palette.len());
+    for color in palette {
+        palette_bytes.extend_from_slice(&[color.r, color.g, color.b]);
+        trns.push(color.a);
+    }
+
+    let mut output = Vec::new();
+    {
+        let cursor = std::io::Cursor::new(&mut output);
+        let mut encoder = png::Encoder::new(cursor, width, height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette_bytes);
+        if trns.iter().any(|&a| a < 255) {
+            encoder.set_trns(trns);
+        }
+        if interlaced {
+            encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(indexed_pixels)?;
+    }
+    Ok(output)
+}
+
+// A DRI segment with a non-zero interval means RST markers in the scan data
+fn has_restart_interval(jpeg_bytes: &[u8]) -> bool {
+    img_parts::jpeg::Jpeg::from_bytes(jpeg_bytes.to_vec().into())
+        .map(|jpeg| {
+            jpeg.segments().iter().any(|segment| {
+                let contents = segment.contents();
+                segment.marker() == 0xDD
+                    && contents.len() >= 2
+                    && u16::from_be_bytes([contents[0], contents[1]]) != 0
+            })
+        })
+        .unwrap_or(false)
+}
+
+// RST markers cannot simply be cut out of the entropy-coded data, since each
+// one resets DC prediction. A lossless libjpeg-turbo transform re-codes the
+// same coefficients, and like jpegtran it writes no restart interval.
+fn strip_restart_markers(jpeg_bytes: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    if !has_restart_interval(&jpeg_bytes) {
+        return Ok(jpeg_bytes);
+    }
+    let stripped = turbojpeg::transform(&turbojpeg::Transform::default(), &jpeg_bytes)?.to_vec();
+    if has_restart_interval(&stripped) {
+        return Err(anyhow!("Could not remove JPEG restart markers"));
+    }
+    Ok(stripped)
+}
+
+/// Quality spread searched by the default `MultiCandidate` JPEG strategy.
+pub const DEFAULT_JPEG_QUALITY_RANGE: u8 = 10;
+/// libwebp method used for lossy WebP output.
+pub const DEFAULT_WEBP_METHOD: i32 = 4;
+/// oxipng preset applied after palette quantization.
+pub const DEFAULT_PNG_QUANTIZED_PRESET: u8 = 2;
+
+/// Qualities the `MultiCandidate` JPEG strategy searches within; outside it
+/// a candidate rarely beats the base encode on size for its quality.
+pub const JPEG_CANDIDATE_QUALITY: std::ops::RangeInclusive<u8> = 60..=95;
+
+/// Base quality and the candidate qualities tried by the `MultiCandidate`
+/// JPEG strategy, clamped to [`JPEG_CANDIDATE_QUALITY`].
+pub fn jpeg_candidates(quality: u8, quality_range: u8) -> (u8, Vec<u8>) {
+    let base_q = quality.clamp(
+        *JPEG_CANDIDATE_QUALITY.start(),
+        *JPEG_CANDIDATE_QUALITY.end(),
+    );
+    let half_range = quality_range / 2;
+    let mut candidates = vec![
+        base_q.saturating_add(quality_range),
+        base_q.saturating_add(half_range),
+        base_q,
+        base_q.saturating_sub(half_range),
+        base_q.saturating_sub(quality_range),
+    ];
+    candidates.retain(|q| JPEG_CANDIDATE_QUALITY.contains(q));
+    candidates.sort_unstable();
+    candidates.dedup();
+    (base_q, candidates)
+}
+
+/// Score of a candidate encode of `size` bytes at `quality` against the
+/// `base_size` encode; the highest score wins.
+pub fn candidate_score(quality: u8, size: usize, base_size: usize) -> f64 {
+    let base_size = base_size.max(1);
+    let size = size.max(1);
+    let size_improve = (base_size as f64 - size as f64) / base_size as f64;
+    let quality_score = quality as f64 / 100.0;
+    let speed_penalty = if quality >= 85 {
+        0.05
+    } else if quality >= 75 {
+        0.07
+    } else {
+        0.1
+    };
+    quality_score * 0.5 + size_improve * 0.4 - speed_penalty * 0.1
+}
+
+/// Baseline 4:2:0 JPEG with Huffman tables optimized for `img`, using
+/// libjpeg-turbo's two-pass encode. Alpha is dropped.
+pub fn encode_jpeg_optimized(img: &image::DynamicImage, quality: u8) -> anyhow::Result<Vec<u8>> {
+    let (pixels, format, subsamp) = if img.color().has_color() {
+        (
+            img.to_rgb8().into_raw(),
+            turbojpeg::PixelFormat::RGB,
+            turbojpeg::Subsamp::Sub2x2,
+        )
+    } else {
+        (
+            img.to_luma8().into_raw(),
+            turbojpeg::PixelFormat::GRAY,
+            turbojpeg::Subsamp::Gray,
+        )
+    };
+    let (width, height) = img.dimensions();
+    let image = turbojpeg::Image {
+        pixels: pixels.as_slice(),
+        width: width as usize,
+        pitch: width as usize * format.size(),
+        height: height as usize,
+        format,
+    };
+
+    let mut compressor = turbojpeg::Compressor::new()?;
+    compressor.set_quality(quality.clamp(1, 100) as i32)?;
+    compressor.set_subsamp(subsamp)?;
+    compressor.set_optimize(true)?;
+    Ok(compressor.compress_to_vec(image)?)
+}
+
+/// Encode with mozjpeg, converting RGB input to YCbCr.
+///
+/// Requires the `mozjpeg` feature.
+#[cfg(feature = "mozjpeg")]
+pub fn encode_jpeg_mozjpeg(
+    img: &image::DynamicImage,
+    quality: u8,
+    progressive: bool,
+    optimize_coding: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let has_color = img.color().has_color();
+    let (pixels, input_space) = if has_color {
+        (img.to_rgb8().into_raw(), mozjpeg::ColorSpace::JCS_RGB)
+    } else {
+        (img.to_luma8().into_raw(), mozjpeg::ColorSpace::JCS_GRAYSCALE)
+    };
+    let (width, height) = img.dimensions();
+
+    // libjpeg errors surface as panics from the mozjpeg crate
+    let encoded = std::panic::catch_unwind(move || -> std::io::Result<Vec<u8>> {
+        let mut compress = mozjpeg::Compress::new(input_space);
+        if !progressive {
+            // Resets every parameter, so it has to come first
+            compress.set_fastest_defaults();
+        }
+        if has_color {
+            compress.set_color_space(mozjpeg::ColorSpace::JCS_YCbCr);
+        }
+        compress.set_size(width as usize, height as usize);
+        compress.set_quality(quality.clamp(1, 100) as f32);
+        compress.set_optimize_coding(optimize_coding);
+        if progressive {
+            compress.set_progressive_mode();
+        }
+
+        let mut started = compress.start_compress(Vec::new())?;
+        started.write_scanlines(&pixels)?;
+        started.finish()
+    })
+    .map_err(|_| anyhow!("mozjpeg encode failed"))?;
+    Ok(encoded?)
+}
+
+#[cfg(not(feature = "mozjpeg"))]
+pub fn encode_jpeg_mozjpeg(
+    _img: &image::DynamicImage,
+    _quality: u8,
+    _progressive: bool,
+    _optimize_coding: bool,
+) -> anyhow::Result<Vec<u8>> {
+    Err(anyhow!("The mozjpeg backend requires building with the `mozjpeg` feature"))
+}
+
+pub fn encode_image(
+    img: &image::DynamicImage,
+    format: ImageFormat,
+    config: &CompressionConfig,
+) -> anyhow::Result<Vec<u8>> {
+    match format {
+        ImageFormat::Png => {
+            if config.lossless {
+                let mut png_data = Vec::new();
+                img.write_to(&mut std::io::Cursor::new(&mut png_data), ImageOutputFormat::Png)?;
+                // Faster lossless optimization preset
+                let mut options = oxipng::Options::from_preset(1);
+                if config.png_interlaced {
+                    options.interlace = Some(oxipng::Interlacing::Adam7);
+                }
+                let optimized = oxipng::optimize_from_memory(&png_data, &options)?;
+                Ok(optimized)
+            } else {
+                encode_png_quantized(img, config, DEFAULT_PNG_QUANTIZED_PRESET)
+            }
+        }
+        ImageFormat::Jpeg => {
+            let encode_jpeg = |quality: u8| -> anyhow::Result<Vec<u8>> {
+                let buffer = match config.jpeg_backend.unwrap_or_default() {
+                    JpegBackend::MozJpeg {
+                        progressive,
+                        optimize_coding,
+                    } => encode_jpeg_mozjpeg(img, quality, progressive, optimize_coding)?,
+                    JpegBackend::Default if config.jpeg_optimize_huffman => {
+                        encode_jpeg_optimized(img, quality)?
+                    }
+                    JpegBackend::Default => {
+                        let mut buffer = Vec::new();
+                        let mut encoder =
+                            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
+                        encoder.encode_image(img)?;
+                        buffer
+                    }
+                };
+                if config.jpeg_strip_restart_markers {
+                    return strip_restart_markers(buffer);
+                }
+                Ok(buffer)
+            };
+
+            // Lossless always means a single maximum-quality encode
+            let strategy = if config.lossless {
+                JpegStrategy::Direct
+            } else {
+                config
+                    .jpeg_strategy
+                    .unwrap_or(JpegStrategy::MultiCandidate {
+                        quality_range: DEFAULT_JPEG_QUALITY_RANGE,
+                    })
+            };
+
+            if let JpegStrategy::MultiCandidate { quality_range } = strategy {
+                let (base_q, candidates) = jpeg_candidates(config.quality_jpg, quality_range);
+
+                let base = encode_jpeg(base_q)?;
+                let base_size = base.len();
+                let mut best = (base, 0.0);
+
+                for cand in candidates {
+                    let data = encode_jpeg(cand)?;
+                    let score = candidate_score(cand, data.len(), base_size);
+                    if score > best.1 {
+                        best = (data, score);
+                    }
+                }
+
+                Ok(best.0)
+            } else if config.lossless {
+                encode_jpeg(100)
+            } else {
+                let q = QUALITY_DEFAULTS;
+                encode_jpeg(config.quality_jpg.clamp(q.jpeg_min, q.jpeg_max))
+            }
+        }
+        ImageFormat::WebP => {
+            // The `image` encoder has no switch for exact transparent pixels
+            if webp_lossless(config) && !config.webp_exact {
+                let mut webp_data = Vec::new();
+                img.write_to(&mut std::io::Cursor::new(&mut webp_data), ImageOutputFormat::WebP)?;
+                Ok(webp_data)
+            } else {
+                encode_webp(img, config, DEFAULT_WEBP_METHOD)
+            }
+        }
+        _ => Err(anyhow!("Unsupported format")),
+    }
+}
+
+// Palette and per-pixel indices for `img` with `config`'s dithering method;
+// `attr` bounds the palette and `dither` is the Floyd-Steinberg level
+fn quantize_pixels(
+    img: &image::DynamicImage,
+    config: &CompressionConfig,
+    attr: &imagequant::Attributes,
+    dither: f32,
+) -> anyhow::Result<(Vec<QuantRgba>, Vec<u8>)> {
+    let (width, height) = img.dimensions();
+    let tile_size = config
+        .large_image_tile_size
+        .filter(|&tile| tile > 0 && width as u64 * height as u64 > tile as u64 * tile as u64);
+    let method = config.png_dithering_method.unwrap_or_default();
+    let dither = match method {
+        PngDitheringMethod::FloydSteinberg => dither,
+        PngDitheringMethod::None | PngDitheringMethod::Ordered => 0.0,
+    };
+    match (method, tile_size) {
+        (PngDitheringMethod::Ordered, tile) => {
+            // imagequant only picks the palette; the noise is added on remap
+            let sample = match tile {
+                Some(tile) => img.thumbnail(tile, tile).to_rgba8(),
+                None => img.to_rgba8(),
+            };
+            let mut image = attr.new_image(
+                to_quant_pixels(sample.as_raw()),
+                sample.width() as usize,
+                sample.height() as usize,
+                0.0,
+            )?;
+            drop(sample);
+            let mut res = attr.quantize(&mut image)?;
+            let palette = res.palette().to_vec();
+            let band_height = tile.unwrap_or(height);
+            let indexed =
+                ordered_dither_remap(img, &palette, config.png_ordered_dither_size, band_height)?;
+            Ok((palette, indexed))
+        }
+        (_, Some(tile)) => quantize_tiled(img, tile, attr, dither),
+        (_, None) => {
+            let rgba = img.to_rgba8();
+            let pixels = to_quant_pixels(rgba.as_raw());
+            drop(rgba);
+            let mut image = attr.new_image(pixels, width as usize, height as usize, 0.0)?;
+            let mut res = attr.quantize(&mut image)?;
+            res.set_dithering_level(dither)?;
+            Ok(res.remapped(&mut image)?)
+        }
+    }
+}
+
+/// Palette-quantize at `config.quality_png`, then optimize with the oxipng
+/// `preset` (0-6). The regular lossy PNG path uses
+/// [`DEFAULT_PNG_QUANTIZED_PRESET`].
+pub fn encode_png_quantized(
+    img: &image::DynamicImage,
+    config: &CompressionConfig,
+    preset: u8,
+) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let target = config
+        .quality_png
+        .clamp(QUALITY_DEFAULTS.png_min, QUALITY_DEFAULTS.png_max);
+    let (min_offset, speed, dither) = if target >= 80 {
+        (25, 8, 0.6)
+    } else if target >= 60 {
+        (30, 9, 0.8)
+    } else {
+        (40, 10, 1.0)
+    };
+
+    let mut attr = imagequant::Attributes::new();
+    let min = target.saturating_sub(min_offset);
+    attr.set_quality(min, target)?;
+    // Balanced speed/quality
+    attr.set_speed(speed.into())?;
+
+    let (palette, indexed_pixels) = quantize_pixels(img, config, &attr, dither)?;
+    let selected = write_indexed_png(
+        width,
+        height,
+        &palette,
+        &indexed_pixels,
+        config.png_interlaced,
+    )?;
+
+    let mut options = oxipng::Options::from_preset(preset);
+    options.strip = StripChunks::All;
+    // The png encoder cannot write interlaced data, so Adam7 is
+    // applied by oxipng while it re-encodes the palette image
+    if config.png_interlaced {
+        options.interlace = Some(oxipng::Interlacing::Adam7);
+    }
+    match oxipng::optimize_from_memory(&selected, &options) {
+        Ok(optimized) => Ok(optimized),
+        Err(_) => Ok(selected),
+    }
+}
+
+fn webp_lossless(config: &CompressionConfig) -> bool {
+    match config.webp_mode {
+        Some(WebpMode::Lossless) => true,
+        Some(WebpMode::Lossy) | Some(WebpMode::NearLossless { .. }) => false,
+        None => config.lossless,
+    }
+}
+
+/// WebP encode through libwebp with `method` (0-6, slower is smaller), in
+/// whichever mode `config` selects. The regular path uses
+/// [`DEFAULT_WEBP_METHOD`].
+pub fn encode_webp(
+    img: &image::DynamicImage,
+    config: &CompressionConfig,
+    method: i32,
+) -> anyhow::Result<Vec<u8>> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let encoder = webp::Encoder::from_rgba(&rgba, width, height);
+
+    let mut webp_config = webp::WebPConfig::new().unwrap();
+    webp_config.quality = config.quality_webp.min(QUALITY_DEFAULTS.webp_max) as f32;
+    webp_config.method = method;
+    webp_config.sns_strength = 70;
+    webp_config.filter_strength = 30;
+    webp_config.filter_sharpness = 3;
+    webp_config.autofilter = 1;
+    webp_config.alpha_quality = 80;
+    webp_config.alpha_compression = 1;
+    webp_config.near_lossless = 60;
+    webp_config.exact = i32::from(config.webp_exact);
+    webp_config.thread_level = 1;
+
+    // In lossless mode quality is compression effort, not fidelity
+    if let Some(WebpMode::NearLossless { level }) = config.webp_mode {
+        webp_config.lossless = 1;
+        webp_config.quality = 75.0;
+        webp_config.near_lossless = level.min(100) as i32;
+    } else if webp_lossless(config) {
+        webp_config.lossless = 1;
+        webp_config.quality = 100.0;
+        webp_config.near_lossless = 100;
+    }
+
+    let webp_data = encoder
+        .encode_advanced(&webp_config)
+        .map_err(|e| anyhow!("WebP encode failed: {:?}", e))?;
+    Ok(webp_data.to_vec())
+}
+
+/// Encode at exactly `quality` (1-100), bypassing the JPEG candidate search so
+/// the output size follows the quality setting.
+pub fn encode_at_quality(
+    img: &image::DynamicImage,
+    format: ImageFormat,
+    quality: u8,
+) -> anyhow::Result<Vec<u8>> {
+    let quality = quality.clamp(1, 100);
+    match format {
+        ImageFormat::Jpeg => {
+            let mut buffer = Vec::new();
+            let mut encoder =
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
+            encoder.encode_image(img)?;
+            Ok(buffer)
+        }
+        _ => {
+            let config = CompressionConfig {
+                lossless: false,
+                quality_jpg: quality,
+                quality_webp: quality,
+                quality_png: quality,
+                ..CompressionConfig::default()
+            };
+            encode_image(img, format, &config)
+        }
+    }
+}
+
+/// 64-bit DCT perceptual hash: the mean-thresholded low frequencies of an
+/// 8x8 DCT, packed most significant bit first. Compare hashes by Hamming
+/// distance; near-duplicates usually differ in fewer than 10 bits.
+pub fn phash(img: &image::DynamicImage) -> u64 {
+    let hasher = image_hasher::HasherConfig::new()
+        .hash_size(8, 8)
+        .hash_alg(image_hasher::HashAlg::Mean)
+        .preproc_dct()
+        .to_hasher();
+    let hash = hasher.hash_image(img);
+    let mut bytes = [0u8; 8];
+    for (target, byte) in bytes.iter_mut().zip(hash.as_bytes()) {
+        *target = *byte;
+    }
+    u64::from_be_bytes(bytes)
+}
+
+fn is_transient_io_error(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::Interrupted
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+// Retry an IO operation on transient failures (busy or network-mounted
+// drives) with exponential backoff. Other errors are returned immediately.
+fn with_io_retry<T>(
+    config: &CompressionConfig,
+    action: &str,
+    path: &Path,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut attempt: u8 = 0;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < config.io_retry_attempts && is_transient_io_error(&e) => {
+                let delay_ms = config
+                    .io_retry_initial_delay_ms
+                    .saturating_mul(1u64 << attempt.min(16));
+                tracing::warn!(
+                    "{} {} failed ({}), retrying in {}ms ({}/{})",
+                    action,
+                    path.display(),
+                    e,
+                    delay_ms,
+                    attempt + 1,
+                    config.io_retry_attempts
+                );
+                std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Encoded output of [`compress_to_memory`].
+pub struct CompressedImage {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    pub original_size: u64,
+    /// EXIF `Orientation` (1-8) declared by a JPEG source, if any.
+    pub exif_orientation: Option<u8>,
+    /// Whether the pixels were rotated to honour `exif_orientation`. The
+    /// pipeline does not rotate yet, so this is currently always false.
+    pub was_auto_rotated: bool,
+}
+
+/// Read the EXIF `Orientation` tag from a raw TIFF-structured EXIF block.
+pub fn read_exif_orientation(raw_exif: &[u8]) -> Option<u8> {
+    let exif = exif::Reader::new().read_raw(raw_exif.to_vec()).ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+    match field.value.get_uint(0)? {
+        value @ 1..=8 => Some(value as u8),
+        _ => None,
+    }
+}
+
+fn insert_jpeg_exif(jpeg_bytes: Vec<u8>, exif: img_parts::Bytes) -> anyhow::Result<Vec<u8>> {
+    let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(jpeg_bytes.into())?;
+    jpeg.set_exif(Some(exif));
+    let mut output = Vec::new();
+    jpeg.encoder().write_to(&mut output)?;
+    Ok(output)
+}
+
+const XMP_JPEG_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_PNG_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+// iTXt layout: keyword NUL, compression flag, compression method, language
+// tag NUL, translated keyword NUL, text. Compressed XMP is not supported.
+fn parse_itxt_xmp(contents: &[u8]) -> Option<Vec<u8>> {
+    let rest = contents.strip_prefix(XMP_PNG_KEYWORD)?.strip_prefix(&[0u8][..])?;
+    let (&compressed, rest) = rest.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    let rest = rest.get(1..)?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    Some(rest[translated_end + 1..].to_vec())
+}
+
+fn extract_xmp(bytes: &img_parts::Bytes, format: ImageFormat) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => {
+            let jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.clone()).ok()?;
+            jpeg.segments()
+                .iter()
+                .filter(|segment| segment.marker() == img_parts::jpeg::markers::APP1)
+                .find_map(|segment| {
+                    segment
+                        .contents()
+                        .strip_prefix(XMP_JPEG_SIGNATURE)
+                        .map(|xmp| xmp.to_vec())
+                })
+        }
+        ImageFormat::Png => {
+            let png = img_parts::png::Png::from_bytes(bytes.clone()).ok()?;
+            png.chunks()
+                .iter()
+                .filter(|chunk| chunk.kind() == *b"iTXt")
+                .find_map(|chunk| parse_itxt_xmp(chunk.contents()))
+        }
+        _ => None,
+    }
+}
+
+fn insert_png_xmp(png_bytes: Vec<u8>, xmp: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut png = img_parts::png::Png::from_bytes(png_bytes.into())?;
+
+    let mut contents = Vec::with_capacity(XMP_PNG_KEYWORD.len() + 5 + xmp.len());
+    contents.extend_from_slice(XMP_PNG_KEYWORD);
+    // Keyword terminator, uncompressed, method 0, empty language and translation
+    contents.extend_from_slice(&[0, 0, 0, 0, 0]);
+    contents.extend_from_slice(xmp);
+
+    let chunks = png.chunks_mut();
+    chunks.retain(|chunk| !(chunk.kind() == *b"iTXt" && parse_itxt_xmp(chunk.contents()).is_some()));
+    // Right after IHDR so readers find it before the image data
+    let position = 1.min(chunks.len());
+    chunks.insert(position, img_parts::png::PngChunk::new(*b"iTXt", contents.into()));
+
+    let mut output = Vec::new();
+    png.encoder().write_to(&mut output)?;
+    Ok(output)
+}
+
+/// Apply `config.resize_width`/`resize_height` when both are set. With
+/// `maintain_aspect_ratio` the image is scaled to fit inside that box.
+pub fn resize_for_config(
+    img: image::DynamicImage,
+    config: &CompressionConfig,
+    maintain_aspect_ratio: bool,
+) -> image::DynamicImage {
+    let (Some(width), Some(height)) = (config.resize_width, config.resize_height) else {
+        return img;
+    };
+    if maintain_aspect_ratio {
+        let (orig_w, orig_h) = img.dimensions();
+        let scale_w = width as f64 / orig_w as f64;
+        let scale_h = height as f64 / orig_h as f64;
+        let scale = scale_w.min(scale_h);
+        let new_w = (orig_w as f64 * scale).round().max(1.0) as u32;
+        let new_h = (orig_h as f64 * scale).round().max(1.0) as u32;
+        img.resize(new_w, new_h, image::imageops::FilterType::Lanczos3)
+    } else {
+        img.resize(width, height, image::imageops::FilterType::Lanczos3)
+    }
+}
+
+/// Decode, resize and encode an image without touching the output location.
+pub fn compress_to_memory(
+    original_path: &Path,
+    config: &CompressionConfig,
+    maintain_aspect_ratio: bool,
+) -> anyhow::Result<CompressedImage> {
+    compress_to_memory_with(original_path, config, maintain_aspect_ratio, None)
+}
+
+/// Like [`compress_to_memory`], but encodes `preloaded` instead of decoding
+/// the file. The source is still read for its size and metadata; embedded
+/// HEIF EXIF is only recovered when the file itself is decoded.
+pub fn compress_to_memory_with(
+    original_path: &Path,
+    config: &CompressionConfig,
+    maintain_aspect_ratio: bool,
+    preloaded: Option<image::DynamicImage>,
+) -> anyhow::Result<CompressedImage> {
+    if let Err(errors) = validate_config(config) {
+        let details: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(anyhow!("Invalid compression config: {}", details.join("; ")));
+    }
+    let heif_source = is_heif_path(original_path);
+    let source_format = if heif_source {
+        None
+    } else {
+        Some(detect_image_format(original_path)?)
+    };
+    let format = match &config.output_format {
+        Some(name) => format_from_name(name)?,
+        None => source_format.unwrap_or(ImageFormat::Jpeg),
+    };
+
+    let original_bytes: img_parts::Bytes =
+        with_io_retry(config, "read", original_path, || fs::read(original_path))?.into();
+    let original_size = original_bytes.len() as u64;
+    let jpeg_exif = match source_format {
+        Some(ImageFormat::Jpeg) => img_parts::jpeg::Jpeg::from_bytes(original_bytes.clone())
+            .ok()
+            .and_then(|jpeg| jpeg.exif()),
+        _ => None,
+    };
+    let exif_orientation = jpeg_exif.as_deref().and_then(read_exif_orientation);
+    let source_exif = jpeg_exif.filter(|_| config.preserve_exif);
+    let source_xmp = match source_format {
+        Some(source_format) if config.embed_xmp => extract_xmp(&original_bytes, source_format),
+        _ => None,
+    };
+    let (img, heif_exif) = match (preloaded, source_format) {
+        (Some(img), _) => (img, None),
+        (None, Some(source_format)) => (
+            image::load_from_memory_with_format(&original_bytes, source_format)?,
+            None,
+        ),
+        (None, None) => decode_heif(&original_bytes)?,
+    };
+    drop(original_bytes);
+    let processed_img = resize_for_config(img, config, maintain_aspect_ratio);
+
+    let mut data = encode_image(&processed_img, format, config)?;
+
+    if config.preserve_exif && format == ImageFormat::Jpeg {
+        if let Some(exif) = source_exif.or(heif_exif.map(img_parts::Bytes::from)) {
+            if let Ok(with_exif) = insert_jpeg_exif(data.clone(), exif) {
+                data = with_exif;
+            }
+        }
+    }
+
+    if let Some(xmp) = source_xmp {
+        if format == ImageFormat::Png {
+            if let Ok(with_xmp) = insert_png_xmp(data.clone(), &xmp) {
+                data = with_xmp;
+            }
+        }
+    }
+
+    Ok(CompressedImage {
+        data,
+        format,
+        original_size,
+        exif_orientation,
+        was_auto_rotated: false,
+    })
+}
+
+/// Write to a sibling temp file and rename it over `path`, so a crash leaves
+/// either the old file or the new one, never a partial write. An existing
+/// file's permissions carry over to the replacement.
+///
+/// `fs::rename` replaces existing files on Windows as well, since std calls
+/// `MoveFileExW` with `MOVEFILE_REPLACE_EXISTING`.
+pub fn write_atomic(config: &CompressionConfig, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow!("Invalid output path: {}", path.display()))?;
+    let temp_path = path.with_file_name(format!("{}.tmp.{}", file_name, Uuid::new_v4()));
+    // The rename swaps in a new file, so carry over the mode bits an
+    // overwrite in place would have kept
+    let permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+
+    let result = with_io_retry(config, "write", &temp_path, || fs::write(&temp_path, data))
+        .and_then(|_| match &permissions {
+            Some(permissions) => fs::set_permissions(&temp_path, permissions.clone()),
+            None => Ok(()),
+        })
+        .and_then(|_| with_io_retry(config, "rename", path, || fs::rename(&temp_path, path)));
+    if result.is_err() {
+        fs::remove_file(&temp_path).ok();
+    }
+    Ok(result?)
+}
+
+/// Writes `data` to `path`, through [`write_atomic`] when `config.atomic_write`
+/// is set and with a plain overwrite otherwise.
+pub fn write_output(config: &CompressionConfig, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    if config.atomic_write {
+        write_atomic(config, path, data)
+    } else {
+        Ok(fs::write(path, data)?)
+    }
+}
+
+pub fn compress_image(
+    original_path: &Path,
+    config: &CompressionConfig,
+    output_path: Option<&Path>,
+    maintain_aspect_ratio: bool,
+) -> anyhow::Result<(u64, u64)> {
+    compress_image_with(original_path, config, output_path, maintain_aspect_ratio, None)
+}
+
+/// Like [`compress_image`], but compresses an already decoded (and possibly
+/// edited) image in place of the file's own pixels. Sizes are reported
+/// against the file at `original_path`.
+pub fn compress_image_with(
+    original_path: &Path,
+    config: &CompressionConfig,
+    output_path: Option<&Path>,
+    maintain_aspect_ratio: bool,
+    preloaded: Option<image::DynamicImage>,
+) -> anyhow::Result<(u64, u64)> {
+    // Read up front: an in-place write replaces the source file
+    let permissions = if config.preserve_file_permissions && cfg!(unix) {
+        Some(fs::metadata(original_path)?.permissions())
+    } else {
+        None
+    };
+    let compressed =
+        compress_to_memory_with(original_path, config, maintain_aspect_ratio, preloaded)?;
+    let format = compressed.format;
+    if config.dry_run {
+        return Ok((compressed.original_size, compressed.data.len() as u64));
+    }
+
+    let final_path = final_output_path(original_path, output_path, format);
+    if let Some(parent) = final_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if config.atomic_write {
+        write_atomic(config, &final_path, &compressed.data)?;
+    } else {
+        // Create a secure temporary directory for this operation
+        let temp_dir = std::env::temp_dir()
+            .join(COMPRESS_TEMP_DIR)
+            .join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&temp_dir).map_err(|e| anyhow!("Failed to create temp dir: {}", e))?;
+
+        // Track the temp directory for cleanup
+        track_temp_dir(&temp_dir);
+
+        // Generate a unique temporary file name
+        let file_stem = original_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("temp");
+        let extension = format_extension(format);
+
+        let temp_path = temp_dir.join(format!("{}_compressed.{}", file_stem, extension));
+
+        with_io_retry(config, "write", &temp_path, || fs::write(&temp_path, &compressed.data))?;
+        with_io_retry(config, "copy", &final_path, || fs::copy(&temp_path, &final_path))?;
+        std::fs::remove_dir_all(temp_dir).ok();
+    }
+
+    if let Some(permissions) = permissions {
+        fs::set_permissions(&final_path, permissions)?;
+    }
+
+    let compressed_size = fs::metadata(&final_path)?.len();
+
+    Ok((compressed.original_size, compressed_size))
+}
+
+/// Default cap on batch worker threads when no explicit limit is given.
+pub const MAX_DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// Resource limits for a parallel batch run with [`run_batch`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchLimits {
+    /// Worker threads; defaults to the CPU count capped at
+    /// [`MAX_DEFAULT_BATCH_CONCURRENCY`].
+    pub max_concurrency: Option<usize>,
+    /// Hold back new jobs while the process RSS exceeds this many MiB.
+    pub memory_limit_mb: Option<u64>,
+}
+
+// Counts running jobs and delays new ones while resident memory is over the
+// limit. A job always starts when nothing else is running, so a single image
+// larger than the limit cannot stall the batch.
+struct MemoryGate {
+    limit_bytes: Option<u64>,
+    active: AtomicUsize,
+    system: Mutex<sysinfo::System>,
+}
+
+struct MemorySlot<'a>(&'a AtomicUsize);
+
+impl Drop for MemorySlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MemoryGate {
+    fn new(limit_mb: Option<u64>) -> Self {
+        MemoryGate {
+            limit_bytes: limit_mb.map(|mb| mb * 1024 * 1024),
+            active: AtomicUsize::new(0),
+            system: Mutex::new(sysinfo::System::new()),
+        }
+    }
+
+    fn acquire(&self) -> MemorySlot<'_> {
+        if let Some(limit) = self.limit_bytes {
+            while self.active.load(Ordering::SeqCst) > 0 && self.process_memory() > limit {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+        }
+        self.active.fetch_add(1, Ordering::SeqCst);
+        MemorySlot(&self.active)
+    }
+
+    fn process_memory(&self) -> u64 {
+        let Ok(pid) = sysinfo::get_current_pid() else {
+            return 0;
+        };
+        let mut system = self.system.lock().unwrap();
+        system.refresh_process(pid);
+        system.process(pid).map(|p| p.memory()).unwrap_or(0)
+    }
+}
+
+/// Apply `priority` to the calling thread.
+///
+/// Linux uses `ioprio_set` in the best-effort class (level 7, 4 or 0).
+/// Windows uses `SetThreadPriority`; `Low` enters background mode, which also
+/// lowers the thread's I/O priority. Other platforms ignore the setting.
+pub fn set_thread_io_priority(priority: IoPriority) -> std::io::Result<()> {
+    thread_local! {
+        static CURRENT: std::cell::Cell<IoPriority> = std::cell::Cell::new(IoPriority::Normal);
+    }
+    if CURRENT.with(|current| current.get()) == priority {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+        const IOPRIO_CLASS_BE: libc::c_int = 2;
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        let level = match priority {
+            IoPriority::Low => 7,
+            IoPriority::Normal => 4,
+            IoPriority::High => 0,
+        };
+        // Who 0 is the calling thread
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_ioprio_set,
+                IOPRIO_WHO_PROCESS,
+                0,
+                (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | level,
+            )
+        };
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Threading::{
+            GetCurrentThread, SetThreadPriority, THREAD_MODE_BACKGROUND_BEGIN,
+            THREAD_MODE_BACKGROUND_END, THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_NORMAL,
+        };
+        let thread = unsafe { GetCurrentThread() };
+        if CURRENT.with(|current| current.get()) == IoPriority::Low {
+            unsafe { SetThreadPriority(thread, THREAD_MODE_BACKGROUND_END) };
+        }
+        let mode = match priority {
+            IoPriority::Low => THREAD_MODE_BACKGROUND_BEGIN,
+            IoPriority::Normal => THREAD_PRIORITY_NORMAL,
+            IoPriority::High => THREAD_PRIORITY_ABOVE_NORMAL,
+        };
+        if unsafe { SetThreadPriority(thread, mode) } == 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    CURRENT.with(|current| current.set(priority));
+    Ok(())
+}
+
+/// Spaces the start of batch files `config.throttle_ms` apart across all
+/// workers of a [`run_batch`] run.
+pub struct Pacer {
+    interval: Option<std::time::Duration>,
+    next_start: Mutex<Option<std::time::Instant>>,
+}
+
+impl Pacer {
+    pub fn new(config: &CompressionConfig) -> Self {
+        Pacer {
+            interval: config
+                .throttle_ms
+                .filter(|&ms| ms > 0)
+                .map(std::time::Duration::from_millis),
+            next_start: Mutex::new(None),
+        }
+    }
+
+    /// Block until the next start slot is due and reserve the one after it.
+    fn wait_turn(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            // The slot is plain data, so a panic elsewhere cannot leave it torn
+            let mut next_start = self.next_start.lock().unwrap_or_else(|e| e.into_inner());
+            let now = std::time::Instant::now();
+            let slot = next_start.map_or(now, |next| next.max(now));
+            *next_start = Some(slot + interval);
+            slot
+        };
+        std::thread::sleep(slot.saturating_duration_since(std::time::Instant::now()));
+    }
+}
+
+/// Run one batch file under `config.io_priority` once `pacer` grants it a
+/// start slot. Only starts are spaced, so nothing waits after the last file.
+pub fn paced_job<R>(config: &CompressionConfig, pacer: &Pacer, job: impl FnOnce() -> R) -> R {
+    pacer.wait_turn();
+    if let Err(e) = set_thread_io_priority(config.io_priority.unwrap_or_default()) {
+        log::warn!("Could not set I/O priority: {}", e);
+    }
+    job()
+}
+
+/// Run `job` over `items` on a dedicated thread pool sized by `limits`.
+/// Results come back in the order of `items`.
+pub fn run_batch<T, R, F>(items: Vec<T>, limits: &BatchLimits, job: F) -> anyhow::Result<Vec<R>>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let threads = limits
+        .max_concurrency
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+                .min(MAX_DEFAULT_BATCH_CONCURRENCY)
+        })
+        .max(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()?;
+    let gate = MemoryGate::new(limits.memory_limit_mb);
+
+    Ok(pool.install(|| {
+        items
+            .into_par_iter()
+            .map(|item| {
+                let _slot = gate.acquire();
+                job(item)
+            })
+            .collect()
+    }))
+}
+
+/// Outcome of a single job passed to [`compress_image_batch`].
+#[derive(Debug, Clone)]
+pub struct BatchJobResult {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub error: Option<String>,
+}
+
+/// Compress a batch of images in parallel.
+///
+/// Each job is a source path and an optional output path; without an output
+/// path the source is overwritten. Results come back in the order of `jobs`.
+///
+/// ```
+/// use ximage::compress::{compress_image_batch, CompressionConfig};
+///
+/// let dir = std::env::temp_dir().join("ximage-batch-example");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// let source = dir.join("input.png");
+/// image::RgbImage::from_pixel(64, 64, image::Rgb([200, 80, 40]))
+///     .save(&source)
+///     .unwrap();
+///
+/// let jobs = vec![(source.clone(), Some(dir.join("output.png")))];
+/// let results = compress_image_batch(jobs, &CompressionConfig::default());
+///
+/// assert_eq!(results.len(), 1);
+/// assert!(results[0].error.is_none());
+/// assert_eq!(results[0].output, dir.join("output.png"));
+/// # std::fs::remove_dir_all(&dir).ok();
+/// ```
+pub fn compress_image_batch(
+    jobs: Vec<(PathBuf, Option<PathBuf>)>,
+    config: &CompressionConfig,
+) -> Vec<BatchJobResult> {
+    jobs.into_par_iter()
+        .map(|(source, output)| {
+            let target = match &config.output_format {
+                Some(name) => format_from_name(name)
+                    .map(|format| final_output_path(&source, output.as_deref(), format))
+                    .unwrap_or_else(|_| output.clone().unwrap_or_else(|| source.clone())),
+                None => output.clone().unwrap_or_else(|| source.clone()),
+            };
+            match compress_image(&source, config, output.as_deref(), false) {
+                Ok((original_size, compressed_size)) => BatchJobResult {
+                    source,
+                    output: target,
+                    original_size,
+                    compressed_size,
+                    error: None,
+                },
+                Err(e) => BatchJobResult {
+                    source,
+                    output: target,
+                    original_size: 0,
+                    compressed_size: 0,
+                    error: Some(e.to_string()),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient_image(width: u32, height: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+        }))
+    }
+
+    // Interlace method is the last byte of IHDR: 8-byte signature, 8-byte
+    // chunk header, then width, height, depth, colour type, compression and
+    // filter method
+    fn ihdr_interlace_byte(png: &[u8]) -> u8 {
+        assert_eq!(&png[12..16], b"IHDR");
+        png[28]
+    }
+
+    #[test]
+    fn png_interlaced_sets_adam7_in_ihdr() {
+        let img = gradient_image(64, 48);
+        for lossless in [true, false] {
+            let interlaced = CompressionConfig {
+                lossless,
+                png_interlaced: true,
+                ..CompressionConfig::default()
+            };
+            let png = encode_image(&img, ImageFormat::Png, &interlaced).unwrap();
+            assert_eq!(ihdr_interlace_byte(&png), 1, "lossless = {}", lossless);
+
+            let plain = CompressionConfig {
+                lossless,
+                ..CompressionConfig::default()
+            };
+            let png = encode_image(&img, ImageFormat::Png, &plain).unwrap();
+            assert_eq!(ihdr_interlace_byte(&png), 0, "lossless = {}", lossless);
+        }
+    }
+
+    // A fresh file path in its own temporary directory
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ximage-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn xmp_survives_jpeg_to_png() {
+        let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF/></x:xmpmeta>"#;
+        let mut jpeg_bytes = Vec::new();
+        gradient_image(32, 32)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg_bytes),
+                ImageOutputFormat::Jpeg(90),
+            )
+            .unwrap();
+        let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(jpeg_bytes.into()).unwrap();
+        let mut contents = XMP_JPEG_SIGNATURE.to_vec();
+        contents.extend_from_slice(xmp);
+        jpeg.segments_mut().insert(
+            0,
+            img_parts::jpeg::JpegSegment::new_with_contents(
+                img_parts::jpeg::markers::APP1,
+                contents.into(),
+            ),
+        );
+        let source = temp_file("with-xmp.jpg");
+        let mut output = Vec::new();
+        jpeg.encoder().write_to(&mut output).unwrap();
+        fs::write(&source, output).unwrap();
+
+        let config = CompressionConfig {
+            output_format: Some("png".to_string()),
+            embed_xmp: true,
+            ..CompressionConfig::default()
+        };
+        let compressed = compress_to_memory(&source, &config, false).unwrap();
+        assert_eq!(compressed.format, ImageFormat::Png);
+        assert_eq!(
+            extract_xmp(&compressed.data.into(), ImageFormat::Png).as_deref(),
+            Some(&xmp[..])
+        );
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare the
+    // cost of the two JPEG strategies
+    #[test]
+    #[ignore]
+    fn bench_jpeg_direct_vs_multi_candidate() {
+        let img = gradient_image(1920, 1080);
+        for strategy in [
+            JpegStrategy::Direct,
+            JpegStrategy::MultiCandidate {
+                quality_range: DEFAULT_JPEG_QUALITY_RANGE,
+            },
+        ] {
+            let config = CompressionConfig {
+                jpeg_strategy: Some(strategy),
+                ..CompressionConfig::default()
+            };
+            let started = std::time::Instant::now();
+            let mut size = 0;
+            for _ in 0..5 {
+                size = encode_image(&img, ImageFormat::Jpeg, &config)
+                    .unwrap()
+                    .len();
+            }
+            println!(
+                "{:?}: {:?} per encode, {} bytes",
+                strategy,
+                started.elapsed() / 5,
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn run_batch_respects_max_concurrency() {
+        for limit in [1, 2, 4] {
+            let running = AtomicUsize::new(0);
+            let peak = AtomicUsize::new(0);
+            let limits = BatchLimits {
+                max_concurrency: Some(limit),
+                memory_limit_mb: None,
+            };
+            let results = run_batch((0..16).collect(), &limits, |n: u32| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                n * 2
+            })
+            .unwrap();
+
+            assert_eq!(results, (0..16).map(|n| n * 2).collect::<Vec<_>>());
+            let peak = peak.load(Ordering::SeqCst);
+            assert!((1..=limit).contains(&peak), "limit {limit}, peak {peak}");
+        }
+    }
+
+    #[test]
+    fn detect_format_from_magic_bytes() {
+        let cases: [(&[u8], &str); 9] = [
+            (&[0xFF, 0xD8, 0xFF, 0xE0], "jpeg"),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "png"),
+            (b"GIF89a", "gif"),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", "webp"),
+            (b"II*\0\x08\0\0\0", "tiff"),
+            (b"BM\x36\0", "bmp"),
+            (b"\0\0\0\x1cftypavif", "avif"),
+            (b"\0\0\0\x18ftypheic", "heif"),
+            (b"\0\0\0\x18ftypisom", "unknown"),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(detect_format_from_bytes(bytes), expected);
+        }
+        assert_eq!(detect_format_from_bytes(&[]), "unknown");
+        assert_eq!(detect_format_from_bytes(b"RIFF\0\0\0\0WAVE"), "unknown");
+    }
+
+    // 16x8 mid-gray baseline JPEG with a restart marker between its two
+    // MCUs. Single-code Huffman tables make every block the bits `00`.
+    fn jpeg_with_restart_markers() -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // DQT: table 0, all ones
+        jpeg.extend_from_slice(&[0xFF, 0xDB, 0x00, 0x43, 0x00]);
+        jpeg.extend_from_slice(&[1; 64]);
+        // SOF0: 8-bit, 8 rows, 16 columns, one component on table 0
+        jpeg.extend_from_slice(&[
+            0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00,
+        ]);
+        // DHT: DC and AC table 0, each one 1-bit code for symbol 0
+        for class in [0x00, 0x10] {
+            jpeg.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x14, class, 0x01]);
+            jpeg.extend_from_slice(&[0; 15]);
+            jpeg.push(0x00);
+        }
+        // DRI: restart after every MCU
+        jpeg.extend_from_slice(&[0xFF, 0xDD, 0x00, 0x04, 0x00, 0x01]);
+        // SOS, then the two MCUs (padded with ones) around RST0
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        jpeg.extend_from_slice(&[0x3F, 0xFF, 0xD0, 0x3F, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn strip_restart_markers_recodes_without_dri() {
+        let original = jpeg_with_restart_markers();
+        assert!(has_restart_interval(&original));
+
+        let stripped = strip_restart_markers(original.clone()).unwrap();
+        assert!(!has_restart_interval(&stripped));
+        assert_eq!(
+            image::load_from_memory(&stripped).unwrap().to_luma8(),
+            image::load_from_memory(&original).unwrap().to_luma8()
+        );
+
+        // Encodes without restart markers pass through untouched
+        let config = CompressionConfig::default();
+        let clean = encode_image(&gradient_image(16, 16), ImageFormat::Jpeg, &config).unwrap();
+        assert_eq!(strip_restart_markers(clean.clone()).unwrap(), clean);
+    }
+
+    #[test]
+    fn compress_to_memory_rejects_an_invalid_config() {
+        let path = temp_file("source.png");
+        gradient_image(8, 8).save(&path).unwrap();
+        let config = CompressionConfig {
+            quality_png: 0,
+            ..Default::default()
+        };
+
+        let err = compress_to_memory(&path, &config, true).unwrap_err();
+        assert!(err.to_string().contains("qualityPng"), "{}", err);
+    }
+
+    #[test]
+    fn optimized_huffman_tables_shrink_jpeg_output() {
+        let img = gradient_image(256, 256);
+        let optimized = encode_jpeg_optimized(&img, 75).unwrap();
+
+        // Same libjpeg-turbo settings, standard Huffman tables
+        let rgb = img.to_rgb8();
+        let mut compressor = turbojpeg::Compressor::new().unwrap();
+        compressor.set_quality(75).unwrap();
+        compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2).unwrap();
+        let standard = compressor
+            .compress_to_vec(turbojpeg::Image {
+                pixels: rgb.as_raw().as_slice(),
+                width: 256,
+                pitch: 256 * 3,
+                height: 256,
+                format: turbojpeg::PixelFormat::RGB,
+            })
+            .unwrap();
+
+        assert!(
+            optimized.len() < standard.len(),
+            "optimized {} vs standard {}",
+            optimized.len(),
+            standard.len()
+        );
+        let decoded = image::load_from_memory(&optimized).unwrap();
+        assert_eq!(decoded.dimensions(), (256, 256));
+    }
+
+    #[test]
+    fn config_json_round_trips_non_default_values() {
+        let config = CompressionConfig {
+            lossless: true,
+            quality_jpg: 33,
+            resize_width: Some(640),
+            webp_mode: Some(WebpMode::NearLossless { level: 60 }),
+            output_format: Some("webp".to_string()),
+            jpeg_strategy: Some(JpegStrategy::MultiCandidate { quality_range: 5 }),
+            png_dithering_method: Some(PngDitheringMethod::Ordered),
+            png_ordered_dither_size: 8,
+            jpeg_backend: Some(JpegBackend::MozJpeg {
+                progressive: false,
+                optimize_coding: true,
+            }),
+            throttle_ms: Some(25),
+            io_priority: Some(IoPriority::Low),
+            ..Default::default()
+        };
+
+        let json = config.to_json();
+        let parsed = CompressionConfig::from_json(&json).unwrap();
+        assert_eq!(parsed.to_json(), json);
+        assert!(parsed.lossless);
+        assert_eq!(parsed.quality_jpg, 33);
+        assert_eq!(parsed.resize_width, Some(640));
+        assert_eq!(parsed.webp_mode, Some(WebpMode::NearLossless { level: 60 }));
+        assert_eq!(parsed.output_format.as_deref(), Some("webp"));
+        assert_eq!(
+            parsed.jpeg_strategy,
+            Some(JpegStrategy::MultiCandidate { quality_range: 5 })
+        );
+        assert_eq!(
+            parsed.png_dithering_method,
+            Some(PngDitheringMethod::Ordered)
+        );
+        assert_eq!(parsed.io_priority, Some(IoPriority::Low));
+    }
+
+    #[test]
+    fn config_from_json_fills_defaults_and_rejects_bad_input() {
+        let parsed = CompressionConfig::from_json(r#"{"qualityPng": 40}"#).unwrap();
+        assert_eq!(parsed.quality_png, 40);
+        assert_eq!(parsed.quality_jpg, CompressionConfig::default().quality_jpg);
+
+        let err = CompressionConfig::from_json(r#"{"qualityPng": "high"}"#).unwrap_err();
+        assert!(err.to_string().starts_with("Invalid compression config"));
+    }
+
+    #[test]
+    fn config_diff_reports_the_one_changed_field() {
+        let a = CompressionConfig::default();
+        let b = CompressionConfig {
+            quality_webp: 42,
+            ..Default::default()
+        };
+
+        let changes = config_diff(&a, &b);
+        assert_eq!(changes.len(), 1, "{:?}", changes);
+        assert_eq!(changes[0].field_name, "qualityWebp");
+        assert_eq!(changes[0].old_value, a.quality_webp.to_string());
+        assert_eq!(changes[0].new_value, "42");
+        assert!(config_diff(&a, &CompressionConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn quality_defaults_lie_within_their_validated_ranges() {
+        let q = QUALITY_DEFAULTS;
+        assert!((q.jpeg_min..=q.jpeg_max).contains(&q.jpeg_default));
+        assert!((q.webp_min..=q.webp_max).contains(&q.webp_default));
+        assert!((q.png_min..=q.png_max).contains(&q.png_default));
+        assert!(validate_config(&CompressionConfig::default()).is_ok());
+
+        let at = |jpg, webp, png| {
+            validate_config(&CompressionConfig {
+                quality_jpg: jpg,
+                quality_webp: webp,
+                quality_png: png,
+                ..Default::default()
+            })
+        };
+        assert!(at(q.jpeg_min, q.webp_min, q.png_min).is_ok());
+        assert!(at(q.jpeg_max, q.webp_max, q.png_max).is_ok());
+        let errors = at(q.jpeg_min - 1, q.webp_max, q.png_min - 1).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["qualityJpg", "qualityPng"]);
+        assert_eq!(
+            errors[1].message,
+            format!("must be between {} and {}", q.png_min, q.png_max)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_atomic_write_preserves_source_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let source = temp_file("source.png");
+        gradient_image(16, 16).save(&source).unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o640)).unwrap();
+        let output = source.with_file_name("output.png");
+        let config = CompressionConfig {
+            preserve_file_permissions: true,
+            atomic_write: false,
+            ..Default::default()
+        };
+
+        compress_image(&source, &config, Some(&output), true).unwrap();
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn each_dithering_method_maps_a_gradient_onto_the_palette() {
+        let (width, height) = (64u32, 16u32);
+        let img =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, _| {
+                let v = (x * 255 / (width - 1)) as u8;
+                image::Rgba([v, v, v, 255])
+            }));
+        let source = |x: u32| (x * 255 / (width - 1)) as f64;
+        let mut attr = imagequant::Attributes::new();
+        attr.set_max_colors(2).unwrap();
+
+        // Per method, the mean error of 8-column band averages
+        let mut band_errors = Vec::new();
+        for method in [
+            PngDitheringMethod::None,
+            PngDitheringMethod::FloydSteinberg,
+            PngDitheringMethod::Ordered,
+        ] {
+            let config = CompressionConfig {
+                png_dithering_method: Some(method),
+                ..Default::default()
+            };
+            let (palette, indexed) = quantize_pixels(&img, &config, &attr, 1.0).unwrap();
+            assert!(palette.len() <= 2, "{method:?}: {} colours", palette.len());
+            assert_eq!(indexed.len(), (width * height) as usize);
+            assert!(
+                indexed.iter().all(|&i| (i as usize) < palette.len()),
+                "{method:?}"
+            );
+
+            let value =
+                |x: u32, y: u32| palette[indexed[(y * width + x) as usize] as usize].r as f64;
+            let pixels = (width * height) as f64;
+            let (mut abs_error, mut signed_error) = (0.0, 0.0);
+            for y in 0..height {
+                for x in 0..width {
+                    abs_error += (value(x, y) - source(x)).abs();
+                    signed_error += value(x, y) - source(x);
+                }
+            }
+            assert!(
+                abs_error / pixels <= 64.0,
+                "{method:?}: {}",
+                abs_error / pixels
+            );
+            assert!(
+                (signed_error / pixels).abs() <= 16.0,
+                "{method:?}: {}",
+                signed_error / pixels
+            );
+
+            let mut band_error = 0.0;
+            for band in 0..width / 8 {
+                let (mut output, mut expected) = (0.0, 0.0);
+                for x in band * 8..band * 8 + 8 {
+                    expected += source(x) * height as f64;
+                    output += (0..height).map(|y| value(x, y)).sum::<f64>();
+                }
+                band_error += (output - expected).abs() / (8 * height) as f64;
+            }
+            band_errors.push(band_error / (width / 8) as f64);
+        }
+        // Error diffusion tracks the ramp's local average; plain remapping bands
+        assert!(band_errors[1] < band_errors[0], "{band_errors:?}");
+    }
+
+    #[test]
+    fn throttle_spaces_file_starts_across_workers() {
+        let config = CompressionConfig {
+            throttle_ms: Some(100),
+            ..Default::default()
+        };
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = temp_file(&format!("paced-{}.png", i));
+                gradient_image(32, 32).save(&path).unwrap();
+                path
+            })
+            .collect();
+
+        let pacer = Pacer::new(&config);
+        let started = std::time::Instant::now();
+        let results = run_batch(paths, &BatchLimits::default(), |path| {
+            paced_job(&config, &pacer, || {
+                compress_image(&path, &config, None, false)
+            })
+        })
+        .unwrap();
+        let elapsed = started.elapsed();
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        // Starts at 0, 100 and 200 ms, with no pause after the last file
+        assert!(
+            elapsed >= std::time::Duration::from_millis(200),
+            "{:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn generated_configs_survive_a_json_round_trip() {
+        // Linear congruential generator; its high bits are the random ones
+        struct Lcg(u32);
+        impl Lcg {
+            fn below(&mut self, n: u64) -> u64 {
+                self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+                u64::from(self.0 >> 16) % n
+            }
+            fn flag(&mut self) -> bool {
+                self.below(2) == 1
+            }
+            fn maybe(&mut self, n: u64) -> Option<u64> {
+                self.flag().then(|| self.below(n))
+            }
+        }
+
+        let mut rng = Lcg(419);
+        for _ in 0..1000 {
+            let config = CompressionConfig {
+                lossless: rng.flag(),
+                quality_jpg: rng.below(256) as u8,
+                quality_webp: rng.below(256) as u8,
+                quality_png: rng.below(256) as u8,
+                preserve_exif: rng.flag(),
+                resize_width: rng.maybe(1 << 15).map(|v| v as u32),
+                resize_height: rng.maybe(1 << 15).map(|v| v as u32),
+                png_interlaced: rng.flag(),
+                io_retry_attempts: rng.below(256) as u8,
+                io_retry_initial_delay_ms: rng.below(1 << 15),
+                webp_mode: rng.maybe(3).map(|v| match v {
+                    0 => WebpMode::Lossy,
+                    1 => WebpMode::Lossless,
+                    _ => WebpMode::NearLossless {
+                        level: rng.below(101) as u8,
+                    },
+                }),
+                output_format: rng
+                    .maybe(4)
+                    .map(|v| ["jpeg", "png", "webp", "ü \"x\""][v as usize].to_string()),
+                embed_xmp: rng.flag(),
+                large_image_tile_size: rng.maybe(8192).map(|v| v as u32),
+                jpeg_strategy: rng.maybe(2).map(|v| match v {
+                    0 => JpegStrategy::Direct,
+                    _ => JpegStrategy::MultiCandidate {
+                        quality_range: rng.below(256) as u8,
+                    },
+                }),
+                atomic_write: rng.flag(),
+                dry_run: rng.flag(),
+                jpeg_strip_restart_markers: rng.flag(),
+                check_disk_space: rng.flag(),
+                preserve_file_permissions: rng.flag(),
+                png_dithering_method: rng.maybe(3).map(|v| match v {
+                    0 => PngDitheringMethod::FloydSteinberg,
+                    1 => PngDitheringMethod::None,
+                    _ => PngDitheringMethod::Ordered,
+                }),
+                png_ordered_dither_size: rng.below(256) as u8,
+                jpeg_optimize_huffman: rng.flag(),
+                jpeg_backend: rng.maybe(2).map(|v| match v {
+                    0 => JpegBackend::Default,
+                    _ => JpegBackend::MozJpeg {
+                        progressive: rng.flag(),
+                        optimize_coding: rng.flag(),
+                    },
+                }),
+                throttle_ms: rng.maybe(1 << 15),
+                io_priority: rng.maybe(3).map(|v| match v {
+                    0 => IoPriority::Low,
+                    1 => IoPriority::Normal,
+                    _ => IoPriority::High,
+                }),
+                webp_exact: rng.flag(),
+            };
+
+            let parsed = CompressionConfig::from_json(&config.to_json()).unwrap();
+            assert_eq!(parsed, config);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Per-pixel colour effects applied before an image is saved or compressed.
+
+use image::{ColorType, DynamicImage, Rgba};
+
+/// Luma weighting used by [`PixelTransform::Grayscale`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LumaStandard {
+    /// SD video weights (0.299, 0.587, 0.114).
+    Bt601,
+    /// HD video and sRGB weights (0.2126, 0.7152, 0.0722).
+    Bt709,
+}
+
+impl LumaStandard {
+    fn weights(self) -> [f32; 3] {
+        match self {
+            LumaStandard::Bt601 => [0.299, 0.587, 0.114],
+            LumaStandard::Bt709 => [0.2126, 0.7152, 0.0722],
+        }
+    }
+}
+
+/// A colour effect applied independently to every pixel. Alpha is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelTransform {
+    /// The classic sepia matrix, blended with the original by `intensity`
+    /// (0-1).
+    Sepia { intensity: f32 },
+    /// Weighted grayscale, kept in RGB layout.
+    Grayscale { standard: LumaStandard },
+    /// BT.709 luma mapped onto a gradient from `shadow` to `highlight`.
+    Duotone { highlight: [u8; 3], shadow: [u8; 3] },
+    /// RGB offsets (-255 to 255) weighted by how far each pixel's luma sits
+    /// in the shadows, midtones or highlights.
+    ColorBalance {
+        shadows: [i16; 3],
+        midtones: [i16; 3],
+        highlights: [i16; 3],
+    },
+    /// Per-channel power curve `255 * (v / 255)^(1 / gamma)` for R, G and B;
+    /// each gamma is clamped to 0.1-10.
+    Gamma { gamma: [f32; 3] },
+}
+
+const SEPIA_MATRIX: [[f32; 3]; 3] = [
+    [0.393, 0.769, 0.189],
+    [0.349, 0.686, 0.168],
+    [0.272, 0.534, 0.131],
+];
+
+fn luma(rgb: [f32; 3], weights: [f32; 3]) -> f32 {
+    rgb[0] * weights[0] + rgb[1] * weights[1] + rgb[2] * weights[2]
+}
+
+fn transform_pixel(rgb: [f32; 3], transform: &PixelTransform) -> [f32; 3] {
+    match *transform {
+        PixelTransform::Sepia { intensity } => {
+            let intensity = intensity.clamp(0.0, 1.0);
+            std::array::from_fn(|c| {
+                let sepia = luma(rgb, SEPIA_MATRIX[c]);
+                rgb[c] + (sepia - rgb[c]) * intensity
+            })
+        }
+        PixelTransform::Grayscale { standard } => [luma(rgb, standard.weights()); 3],
+        PixelTransform::Duotone { highlight, shadow } => {
+            let t = luma(rgb, LumaStandard::Bt709.weights()) / 255.0;
+            std::array::from_fn(|c| shadow[c] as f32 + (highlight[c] as f32 - shadow[c] as f32) * t)
+        }
+        PixelTransform::ColorBalance {
+            shadows,
+            midtones,
+            highlights,
+        } => {
+            // Triangular zone weights over luma; they sum to 1 everywhere
+            let l = luma(rgb, LumaStandard::Bt709.weights()) / 255.0;
+            let shadow_weight = (1.0 - l * 2.0).max(0.0);
+            let highlight_weight = (l * 2.0 - 1.0).max(0.0);
+            let midtone_weight = 1.0 - shadow_weight - highlight_weight;
+            std::array::from_fn(|c| {
+                rgb[c]
+                    + shadows[c].clamp(-255, 255) as f32 * shadow_weight
+                    + midtones[c].clamp(-255, 255) as f32 * midtone_weight
+                    + highlights[c].clamp(-255, 255) as f32 * highlight_weight
+            })
+        }
+        PixelTransform::Gamma { gamma } => {
+            std::array::from_fn(|c| 255.0 * (rgb[c] / 255.0).powf(1.0 / gamma[c].clamp(0.1, 10.0)))
+        }
+    }
+}
+
+/// Apply `transform` to every pixel of `img`, returning an RGBA8 image.
+pub fn apply_pixel_transform(img: DynamicImage, transform: &PixelTransform) -> DynamicImage {
+    let mut rgba = img.into_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let Rgba([r, g, b, a]) = *pixel;
+        let out = transform_pixel([r as f32, g as f32, b as f32], transform);
+        *pixel = Rgba([
+            out[0].round().clamp(0.0, 255.0) as u8,
+            out[1].round().clamp(0.0, 255.0) as u8,
+            out[2].round().clamp(0.0, 255.0) as u8,
+            a,
+        ]);
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Colour encodings handled by [`convert_to_color_space`]. True CMYK output is
+/// not supported: results stay RGB or gray and no output ICC profile is
+/// embedded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSpace {
+    /// Gamma-encoded with the sRGB transfer curve.
+    Srgb,
+    /// Linear light, no transfer curve.
+    Linear,
+    /// BT.601 luma of the sRGB-encoded channels.
+    GrayscaleBt601,
+    /// BT.709 luma of the sRGB-encoded channels.
+    GrayscaleBt709,
+}
+
+impl ColorSpace {
+    /// Whether this is one of the weighted grayscale targets.
+    pub fn is_grayscale(self) -> bool {
+        matches!(
+            self,
+            ColorSpace::GrayscaleBt601 | ColorSpace::GrayscaleBt709
+        )
+    }
+}
+
+/// Decode one sRGB-encoded sample in [0, 1] to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode one linear sample in [0, 1] with the sRGB transfer curve.
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Whether `color` stores pixels without colour channels.
+pub fn is_gray_color_type(color: ColorType) -> bool {
+    matches!(
+        color,
+        ColorType::L8 | ColorType::La8 | ColorType::L16 | ColorType::La16
+    )
+}
+
+/// Convert `img`, encoded as `source` (`Srgb` or `Linear`), to `target`.
+///
+/// Samples are processed as f32 in [0, 1] and written back at the source bit
+/// depth (8 or 16 bit) with alpha kept. Grayscale targets and gray sources
+/// come back as luma images.
+pub fn convert_to_color_space(
+    img: DynamicImage,
+    source: ColorSpace,
+    target: ColorSpace,
+) -> DynamicImage {
+    let color = img.color();
+    let high_depth = matches!(
+        color,
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+    );
+    let has_alpha = color.has_alpha();
+    let gray_output = target.is_grayscale() || is_gray_color_type(color);
+
+    let mut rgba = img.into_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let rgb = [r, g, b].map(|value| value.clamp(0.0, 1.0));
+        // Route everything through sRGB-encoded values; BT.601/709 luma is
+        // defined on gamma-encoded channels
+        let encoded = match source {
+            ColorSpace::Linear => rgb.map(linear_to_srgb),
+            _ => rgb,
+        };
+        let out = match target {
+            ColorSpace::Srgb => encoded,
+            ColorSpace::Linear => encoded.map(srgb_to_linear),
+            ColorSpace::GrayscaleBt601 => [luma(encoded, LumaStandard::Bt601.weights()); 3],
+            ColorSpace::GrayscaleBt709 => [luma(encoded, LumaStandard::Bt709.weights()); 3],
+        };
+        pixel.0 = [out[0], out[1], out[2], a];
+    }
+
+    let converted = DynamicImage::ImageRgba32F(rgba);
+    match (gray_output, has_alpha, high_depth) {
+        (true, false, false) => DynamicImage::ImageLuma8(converted.to_luma8()),
+        (true, true, false) => DynamicImage::ImageLumaA8(converted.to_luma_alpha8()),
+        (true, false, true) => DynamicImage::ImageLuma16(converted.to_luma16()),
+        (true, true, true) => DynamicImage::ImageLumaA16(converted.to_luma_alpha16()),
+        (false, false, false) => DynamicImage::ImageRgb8(converted.to_rgb8()),
+        (false, true, false) => DynamicImage::ImageRgba8(converted.to_rgba8()),
+        (false, false, true) => DynamicImage::ImageRgb16(converted.to_rgb16()),
+        (false, true, true) => DynamicImage::ImageRgba16(converted.to_rgba16()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_pixel(rgba: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, Rgba(rgba)))
+    }
+
+    #[test]
+    fn gamma_brightens_midtones_per_channel() {
+        // 255 * (127 / 255)^(1 / 2.2) = 185.75
+        let transform = PixelTransform::Gamma {
+            gamma: [2.2, 1.0, 0.5],
+        };
+        let out = apply_pixel_transform(single_pixel([127, 127, 127, 200]), &transform);
+        assert_eq!(out.to_rgba8().get_pixel(0, 0).0, [186, 127, 63, 200]);
+    }
+
+    #[test]
+    fn gamma_is_clamped_to_its_range() {
+        let clamped = PixelTransform::Gamma { gamma: [100.0; 3] };
+        let limit = PixelTransform::Gamma { gamma: [10.0; 3] };
+        let img = single_pixel([40, 127, 200, 255]);
+        assert_eq!(
+            apply_pixel_transform(img.clone(), &clamped).to_rgba8(),
+            apply_pixel_transform(img, &limit).to_rgba8()
+        );
+    }
+}
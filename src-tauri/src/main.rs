@@ -4,34 +4,48 @@ use anyhow::anyhow;
 use base64::Engine;
 use image::ImageFormat;
 use image::GenericImageView;
-use img_parts::ImageEXIF;
-use oxipng::StripChunks;
-use imagequant::RGBA as QuantRgba;
-use std::collections::HashMap;
-use std::sync::Mutex;
+use img_parts::{ImageEXIF, ImageICC};
+use rayon::prelude::*;
 use std::{fs, path::Path};
-use tauri::{Manager, Result};
+use tauri::{Emitter, Manager, Result};
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
+use ximage::compress::{
+    candidate_score, compress_image, compress_image_with, compress_to_memory,
+    compress_to_memory_with, config_diff, detect_format_from_bytes, detect_image_format,
+    encode_at_quality, encode_image, encode_png_quantized, encode_webp, final_output_path,
+    format_extension, format_from_name, format_name, jpeg_candidates, mime_type, paced_job, phash,
+    read_exif_orientation, resize_for_config, run_batch, track_temp_dir, tracked_temp_dirs,
+    validate_config, write_atomic, write_output, BatchLimits, CompressionConfig, ConfigFieldChange,
+    ConfigValidationError, IoPriority, JpegBackend, JpegStrategy, Pacer, PngDitheringMethod,
+    QualityDefaults, WebpMode, COMPRESS_TEMP_DIR, DEFAULT_IO_RETRY_ATTEMPTS,
+    DEFAULT_IO_RETRY_DELAY_MS, DEFAULT_JPEG_QUALITY_RANGE, DEFAULT_PNG_QUANTIZED_PRESET,
+    DEFAULT_WEBP_METHOD, QUALITY_DEFAULTS,
+};
+use ximage::effects::{
+    apply_pixel_transform, convert_to_color_space, is_gray_color_type, ColorSpace, LumaStandard,
+    PixelTransform,
+};
 
-// Global temporary directory tracker to clean up resources
-lazy_static::lazy_static! {
-    static ref TEMP_DIRS: Mutex<HashMap<String, std::time::SystemTime>> = Mutex::new(HashMap::new());
-}
-
-struct CompressionConfig {
-    lossless: bool,
-    quality_jpg: u8,
-    quality_webp: u8,
-    quality_png: u8,
-    preserve_exif: bool,
-    resize_width: Option<u32>,
-    resize_height: Option<u32>,
-}
+const MAX_FILENAME_BYTES: usize = 255;
+// Per-operation upload directories live under this one in the system temp dir
+const UPLOAD_TEMP_DIR: &str = "Ximage-upload";
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
 
 // Sanitize filename to prevent path traversal attacks
 fn sanitize_filename(filename: &str) -> String {
+    // Compose first so visually identical inputs map to the same name, then
+    // drop control characters and replacement characters from bad decodes
+    let normalized: String = filename
+        .nfc()
+        .filter(|c| !c.is_control() && *c != '\u{FFFD}')
+        .collect();
+
     // Remove any path separators and parent directory references
-    let sanitized = filename
+    let sanitized = normalized
         .replace("..", "")
         .replace("/", "_")
         .replace("\\", "_")
@@ -47,278 +61,120 @@ fn sanitize_filename(filename: &str) -> String {
         }
     }
 
-    clean
-}
-
-fn detect_image_format(path: &Path) -> anyhow::Result<ImageFormat> {
-    let ext = path
-        .extension()
-        .ok_or(anyhow!("No extension"))?
-        .to_string_lossy()
-        .to_lowercase();
-
-    match ext.as_str() {
-        "png" => Ok(ImageFormat::Png),
-        "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
-        "webp" => Ok(ImageFormat::WebP),
-        _ => Err(anyhow!("Unsupported format: {}", ext)),
+    // Windows reserves these device names whatever extension follows them
+    let base = clean.split('.').next().unwrap_or("");
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+    {
+        clean.insert(0, '_');
     }
-}
 
-fn preserve_exif_data(original_path: &Path, compressed_path: &Path, format: ImageFormat) -> anyhow::Result<()> {
-    let original_bytes = fs::read(original_path)?;
-    let compressed_bytes = fs::read(compressed_path)?;
-    
-    let exif_data: Option<img_parts::Bytes> = match format {
-        ImageFormat::Jpeg => {
-            let jpeg = img_parts::jpeg::Jpeg::from_bytes(original_bytes.into())?;
-            jpeg.exif()
-        }
-        _ => None,
-    };
-    
-    if let Some(exif) = exif_data {
-        let new_bytes = match format {
-            ImageFormat::Jpeg => {
-                let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(compressed_bytes.into())?;
-                jpeg.set_exif(Some(exif));
-                let mut output = Vec::new();
-                jpeg.encoder().write_to(&mut output)?;
-                output
-            }
-            _ => compressed_bytes,
+    if clean.len() > MAX_FILENAME_BYTES {
+        // Keep the extension and cut the stem on a char boundary
+        let ext = match clean.rfind('.') {
+            Some(dot) if clean.len() - dot < 16 => clean[dot..].to_string(),
+            _ => String::new(),
         };
-        fs::write(compressed_path, new_bytes)?;
-    }
-    
-    Ok(())
-}
-
-fn compress_image(
-    original_path: &Path,
-    config: &CompressionConfig,
-    output_path: Option<&Path>,
-    maintain_aspect_ratio: bool,
-) -> anyhow::Result<(u64, u64)> {
-    let format = detect_image_format(original_path)?;
-    let original_size = fs::metadata(original_path)?.len();
-
-    let img = image::open(original_path)?;
-    let mut processed_img = img;
-
-    // Resize if needed
-    if let (Some(width), Some(height)) = (config.resize_width, config.resize_height) {
-        if maintain_aspect_ratio {
-            let (orig_w, orig_h) = processed_img.dimensions();
-            let scale_w = width as f64 / orig_w as f64;
-            let scale_h = height as f64 / orig_h as f64;
-            let scale = scale_w.min(scale_h);
-            let new_w = (orig_w as f64 * scale).round().max(1.0) as u32;
-            let new_h = (orig_h as f64 * scale).round().max(1.0) as u32;
-            processed_img = processed_img.resize(new_w, new_h, image::imageops::FilterType::Lanczos3);
-        } else {
-            processed_img = processed_img.resize(width, height, image::imageops::FilterType::Lanczos3);
+        let mut cut = MAX_FILENAME_BYTES - ext.len();
+        while !clean.is_char_boundary(cut) {
+            cut -= 1;
         }
+        // A stem cut just after a dot would run into the extension's dot
+        let stem = clean[..cut].trim_end_matches('.');
+        clean = format!("{}{}", stem, ext);
     }
 
-    // Create a secure temporary directory for this operation
-    let temp_dir = std::env::temp_dir()
-        .join("Ximage-compress")
-        .join(Uuid::new_v4().to_string());
-    std::fs::create_dir_all(&temp_dir).map_err(|e| anyhow!("Failed to create temp dir: {}", e))?;
-
-    // Track the temp directory for cleanup
-    {
-        let mut dirs = TEMP_DIRS.lock().unwrap();
-        dirs.insert(
-            temp_dir.to_string_lossy().to_string(),
-            std::time::SystemTime::now(),
-        );
+    if clean.trim_matches(|c| c == '.' || c == '_').is_empty() {
+        return "unnamed_file".to_string();
     }
 
-    // Generate a unique temporary file name
-    let file_stem = original_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("temp");
-    let extension = original_path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("tmp");
-
-    let temp_path = temp_dir.join(format!("{}_compressed.{}", file_stem, extension));
-
-    match format {
-        ImageFormat::Png => {
-            if config.lossless {
-                processed_img.save_with_format(&temp_path, ImageFormat::Png)?;
-                let png_data = fs::read(&temp_path)?;
-                // Faster lossless optimization preset
-                let optimized = oxipng::optimize_from_memory(&png_data, &oxipng::Options::from_preset(1))?;
-                fs::write(&temp_path, optimized)?;
-            } else {
-                let rgba = processed_img.to_rgba8();
-                let (width, height) = rgba.dimensions();
-                let pixels: Vec<QuantRgba> = rgba
-                    .as_raw()
-                    .chunks_exact(4)
-                    .map(|c| QuantRgba::new(c[0], c[1], c[2], c[3]))
-                    .collect();
-
-                let quantize_once = |target: u8, min_offset: u8, speed: u8, dither: f32| -> anyhow::Result<Vec<u8>> {
-                    let mut attr = imagequant::Attributes::new();
-                    let min = target.saturating_sub(min_offset);
-                    attr.set_quality(min, target)?;
-                    // Balanced speed/quality
-                    attr.set_speed(speed.into())?;
-
-                    let mut img = attr.new_image(pixels.clone(), width as usize, height as usize, 0.0)?;
-                    let mut res = attr.quantize(&mut img)?;
-                    res.set_dithering_level(dither)?;
-                    let (palette, indexed_pixels) = res.remapped(&mut img)?;
-
-                    let mut palette_bytes = Vec::with_capacity(palette.len() * 3);
-                    let mut trns = Vec::with_capacity(palette.len());
-                    for color in palette {
-                        palette_bytes.extend_from_slice(&[color.r, color.g, color.b]);
-                        trns.push(color.a);
-                    }
-
-                    let mut output = Vec::new();
-                    {
-                        let cursor = std::io::Cursor::new(&mut output);
-                        let mut encoder = png::Encoder::new(cursor, width, height);
-                        encoder.set_color(png::ColorType::Indexed);
-                        encoder.set_depth(png::BitDepth::Eight);
-                        encoder.set_palette(palette_bytes);
-                        if trns.iter().any(|&a| a < 255) {
-                            encoder.set_trns(trns);
-                        }
-                        let mut writer = encoder.write_header()?;
-                        writer.write_image_data(&indexed_pixels)?;
-                    }
-                    Ok(output)
-                };
+    clean
+}
 
-                let target = config.quality_png.min(100).max(10);
-                let (min_offset, speed, dither) = if target >= 80 {
-                    (25, 8, 0.6)
-                } else if target >= 60 {
-                    (30, 9, 0.8)
-                } else {
-                    (40, 10, 1.0)
-                };
-                let selected = quantize_once(target, min_offset, speed, dither)?;
-
-                fs::write(&temp_path, selected)?;
-                let png_data = fs::read(&temp_path)?;
-                let mut options = oxipng::Options::from_preset(2);
-                options.strip = StripChunks::All;
-                if let Ok(optimized) = oxipng::optimize_from_memory(&png_data, &options) {
-                    let _ = fs::write(&temp_path, optimized);
-                }
-            }
-        }
-        ImageFormat::Jpeg => {
-            if config.lossless {
-                let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
-                    std::io::BufWriter::new(std::fs::File::create(&temp_path)?),
-                    100,
-                );
-                encoder.encode_image(&processed_img)?;
-            } else {
-                let encode_jpeg = |quality: u8| -> anyhow::Result<Vec<u8>> {
-                    let mut buffer = Vec::new();
-                    let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality);
-                    encoder.encode_image(&processed_img)?;
-                    Ok(buffer)
-                };
+// Pointer tags are regenerated by the EXIF writer and must not be copied
+fn is_exif_pointer_tag(tag: exif::Tag) -> bool {
+    matches!(
+        tag,
+        exif::Tag::ExifIFDPointer
+            | exif::Tag::GPSInfoIFDPointer
+            | exif::Tag::InteropIFDPointer
+            | exif::Tag::JPEGInterchangeFormat
+            | exif::Tag::JPEGInterchangeFormatLength
+    )
+}
 
-                let base_q = config.quality_jpg.clamp(60, 95);
-                let mut candidates = vec![
-                    base_q.saturating_add(10),
-                    base_q.saturating_add(5),
-                    base_q,
-                    base_q.saturating_sub(5),
-                    base_q.saturating_sub(10),
-                ];
-                candidates.retain(|q| *q >= 60 && *q <= 95);
-                candidates.sort_unstable();
-                candidates.dedup();
-
-                let base = encode_jpeg(base_q)?;
-                let base_size = base.len().max(1);
-                let mut best = (base, 0.0);
-
-                for cand in candidates {
-                    let data = encode_jpeg(cand)?;
-                    let size = data.len().max(1);
-                    let size_improve = (base_size as f64 - size as f64) / base_size as f64;
-                    let quality_score = cand as f64 / 100.0;
-                    let speed_penalty = if cand >= 85 { 0.05 } else if cand >= 75 { 0.07 } else { 0.1 };
-                    let score = quality_score * 0.5 + size_improve * 0.4 - speed_penalty * 0.1;
-                    if score > best.1 {
-                        best = (data, score);
-                    }
-                }
+// Rebuild a raw EXIF (TIFF) block keeping only the fields accepted by `keep`.
+// Returns the new block with the number of kept and dropped fields.
+fn filter_exif_fields(
+    raw_exif: &[u8],
+    keep: impl Fn(&exif::Field) -> bool,
+) -> anyhow::Result<(Vec<u8>, u32, u32)> {
+    let exif = exif::Reader::new().read_raw(raw_exif.to_vec())?;
+    let mut writer = exif::experimental::Writer::new();
+    let mut kept = 0u32;
+    let mut dropped = 0u32;
 
-                fs::write(&temp_path, best.0)?;
-            }
+    for field in exif.fields() {
+        if is_exif_pointer_tag(field.tag) {
+            continue;
         }
-        ImageFormat::WebP => {
-            if config.lossless {
-                processed_img.save_with_format(&temp_path, ImageFormat::WebP)?;
-            } else {
-                let rgba = processed_img.to_rgba8();
-                let (width, height) = rgba.dimensions();
-                let encoder = webp::Encoder::from_rgba(&rgba, width, height);
-
-                let mut webp_config = webp::WebPConfig::new().unwrap();
-                webp_config.quality = config.quality_webp as f32;
-                webp_config.method = 4;
-                webp_config.sns_strength = 70;
-                webp_config.filter_strength = 30;
-                webp_config.filter_sharpness = 3;
-                webp_config.autofilter = 1;
-                webp_config.alpha_quality = 80;
-                webp_config.alpha_compression = 1;
-                webp_config.near_lossless = 60;
-                webp_config.exact = 0;
-                webp_config.thread_level = 1;
-
-                let webp_data = encoder
-                    .encode_advanced(&webp_config)
-                    .map_err(|e| anyhow!("WebP encode failed: {:?}", e))?;
-                fs::write(&temp_path, &*webp_data)?;
-            }
+        if keep(field) {
+            writer.push_field(field);
+            kept += 1;
+        } else {
+            dropped += 1;
         }
-        _ => return Err(anyhow!("Unsupported format")),
-    }
-
-    if config.preserve_exif && format == ImageFormat::Jpeg {
-        let _ = preserve_exif_data(original_path, &temp_path, format);
     }
 
-    let final_path = if let Some(out_path) = output_path {
-        if let Some(parent) = out_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        out_path.to_path_buf()
-    } else {
-        original_path.to_path_buf()
-    };
-
-    fs::copy(&temp_path, &final_path)?;
+    let mut output = std::io::Cursor::new(Vec::new());
+    writer.write(&mut output, exif.little_endian())?;
+    Ok((output.into_inner(), kept, dropped))
+}
 
-    let compressed_size = fs::metadata(&final_path)?.len();
+// "default", or "mozJpeg" with its two switches (both on when omitted)
+fn parse_jpeg_backend(
+    name: &str,
+    progressive: Option<bool>,
+    optimize_coding: Option<bool>,
+) -> anyhow::Result<JpegBackend> {
+    match name {
+        "default" => Ok(JpegBackend::Default),
+        "mozJpeg" => Ok(JpegBackend::MozJpeg {
+            progressive: progressive.unwrap_or(true),
+            optimize_coding: optimize_coding.unwrap_or(true),
+        }),
+        other => Err(anyhow!(
+            "unknown jpeg backend `{}`, expected `default` or `mozJpeg`",
+            other
+        )),
+    }
+}
 
-    std::fs::remove_dir_all(temp_dir).ok();
+// Enum arguments arrive as bare serde variant names ("floydSteinberg", "low"),
+// never as JSON-encoded strings
+fn parse_variant_name<T: serde::de::DeserializeOwned>(name: String) -> serde_json::Result<T> {
+    serde_json::from_value(serde_json::Value::String(name))
+}
 
-    Ok((original_size, compressed_size))
+// "direct", or "multiCandidate" scoring candidates within `quality_range`
+// (DEFAULT_JPEG_QUALITY_RANGE when omitted)
+fn parse_jpeg_strategy(name: &str, quality_range: Option<u8>) -> anyhow::Result<JpegStrategy> {
+    match name {
+        "direct" => Ok(JpegStrategy::Direct),
+        "multiCandidate" => Ok(JpegStrategy::MultiCandidate {
+            quality_range: quality_range.unwrap_or(DEFAULT_JPEG_QUALITY_RANGE),
+        }),
+        other => Err(anyhow!(
+            "unknown jpeg strategy `{}`, expected `direct` or `multiCandidate`",
+            other
+        )),
+    }
 }
 
 #[tauri::command]
 async fn compress_images(
+    app: tauri::AppHandle,
     paths: Vec<String>,
     lossless: bool,
     quality_jpg: u8,
@@ -327,7 +183,55 @@ async fn compress_images(
     preserve_exif: bool,
     resize_width: Option<u32>,
     resize_height: Option<u32>,
-) -> Result<Vec<(String, u64, u64, String)>> {
+    png_interlaced: Option<bool>,
+    io_retry_attempts: Option<u8>,
+    io_retry_initial_delay_ms: Option<u64>,
+    webp_mode_json: Option<String>,
+    embed_xmp: Option<bool>,
+    jpeg_strategy: Option<String>,
+    jpeg_quality_range: Option<u8>,
+    max_batch_concurrency: Option<usize>,
+    memory_limit_mb: Option<u64>,
+    jpeg_strip_restart_markers: Option<bool>,
+    check_disk_space: Option<bool>,
+    preserve_file_permissions: Option<bool>,
+    png_dithering_method: Option<String>,
+    png_ordered_dither_size: Option<u8>,
+    jpeg_optimize_huffman: Option<bool>,
+    jpeg_backend: Option<String>,
+    mozjpeg_progressive: Option<bool>,
+    mozjpeg_optimize_coding: Option<bool>,
+    throttle_ms: Option<u64>,
+    io_priority: Option<String>,
+    output_format: Option<String>,
+) -> Result<Vec<(String, u64, u64, String, Option<u8>)>> {
+    let webp_mode = match webp_mode_json {
+        Some(json) => Some(serde_json::from_str::<WebpMode>(&json)?),
+        None => None,
+    };
+    let jpeg_strategy = match jpeg_strategy {
+        Some(name) => Some(parse_jpeg_strategy(&name, jpeg_quality_range)?),
+        None => None,
+    };
+    let png_dithering_method = match png_dithering_method {
+        Some(name) => Some(parse_variant_name::<PngDitheringMethod>(name)?),
+        None => None,
+    };
+    let jpeg_backend = match jpeg_backend {
+        Some(name) => Some(parse_jpeg_backend(
+            &name,
+            mozjpeg_progressive,
+            mozjpeg_optimize_coding,
+        )?),
+        None => None,
+    };
+    let io_priority = match io_priority {
+        Some(name) => Some(parse_variant_name::<IoPriority>(name)?),
+        None => None,
+    };
+    if let Some(name) = output_format.as_deref() {
+        format_from_name(name)?;
+    }
     let config = CompressionConfig {
         lossless,
         quality_jpg,
@@ -336,27 +240,66 @@ async fn compress_images(
         preserve_exif,
         resize_width,
         resize_height,
+        png_interlaced: png_interlaced.unwrap_or(false),
+        io_retry_attempts: io_retry_attempts.unwrap_or(DEFAULT_IO_RETRY_ATTEMPTS),
+        io_retry_initial_delay_ms: io_retry_initial_delay_ms.unwrap_or(DEFAULT_IO_RETRY_DELAY_MS),
+        webp_mode,
+        embed_xmp: embed_xmp.unwrap_or(false),
+        jpeg_strategy,
+        jpeg_strip_restart_markers: jpeg_strip_restart_markers.unwrap_or(false),
+        check_disk_space: check_disk_space.unwrap_or(false),
+        preserve_file_permissions: preserve_file_permissions.unwrap_or(false),
+        png_dithering_method,
+        png_ordered_dither_size: png_ordered_dither_size.unwrap_or(4),
+        jpeg_optimize_huffman: jpeg_optimize_huffman.unwrap_or(true),
+        jpeg_backend,
+        throttle_ms,
+        io_priority,
+        output_format,
+        ..CompressionConfig::default()
     };
 
-    let mut results = Vec::new();
+    // Outputs overwrite the sources, so they need about as much space again
+    if config.check_disk_space && paths.len() > DISK_CHECK_MIN_BATCH {
+        let required: u64 = paths
+            .iter()
+            .filter_map(|p| fs::metadata(p).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let directory = Path::new(&paths[0]).parent().unwrap_or(Path::new("."));
+        match disk_space_for(directory, required) {
+            Ok(info) if !info.has_sufficient_space => {
+                let _ = app.emit("insufficient_disk_space", info);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Disk space check failed: {}", e),
+        }
+    }
+
+    let limits = BatchLimits {
+        max_concurrency: max_batch_concurrency,
+        memory_limit_mb,
+    };
 
-    for path_str in paths {
-        let path = Path::new(&path_str);
+    let pacer = Pacer::new(&config);
+    let results = run_batch(paths, &limits, |path_str| {
+        paced_job(&config, &pacer, || {
+            let path = Path::new(&path_str);
+            // Outputs overwrite the sources, so read the orientation first
+            let exif_orientation = jpeg_orientation(path);
 
-        match compress_image(path, &config, None, false) {
-            Ok((original_size, compressed_size)) => {
-                results.push((
+            match compress_image(path, &config, None, false) {
+                Ok((original_size, compressed_size)) => (
                     path_str,
                     original_size,
                     compressed_size,
                     "success".to_string(),
-                ));
-            }
-            Err(e) => {
-                results.push((path_str, 0, 0, format!("error: {}", e)));
+                    exif_orientation,
+                ),
+                Err(e) => (path_str, 0, 0, format!("error: {}", e), exif_orientation),
             }
-        }
-    }
+        })
+    })?;
 
     Ok(results)
 }
@@ -380,6 +323,99 @@ struct FileData {
     source_path: Option<String>,
 }
 
+// Validate, stage and compress a single uploaded file, returning the
+// (name, original size, compressed size, status, source index) row
+fn compress_uploaded_file(
+    file_info: FileData,
+    temp_dir: &Path,
+    config: &CompressionConfig,
+    output_path: Option<&str>,
+    keep_aspect_ratio: bool,
+) -> (String, u64, u64, String, u32) {
+    println!("📂 处理文件: {}", file_info.name);
+
+    // Sanitize the filename to prevent path traversal
+    let sanitized_filename = sanitize_filename(&file_info.name);
+
+    // Validate file format
+    if !SUPPORTED_EXTENSIONS
+        .iter()
+        .any(|&f| f.eq_ignore_ascii_case(&file_info.format))
+    {
+        println!("❌ 不支持的文件格式: {}", file_info.format);
+        return (file_info.name, 0, 0, "unsupported_format".to_string(), file_info.source_index);
+    }
+
+    // 解码 base64 数据
+    let file_bytes = match base64::engine::general_purpose::STANDARD.decode(&file_info.data) {
+        Ok(bytes) => {
+            println!("✅ Base64 解码成功, {} 字节", bytes.len());
+
+            // Check file size (e.g., limit to 50MB)
+            if bytes.len() > 50 * 1024 * 1024 {
+                println!("❌ 文件过大 (>50MB): {} bytes", bytes.len());
+                return (file_info.name, 0, 0, "file_too_large".to_string(), file_info.source_index);
+            }
+
+            bytes
+        }
+        Err(e) => {
+            println!("❌ Base64 解码失败: {}", e);
+            return (file_info.name, 0, 0, "decode_failed".to_string(), file_info.source_index);
+        }
+    };
+
+    // 保存到临时文件; files run in parallel, so each gets its own subdirectory
+    let file_temp_dir = temp_dir.join(file_info.source_index.to_string());
+    let temp_path = file_temp_dir.join(&sanitized_filename);
+    if let Err(e) = fs::create_dir_all(&file_temp_dir).and_then(|_| fs::write(&temp_path, &file_bytes)) {
+        println!("❌ 保存临时文件失败: {}", e);
+        return (file_info.name, 0, 0, "save_failed".to_string(), file_info.source_index);
+    }
+
+    println!("💾 临时文件已保存: {}", temp_path.display());
+
+    let output_file_path = output_path.map(|p| {
+        let output_dir = Path::new(p);
+        output_dir.join(&sanitized_filename)
+    });
+
+    let source_path = file_info.source_path.as_ref().map(Path::new);
+
+    if output_file_path.is_none() && source_path.is_none() {
+        println!("❌ 未提供原始路径，无法覆盖原文件");
+        return (file_info.name, 0, 0, "missing_source_path".to_string(), file_info.source_index);
+    }
+
+    let original_path = source_path.unwrap_or(temp_path.as_path());
+
+    match compress_image(original_path, config, output_file_path.as_deref(), keep_aspect_ratio) {
+        Ok((original_size, compressed_size)) => {
+            let ratio = if original_size > 0 {
+                let saved = original_size.saturating_sub(compressed_size);
+                ((saved as f64 / original_size as f64) * 100.0) as u32
+            } else {
+                0
+            };
+            println!(
+                "✅ 压缩成功: {} -> {} (节省 {}%)",
+                original_size, compressed_size, ratio
+            );
+            (
+                file_info.name,
+                original_size,
+                compressed_size,
+                "success".to_string(),
+                file_info.source_index,
+            )
+        }
+        Err(e) => {
+            println!("❌ 压缩失败: {}", e);
+            (file_info.name, 0, 0, "compress_failed".to_string(), file_info.source_index)
+        }
+    }
+}
+
 #[tauri::command]
 async fn compress_uploaded_files(
     file_data: Vec<FileData>,
@@ -392,142 +428,9077 @@ async fn compress_uploaded_files(
     resize_height: Option<u32>,
     maintain_aspect_ratio: Option<bool>,
     output_path: Option<String>,
+    png_interlaced: Option<bool>,
+    io_retry_attempts: Option<u8>,
+    io_retry_initial_delay_ms: Option<u64>,
+    webp_mode_json: Option<String>,
+    embed_xmp: Option<bool>,
+    jpeg_strategy: Option<String>,
+    jpeg_quality_range: Option<u8>,
+    max_batch_concurrency: Option<usize>,
+    memory_limit_mb: Option<u64>,
+    jpeg_strip_restart_markers: Option<bool>,
+    preserve_file_permissions: Option<bool>,
+    png_dithering_method: Option<String>,
+    png_ordered_dither_size: Option<u8>,
+    jpeg_optimize_huffman: Option<bool>,
+    jpeg_backend: Option<String>,
+    mozjpeg_progressive: Option<bool>,
+    mozjpeg_optimize_coding: Option<bool>,
+    throttle_ms: Option<u64>,
+    io_priority: Option<String>,
+    output_format: Option<String>,
 ) -> std::result::Result<Vec<(String, u64, u64, String, u32)>, String> {
     println!("🎯 后端收到前端上传的 {} 个文件进行压缩", file_data.len());
-    let mut results = Vec::new();
-
+    let webp_mode = match webp_mode_json {
+        Some(json) => Some(
+            serde_json::from_str::<WebpMode>(&json)
+                .map_err(|e| format!("Invalid webp mode: {}", e))?,
+        ),
+        None => None,
+    };
+    let jpeg_strategy = match jpeg_strategy {
+        Some(name) => Some(
+            parse_jpeg_strategy(&name, jpeg_quality_range)
+                .map_err(|e| format!("Invalid jpeg strategy: {}", e))?,
+        ),
+        None => None,
+    };
+    let png_dithering_method = match png_dithering_method {
+        Some(name) => Some(
+            parse_variant_name::<PngDitheringMethod>(name)
+                .map_err(|e| format!("Invalid png dithering method: {}", e))?,
+        ),
+        None => None,
+    };
+    let jpeg_backend = match jpeg_backend {
+        Some(name) => Some(
+            parse_jpeg_backend(&name, mozjpeg_progressive, mozjpeg_optimize_coding)
+                .map_err(|e| format!("Invalid jpeg backend: {}", e))?,
+        ),
+        None => None,
+    };
+    let io_priority = match io_priority {
+        Some(name) => Some(
+            parse_variant_name::<IoPriority>(name)
+                .map_err(|e| format!("Invalid io priority: {}", e))?,
+        ),
+        None => None,
+    };
+    if let Some(name) = output_format.as_deref() {
+        format_from_name(name).map_err(|e| e.to_string())?;
+    }
     let keep_aspect_ratio = maintain_aspect_ratio.unwrap_or(false);
 
     // Create a secure temporary directory for this operation
     let temp_dir = std::env::temp_dir()
-        .join("Ximage-upload")
+        .join(UPLOAD_TEMP_DIR)
         .join(Uuid::new_v4().to_string());
     std::fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
 
-    for file_info in file_data {
-        println!("📂 处理文件: {}", file_info.name);
+    // 压缩配置
+    let config = CompressionConfig {
+        lossless,
+        quality_jpg,
+        quality_webp,
+        quality_png,
+        preserve_exif,
+        resize_width,
+        resize_height,
+        png_interlaced: png_interlaced.unwrap_or(false),
+        io_retry_attempts: io_retry_attempts.unwrap_or(DEFAULT_IO_RETRY_ATTEMPTS),
+        io_retry_initial_delay_ms: io_retry_initial_delay_ms.unwrap_or(DEFAULT_IO_RETRY_DELAY_MS),
+        webp_mode,
+        embed_xmp: embed_xmp.unwrap_or(false),
+        jpeg_strategy,
+        jpeg_strip_restart_markers: jpeg_strip_restart_markers.unwrap_or(false),
+        preserve_file_permissions: preserve_file_permissions.unwrap_or(false),
+        png_dithering_method,
+        png_ordered_dither_size: png_ordered_dither_size.unwrap_or(4),
+        jpeg_optimize_huffman: jpeg_optimize_huffman.unwrap_or(true),
+        jpeg_backend,
+        throttle_ms,
+        io_priority,
+        output_format,
+        ..CompressionConfig::default()
+    };
+
+    let limits = BatchLimits {
+        max_concurrency: max_batch_concurrency,
+        memory_limit_mb,
+    };
+    let pacer = Pacer::new(&config);
+    let results = run_batch(file_data, &limits, |file_info| {
+        paced_job(&config, &pacer, || {
+            compress_uploaded_file(
+                file_info,
+                &temp_dir,
+                &config,
+                output_path.as_deref(),
+                keep_aspect_ratio,
+            )
+        })
+    })
+    .map_err(|e| e.to_string())?;
 
-        // Sanitize the filename to prevent path traversal
-        let sanitized_filename = sanitize_filename(&file_info.name);
+    // Clean up temp directory after processing
+    std::fs::remove_dir_all(&temp_dir).ok(); // Ignore errors during cleanup
 
-        // Validate file format
-        let valid_formats = ["png", "jpg", "jpeg", "webp"];
-        if !valid_formats
-            .iter()
-            .any(|&f| f.eq_ignore_ascii_case(&file_info.format))
-        {
-            println!("❌ 不支持的文件格式: {}", file_info.format);
-            results.push((file_info.name, 0, 0, "unsupported_format".to_string(), file_info.source_index));
-            continue;
+    println!("📦 后端返回压缩结果: {:?}", results);
+    Ok(results)
+}
+
+// Longest side of the preview used for size estimation
+const ESTIMATE_SAMPLE_SIZE: u32 = 512;
+
+// Estimate the encoded size by compressing a downsampled preview and scaling
+// the result by the pixel ratio. Much cheaper than a full encode.
+fn estimate_compressed_size(
+    img: &image::DynamicImage,
+    format: ImageFormat,
+    config: &CompressionConfig,
+) -> anyhow::Result<u64> {
+    let (width, height) = img.dimensions();
+    let thumbnail;
+    let sample = if width > ESTIMATE_SAMPLE_SIZE || height > ESTIMATE_SAMPLE_SIZE {
+        thumbnail = img.thumbnail(ESTIMATE_SAMPLE_SIZE, ESTIMATE_SAMPLE_SIZE);
+        &thumbnail
+    } else {
+        img
+    };
+
+    let (sample_w, sample_h) = sample.dimensions();
+    let encoded = encode_image(sample, format, config)?;
+    let scale = (width as f64 * height as f64) / (sample_w as f64 * sample_h as f64).max(1.0);
+    Ok((encoded.len() as f64 * scale).round() as u64)
+}
+
+// Count distinct colors and the share of non-opaque pixels. Large images are
+// sampled with nearest-neighbour so no new colors get blended in.
+fn analyze_colors(img: &image::DynamicImage) -> (usize, f64) {
+    let (width, height) = img.dimensions();
+    let rgba = if width > ESTIMATE_SAMPLE_SIZE || height > ESTIMATE_SAMPLE_SIZE {
+        img.resize(ESTIMATE_SAMPLE_SIZE, ESTIMATE_SAMPLE_SIZE, image::imageops::FilterType::Nearest)
+            .to_rgba8()
+    } else {
+        img.to_rgba8()
+    };
+
+    let mut colors = std::collections::HashSet::new();
+    let mut transparent = 0usize;
+    for pixel in rgba.pixels() {
+        colors.insert(pixel.0);
+        if pixel.0[3] < 255 {
+            transparent += 1;
         }
+    }
 
-        // 解码 base64 数据
-        let file_bytes = match base64::engine::general_purpose::STANDARD.decode(&file_info.data) {
-            Ok(bytes) => {
-                println!("✅ Base64 解码成功, {} 字节", bytes.len());
+    let total = (rgba.width() as usize * rgba.height() as usize).max(1);
+    (colors.len(), transparent as f64 / total as f64)
+}
 
-                // Check file size (e.g., limit to 50MB)
-                if bytes.len() > 50 * 1024 * 1024 {
-                    println!("❌ 文件过大 (>50MB): {} bytes", bytes.len());
-                    results.push((file_info.name, 0, 0, "file_too_large".to_string(), file_info.source_index));
-                    continue;
-                }
+// Entropy above this looks photographic, below the low mark flat graphics
+const HIGH_ENTROPY_THRESHOLD: f64 = 6.5;
+const LOW_ENTROPY_THRESHOLD: f64 = 3.0;
 
-                bytes
-            }
-            Err(e) => {
-                println!("❌ Base64 解码失败: {}", e);
-                results.push((file_info.name, 0, 0, "decode_failed".to_string(), file_info.source_index));
-                continue;
-            }
-        };
+// Shannon entropy of the grayscale histogram in bits, in [0.0, 8.0]. Large
+// images are sampled with nearest-neighbour so the histogram is not smoothed.
+fn measure_image_entropy(img: &image::DynamicImage) -> f64 {
+    let (width, height) = img.dimensions();
+    let gray = if width > 256 || height > 256 {
+        img.resize(256, 256, image::imageops::FilterType::Nearest)
+            .to_luma8()
+    } else {
+        img.to_luma8()
+    };
 
-        // 保存到临时文件
-        let temp_path = temp_dir.join(&sanitized_filename);
-        if let Err(e) = fs::write(&temp_path, &file_bytes) {
-            println!("❌ 保存临时文件失败: {}", e);
-            results.push((file_info.name, 0, 0, "save_failed".to_string(), file_info.source_index));
-            continue;
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total = (gray.width() as u64 * gray.height() as u64).max(1) as f64;
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompressionRecommendations {
+    suggested_format: String,
+    suggested_quality: u8,
+    estimated_savings_percent: f64,
+    reasons: Vec<String>,
+}
+
+fn recommend_compression(path: &Path) -> anyhow::Result<CompressionRecommendations> {
+    let source_format = detect_image_format(path)?;
+    let original_size = fs::metadata(path)?.len();
+    let img = image::open(path)?;
+
+    let (color_count, transparent_ratio) = analyze_colors(&img);
+    let entropy = measure_image_entropy(&img);
+    let mut reasons = Vec::new();
+
+    let (format, quality, lossless) = if transparent_ratio > 0.01 {
+        reasons.push(format!(
+            "{:.1}% of pixels are transparent: the output format must keep the alpha channel",
+            transparent_ratio * 100.0
+        ));
+        if color_count <= 256 {
+            reasons.push(format!(
+                "Image has {} colors: palette compression will be effective",
+                color_count
+            ));
+            (ImageFormat::Png, 80, false)
+        } else {
+            reasons.push(format!(
+                "Image has {} colors: WebP stores alpha more compactly than PNG",
+                color_count
+            ));
+            (ImageFormat::WebP, 80, false)
+        }
+    } else if color_count <= 256 {
+        reasons.push(format!(
+            "Image has {} colors: palette compression will be effective",
+            color_count
+        ));
+        (ImageFormat::Png, 80, false)
+    } else if entropy < LOW_ENTROPY_THRESHOLD
+        || (color_count <= 4096 && entropy <= HIGH_ENTROPY_THRESHOLD)
+    {
+        reasons.push(format!(
+            "Image has {} colors and {:.2} bits of entropy: looks like a graphic or screenshot, lossless WebP keeps edges sharp",
+            color_count, entropy
+        ));
+        (ImageFormat::WebP, 100, true)
+    } else {
+        reasons.push(format!(
+            "Image has {} colors and {:.2} bits of entropy: looks photographic, lossy compression will be effective",
+            color_count, entropy
+        ));
+        if source_format == ImageFormat::Jpeg {
+            reasons.push("Source is already JPEG: keeping the format avoids compatibility surprises".to_string());
+            (ImageFormat::Jpeg, 75, false)
+        } else {
+            (ImageFormat::WebP, 75, false)
         }
+    };
+
+    let config = CompressionConfig {
+        lossless,
+        quality_jpg: quality,
+        quality_webp: quality,
+        quality_png: quality,
+        ..CompressionConfig::default()
+    };
+    let estimated_size = estimate_compressed_size(&img, format, &config)?;
+    let estimated_savings_percent = if original_size > 0 {
+        (original_size as f64 - estimated_size as f64) / original_size as f64 * 100.0
+    } else {
+        0.0
+    };
 
-        println!("💾 临时文件已保存: {}", temp_path.display());
+    Ok(CompressionRecommendations {
+        suggested_format: format_name(format).to_string(),
+        suggested_quality: quality,
+        estimated_savings_percent,
+        reasons,
+    })
+}
 
-        // 压缩文件
-        let config = CompressionConfig {
-            lossless,
-            quality_jpg,
-            quality_webp,
-            quality_png,
-            preserve_exif,
-            resize_width,
-            resize_height,
-        };
-
-        let output_file_path = output_path.as_ref().map(|p| {
-            let output_dir = Path::new(p);
-            output_dir.join(&sanitized_filename)
+#[tauri::command]
+async fn get_image_entropy(path: String) -> std::result::Result<f64, String> {
+    let img = image::open(&path).map_err(|e| e.to_string())?;
+    Ok(measure_image_entropy(&img))
+}
+
+#[tauri::command]
+async fn get_compression_recommendations(
+    path: String,
+) -> std::result::Result<CompressionRecommendations, String> {
+    recommend_compression(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeExifResult {
+    fields_copied: u32,
+    fields_skipped: u32,
+}
+
+fn merge_exif_data(
+    source_path: &Path,
+    target_path: &Path,
+    fields: Option<&[String]>,
+) -> anyhow::Result<MergeExifResult> {
+    if detect_image_format(source_path)? != ImageFormat::Jpeg
+        || detect_image_format(target_path)? != ImageFormat::Jpeg
+    {
+        return Err(anyhow!("EXIF merging is only supported for JPEG files"));
+    }
+
+    let source = img_parts::jpeg::Jpeg::from_bytes(fs::read(source_path)?.into())?;
+    let raw_exif = source
+        .exif()
+        .ok_or(anyhow!("Source image has no EXIF data"))?;
+
+    let (exif_bytes, fields_copied, fields_skipped) = match fields {
+        Some(names) => filter_exif_fields(&raw_exif, |field| {
+            let tag_name = field.tag.to_string();
+            names.iter().any(|name| name.eq_ignore_ascii_case(&tag_name))
+        })?,
+        None => {
+            let exif = exif::Reader::new().read_raw(raw_exif.to_vec())?;
+            let count = exif
+                .fields()
+                .filter(|field| !is_exif_pointer_tag(field.tag))
+                .count() as u32;
+            (raw_exif.to_vec(), count, 0)
+        }
+    };
+
+    // Nothing matched the requested fields: leave the target untouched
+    if fields_copied == 0 {
+        return Ok(MergeExifResult {
+            fields_copied,
+            fields_skipped,
         });
+    }
 
-        let source_path = file_info.source_path.as_ref().map(Path::new);
+    let mut target = img_parts::jpeg::Jpeg::from_bytes(fs::read(target_path)?.into())?;
+    target.set_exif(Some(exif_bytes.into()));
+    let mut output = Vec::new();
+    target.encoder().write_to(&mut output)?;
+    write_atomic(&CompressionConfig::default(), target_path, &output)?;
 
-        if output_file_path.is_none() && source_path.is_none() {
-            println!("❌ 未提供原始路径，无法覆盖原文件");
-            results.push((file_info.name, 0, 0, "missing_source_path".to_string(), file_info.source_index));
-            continue;
+    Ok(MergeExifResult {
+        fields_copied,
+        fields_skipped,
+    })
+}
+
+#[tauri::command]
+async fn merge_exif(
+    source_path: String,
+    target_path: String,
+    fields: Option<Vec<String>>,
+) -> std::result::Result<MergeExifResult, String> {
+    merge_exif_data(
+        Path::new(&source_path),
+        Path::new(&target_path),
+        fields.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessedImageResult {
+    output_path: String,
+    width: u32,
+    height: u32,
+    size_bytes: u64,
+}
+
+// Save a processed image to `output_path` (or over the source) keeping the
+// source format. With a config the regular encoder settings apply, otherwise
+// the image is written at full quality.
+fn save_processed_image(
+    img: &image::DynamicImage,
+    source_path: &Path,
+    output_path: Option<&str>,
+    config_json: Option<&str>,
+) -> anyhow::Result<ProcessedImageResult> {
+    let config = processed_image_config(config_json)?;
+    save_processed_image_with(img, source_path, output_path, &config)
+}
+
+fn processed_image_config(config_json: Option<&str>) -> anyhow::Result<CompressionConfig> {
+    Ok(match config_json {
+        Some(json) => CompressionConfig::from_json(json)?,
+        None => CompressionConfig {
+            lossless: true,
+            ..CompressionConfig::default()
+        },
+    })
+}
+
+// Encode in `config.output_format`, or the source format when unset; a
+// format change also changes the output file's extension
+fn save_processed_image_with(
+    img: &image::DynamicImage,
+    source_path: &Path,
+    output_path: Option<&str>,
+    config: &CompressionConfig,
+) -> anyhow::Result<ProcessedImageResult> {
+    let format = match config.output_format.as_deref() {
+        Some(name) => format_from_name(name)?,
+        None => detect_image_format(source_path)?,
+    };
+
+    let final_path = final_output_path(source_path, output_path.map(Path::new), format);
+    let data = encode_image(img, format, config)?;
+    if !config.dry_run {
+        if let Some(parent) = final_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        write_output(config, &final_path, &data)?;
+    }
 
-        let original_path = source_path.unwrap_or(temp_path.as_path());
-        
-        match compress_image(original_path, &config, output_file_path.as_deref(), keep_aspect_ratio) {
-            Ok((original_size, compressed_size)) => {
-                let ratio = if original_size > 0 {
-                    let saved = original_size.saturating_sub(compressed_size);
-                    ((saved as f64 / original_size as f64) * 100.0) as u32
-                } else {
-                    0
-                };
-                println!(
-                    "✅ 压缩成功: {} -> {} (节省 {}%)",
-                    original_size, compressed_size, ratio
-                );
-                results.push((
-                    file_info.name,
-                    original_size,
-                    compressed_size,
-                    "success".to_string(),
-                    file_info.source_index,
-                ));
-            }
-            Err(e) => {
-                println!("❌ 压缩失败: {}", e);
-                results.push((file_info.name, 0, 0, "compress_failed".to_string(), file_info.source_index));
+    let (width, height) = img.dimensions();
+    Ok(ProcessedImageResult {
+        output_path: final_path.to_string_lossy().to_string(),
+        width,
+        height,
+        size_bytes: data.len() as u64,
+    })
+}
+
+// Edge-aware smoothing: repeated Gaussian blurs blended back per pixel with a
+// weight that falls off on strong Sobel gradients, approximating a bilateral
+// filter at a fraction of the cost.
+fn reduce_noise(img: &image::DynamicImage, strength: f32) -> image::DynamicImage {
+    let strength = strength.clamp(0.0, 1.0);
+    if strength <= 0.0 {
+        return img.clone();
+    }
+
+    let edges = imageproc::gradients::sobel_gradients(&img.to_luma8());
+    let edge_limit = 64.0 + 256.0 * strength;
+    let sigma = 0.6 + 1.4 * strength;
+    let passes = 1 + (strength * 2.0).round() as u32;
+
+    let mut current = img.to_rgba8();
+    for _ in 0..passes {
+        let blurred = imageproc::filter::gaussian_blur_f32(&current, sigma);
+        for (x, y, pixel) in current.enumerate_pixels_mut() {
+            let gradient = edges.get_pixel(x, y).0[0] as f32;
+            let weight = strength * (1.0 - (gradient / edge_limit).min(1.0));
+            let smooth = blurred.get_pixel(x, y);
+            for c in 0..4 {
+                let value = pixel.0[c] as f32 * (1.0 - weight) + smooth.0[c] as f32 * weight;
+                pixel.0[c] = value.round().clamp(0.0, 255.0) as u8;
             }
         }
     }
 
-    // Clean up temp directory after processing
-    std::fs::remove_dir_all(&temp_dir).ok(); // Ignore errors during cleanup
+    image::DynamicImage::ImageRgba8(current)
+}
+
+#[tauri::command]
+async fn apply_noise_reduction(
+    path: String,
+    strength: f32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    let source_path = Path::new(&path);
+
+    // Nothing to do: hand back the original file untouched
+    if strength <= 0.0 && config_json.is_none() {
+        let final_path = output_path.clone().unwrap_or(path.clone());
+        if let Some(out) = &output_path {
+            fs::copy(source_path, out).map_err(|e| e.to_string())?;
+        }
+        let (width, height) = image::image_dimensions(source_path).map_err(|e| e.to_string())?;
+        let size_bytes = fs::metadata(&final_path).map_err(|e| e.to_string())?.len();
+        return Ok(ProcessedImageResult {
+            output_path: final_path,
+            width,
+            height,
+            size_bytes,
+        });
+    }
+
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let denoised = reduce_noise(&img, strength);
+    save_processed_image(&denoised, source_path, output_path.as_deref(), config_json.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProgressEvent {
+    current: u32,
+    total: u32,
+    path: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpriteFrame {
+    path: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpriteResult {
+    sprite_path: String,
+    width: u32,
+    height: u32,
+    frames: Vec<SpriteFrame>,
+}
+
+fn build_sprite(
+    app: &tauri::AppHandle,
+    paths: &[String],
+    output_path: &str,
+    config_json: Option<&str>,
+) -> anyhow::Result<SpriteResult> {
+    if paths.is_empty() {
+        return Err(anyhow!("No images provided"));
+    }
+
+    let mut images = Vec::with_capacity(paths.len());
+    for path in paths {
+        images.push((path.clone(), image::open(path)?.to_rgba8()));
+    }
+
+    // Greedy shelf packing: tallest first, left-to-right, and a new shelf
+    // whenever the next image would overflow the row width
+    images.sort_by(|a, b| b.1.height().cmp(&a.1.height()));
+    let total_area: u64 = images
+        .iter()
+        .map(|(_, img)| img.width() as u64 * img.height() as u64)
+        .sum();
+    let widest = images.iter().map(|(_, img)| img.width()).max().unwrap_or(1);
+    let row_width = widest.max((total_area as f64).sqrt().ceil() as u32);
+
+    let mut frames = Vec::with_capacity(images.len());
+    let (mut x, mut y, mut shelf_height, mut sprite_width) = (0u32, 0u32, 0u32, 0u32);
+    for (path, img) in &images {
+        if x > 0 && x + img.width() > row_width {
+            y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+        frames.push(SpriteFrame {
+            path: path.clone(),
+            x,
+            y,
+            width: img.width(),
+            height: img.height(),
+        });
+        x += img.width();
+        shelf_height = shelf_height.max(img.height());
+        sprite_width = sprite_width.max(x);
+    }
+    let sprite_height = y + shelf_height;
+
+    let total = images.len() as u32;
+    let mut canvas = image::RgbaImage::new(sprite_width, sprite_height);
+    for (index, ((_, img), frame)) in images.iter().zip(&frames).enumerate() {
+        image::imageops::overlay(&mut canvas, img, frame.x as i64, frame.y as i64);
+        let _ = app.emit(
+            "sprite-progress",
+            ProgressEvent {
+                current: index as u32 + 1,
+                total,
+                path: frame.path.clone(),
+            },
+        );
+    }
+
+    // The output path doubles as the source so its extension picks the format
+    let sprite = image::DynamicImage::ImageRgba8(canvas);
+    let saved = save_processed_image(&sprite, Path::new(output_path), None, config_json)?;
+
+    Ok(SpriteResult {
+        sprite_path: saved.output_path,
+        width: sprite_width,
+        height: sprite_height,
+        frames,
+    })
+}
+
+#[tauri::command]
+async fn create_image_sprite(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    output_path: String,
+    config_json: Option<String>,
+) -> std::result::Result<SpriteResult, String> {
+    build_sprite(&app, &paths, &output_path, config_json.as_deref()).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExifThumbnailResult {
+    data_base64: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    size_bytes: u32,
+}
+
+// Locate the JPEG thumbnail referenced by IFD1. Offsets are relative to the
+// start of the TIFF header, which is where the raw EXIF block begins.
+fn read_exif_thumbnail(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    if detect_image_format(path)? != ImageFormat::Jpeg {
+        return Err(anyhow!("EXIF thumbnails are only supported for JPEG files"));
+    }
+
+    let jpeg = img_parts::jpeg::Jpeg::from_bytes(fs::read(path)?.into())?;
+    let raw_exif = match jpeg.exif() {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+
+    let exif = exif::Reader::new().read_raw(raw_exif.to_vec())?;
+    let offset = exif
+        .get_field(exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL)
+        .and_then(|field| field.value.get_uint(0));
+    let length = exif
+        .get_field(exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL)
+        .and_then(|field| field.value.get_uint(0));
+
+    Ok(match (offset, length) {
+        (Some(offset), Some(length)) => raw_exif
+            .get(offset as usize..offset as usize + length as usize)
+            .map(|thumbnail| thumbnail.to_vec()),
+        _ => None,
+    })
+}
+
+#[tauri::command]
+async fn get_exif_thumbnail(path: String) -> std::result::Result<ExifThumbnailResult, String> {
+    let thumbnail = read_exif_thumbnail(Path::new(&path)).map_err(|e| e.to_string())?;
+
+    Ok(match thumbnail {
+        Some(bytes) => {
+            let dimensions = image::load_from_memory_with_format(&bytes, ImageFormat::Jpeg)
+                .ok()
+                .map(|img| img.dimensions());
+            ExifThumbnailResult {
+                data_base64: Some(base64::engine::general_purpose::STANDARD.encode(&bytes)),
+                width: dimensions.map(|(w, _)| w),
+                height: dimensions.map(|(_, h)| h),
+                size_bytes: bytes.len() as u32,
+            }
+        }
+        None => ExifThumbnailResult {
+            data_base64: None,
+            width: None,
+            height: None,
+            size_bytes: 0,
+        },
+    })
+}
+
+const DEFAULT_DATA_URI_MAX_BYTES: u64 = 2 * 1024 * 1024;
+
+#[tauri::command]
+async fn compress_image_to_data_uri(
+    path: String,
+    config_json: String,
+    data_uri_max_bytes: Option<u64>,
+) -> std::result::Result<String, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let compressed =
+        compress_to_memory(Path::new(&path), &config, false).map_err(|e| e.to_string())?;
+
+    let max_bytes = data_uri_max_bytes.unwrap_or(DEFAULT_DATA_URI_MAX_BYTES);
+    if compressed.data.len() as u64 > max_bytes {
+        return Err("too_large_for_data_uri".to_string());
+    }
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime_type(compressed.format),
+        base64::engine::general_purpose::STANDARD.encode(&compressed.data)
+    ))
+}
+
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CompressionResult {
+    path: String,
+    output_path: Option<String>,
+    original_size: u64,
+    compressed_size: u64,
+    status: String,
+    selected_format: Option<String>,
+    final_quality: Option<u8>,
+    mime_type: Option<String>,
+    exif_orientation: Option<u8>,
+    was_auto_rotated: bool,
+}
+
+// Output path for a converted file: `output_dir/{stem}.{ext}`, or next to the
+// source when no directory is given
+fn converted_output_path(
+    source: &Path,
+    output_dir: Option<&str>,
+    format: ImageFormat,
+) -> std::path::PathBuf {
+    match output_dir {
+        Some(dir) => {
+            let stem = source
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "image".to_string());
+            Path::new(dir).join(format!(
+                "{}.{}",
+                sanitize_filename(&stem),
+                format_extension(format)
+            ))
+        }
+        None => final_output_path(source, None, format),
+    }
+}
+
+fn compress_best_format(
+    path: &Path,
+    quality: u8,
+    max_size_bytes: Option<u64>,
+    output_dir: Option<&str>,
+) -> anyhow::Result<CompressionResult> {
+    // Decode once; every candidate encodes from these pixels
+    let img = image::open(path)?;
+    // JPEG would flatten transparency, so only offer it for opaque images
+    let has_alpha = img.color().has_alpha();
+
+    let mut candidates = Vec::new();
+    for format in [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP] {
+        if format == ImageFormat::Jpeg && has_alpha {
+            continue;
+        }
+        let config = CompressionConfig {
+            quality_jpg: quality,
+            quality_webp: quality,
+            quality_png: quality,
+            output_format: Some(format_name(format).to_string()),
+            ..CompressionConfig::default()
+        };
+        candidates.push(compress_to_memory_with(path, &config, false, Some(img.clone()))?);
+    }
+
+    let original_size = candidates.first().map(|c| c.original_size).unwrap_or(0);
+    let best = candidates
+        .into_iter()
+        .filter(|c| max_size_bytes.is_none_or(|max| c.data.len() as u64 <= max))
+        .min_by_key(|c| c.data.len());
+
+    let best = match best {
+        Some(best) => best,
+        None => {
+            return Ok(CompressionResult {
+                path: path.to_string_lossy().to_string(),
+                original_size,
+                status: "exceeds_max_size".to_string(),
+                ..CompressionResult::default()
+            })
+        }
+    };
+
+    let output_path = converted_output_path(path, output_dir, best.format);
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Without an output dir a same-format winner replaces the source
+    write_atomic(&CompressionConfig::default(), &output_path, &best.data)?;
+
+    Ok(CompressionResult {
+        path: path.to_string_lossy().to_string(),
+        output_path: Some(output_path.to_string_lossy().to_string()),
+        original_size,
+        compressed_size: best.data.len() as u64,
+        status: "success".to_string(),
+        exif_orientation: best.exif_orientation,
+        was_auto_rotated: best.was_auto_rotated,
+        selected_format: Some(format_name(best.format).to_string()),
+        ..CompressionResult::default()
+    })
+}
+
+#[tauri::command]
+async fn compress_with_best_format(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    quality: u8,
+    max_size_bytes: Option<u64>,
+    output_dir: Option<String>,
+) -> std::result::Result<Vec<CompressionResult>, String> {
+    let total = paths.len() as u32;
+    let mut results = Vec::with_capacity(paths.len());
+
+    for (index, path_str) in paths.into_iter().enumerate() {
+        let result = match compress_best_format(
+            Path::new(&path_str),
+            quality,
+            max_size_bytes,
+            output_dir.as_deref(),
+        ) {
+            Ok(result) => result,
+            Err(e) => CompressionResult {
+                path: path_str.clone(),
+                status: format!("error: {}", e),
+                ..CompressionResult::default()
+            },
+        };
+        results.push(result);
+        let _ = app.emit(
+            "compression-progress",
+            ProgressEvent {
+                current: index as u32 + 1,
+                total,
+                path: path_str,
+            },
+        );
+    }
 
-    println!("📦 后端返回压缩结果: {:?}", results);
     Ok(results)
 }
 
-fn main() {
-    tauri::Builder::default()
-        .setup(|app| {
-            #[cfg(debug_assertions)]
-            {
-                app.get_webview_window("main").unwrap().open_devtools();
+const SIZE_TARGET_MAX_ITERATIONS: u32 = 12;
+
+// Default size-target output: `{stem}_compressed.{ext}` next to the source,
+// so the original is never written over
+fn size_target_output_path(source: &Path, format: ImageFormat) -> std::path::PathBuf {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+    source.with_file_name(format!("{}_compressed.{}", stem, format_extension(format)))
+}
+
+// EXIF orientation of a JPEG file, for results built without compress_to_memory
+fn jpeg_orientation(path: &Path) -> Option<u8> {
+    if detect_image_format(path).ok()? != ImageFormat::Jpeg {
+        return None;
+    }
+    let jpeg = img_parts::jpeg::Jpeg::from_bytes(fs::read(path).ok()?.into()).ok()?;
+    jpeg.exif().as_deref().and_then(read_exif_orientation)
+}
+
+// Bisect quality for the highest quality whose output fits within
+// `tolerance_percent` above the target; the search runs to the end rather
+// than stopping at the first fit. PNG size is not monotonic in quality
+// (palette quantisation is non-linear), so PNG results are a best effort
+// rather than the true optimum. Nothing is written; the result carries
+// the encoded bytes and the path they are meant for.
+fn encode_to_size_target(
+    path: &Path,
+    target_bytes: u64,
+    tolerance_percent: f64,
+    format: ImageFormat,
+    output_path: Option<&Path>,
+) -> anyhow::Result<(CompressionResult, Vec<u8>)> {
+    let original_size = fs::metadata(path)?.len();
+    // Read before writing: an explicit output path may be the source itself
+    let exif_orientation = jpeg_orientation(path);
+    let img = image::open(path)?;
+
+    let tolerance = tolerance_percent.max(0.0) / 100.0;
+    let upper = (target_bytes as f64 * (1.0 + tolerance)) as u64;
+
+    let (mut low, mut high) = (1u8, 100u8);
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    let mut smallest: Option<(u8, Vec<u8>)> = None;
+
+    for _ in 0..SIZE_TARGET_MAX_ITERATIONS {
+        if low > high {
+            break;
+        }
+        let quality = ((low as u16 + high as u16) / 2) as u8;
+        let data = encode_at_quality(&img, format, quality)?;
+        let size = data.len() as u64;
+
+        if size <= upper {
+            if best.as_ref().is_none_or(|(q, _)| quality > *q) {
+                best = Some((quality, data));
+            }
+            low = quality + 1;
+        } else {
+            if smallest.as_ref().is_none_or(|(_, d)| data.len() < d.len()) {
+                smallest = Some((quality, data));
+            }
+            if quality == 1 {
+                break;
+            }
+            high = quality - 1;
+        }
+    }
+
+    let (status, (final_quality, data)) = match (best, smallest) {
+        (Some(best), _) => ("success", best),
+        (None, Some(smallest)) => ("target_not_reached", smallest),
+        (None, None) => return Err(anyhow!("No quality level could be encoded")),
+    };
+
+    let output_path = match output_path {
+        Some(output_path) => output_path.to_path_buf(),
+        None => size_target_output_path(path, format),
+    };
+
+    let result = CompressionResult {
+        path: path.to_string_lossy().to_string(),
+        output_path: Some(output_path.to_string_lossy().to_string()),
+        original_size,
+        compressed_size: data.len() as u64,
+        status: status.to_string(),
+        selected_format: Some(format_name(format).to_string()),
+        final_quality: Some(final_quality),
+        exif_orientation,
+        ..CompressionResult::default()
+    };
+    Ok((result, data))
+}
+
+// `encode_to_size_target`, then write the result unless `dry_run` is set
+fn compress_to_size_target(
+    path: &Path,
+    target_bytes: u64,
+    tolerance_percent: f64,
+    format: ImageFormat,
+    output_path: Option<&Path>,
+    dry_run: bool,
+) -> anyhow::Result<CompressionResult> {
+    let (result, data) =
+        encode_to_size_target(path, target_bytes, tolerance_percent, format, output_path)?;
+    if !dry_run {
+        if let Some(output_path) = &result.output_path {
+            fs::write(output_path, &data)?;
+        }
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+async fn compress_with_size_target(
+    path: String,
+    target_bytes: u64,
+    tolerance_percent: f64,
+    format: String,
+    output_path: Option<String>,
+    dry_run: Option<bool>,
+) -> std::result::Result<CompressionResult, String> {
+    let format = format_from_name(&format).map_err(|e| e.to_string())?;
+    compress_to_size_target(
+        Path::new(&path),
+        target_bytes,
+        tolerance_percent,
+        format,
+        output_path.as_deref().map(Path::new),
+        dry_run.unwrap_or(false),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Walk the top-level chunks of a RIFF/WebP file as (fourcc, payload)
+fn riff_chunks(bytes: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return chunks;
+    }
+
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let mut fourcc = [0u8; 4];
+        fourcc.copy_from_slice(&bytes[offset..offset + 4]);
+        let size = u32::from_le_bytes([
+            bytes[offset + 4],
+            bytes[offset + 5],
+            bytes[offset + 6],
+            bytes[offset + 7],
+        ]) as usize;
+        let start = offset + 8;
+        chunks.push((fourcc, &bytes[start..(start + size).min(bytes.len())]));
+        // Payloads are padded to an even length
+        offset += 8 + size + (size & 1);
+    }
+    chunks
+}
+
+// Bytes taken by metadata: JPEG APPn/COM segments, PNG ancillary chunks and
+// WebP EXIF/XMP/ICC chunks, each including its header
+fn metadata_size(bytes: &[u8], format: ImageFormat) -> anyhow::Result<u64> {
+    Ok(match format {
+        ImageFormat::Jpeg => {
+            let jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.to_vec().into())?;
+            jpeg.segments()
+                .iter()
+                .filter(|segment| {
+                    let marker = segment.marker();
+                    (0xE0..=0xEF).contains(&marker) || marker == img_parts::jpeg::markers::COM
+                })
+                .map(|segment| segment.contents().len() as u64 + 4)
+                .sum()
+        }
+        ImageFormat::Png => {
+            let png = img_parts::png::Png::from_bytes(bytes.to_vec().into())?;
+            png.chunks()
+                .iter()
+                .filter(|chunk| chunk.kind()[0].is_ascii_lowercase())
+                .map(|chunk| chunk.contents().len() as u64 + 12)
+                .sum()
+        }
+        ImageFormat::WebP => riff_chunks(bytes)
+            .iter()
+            .filter(|(fourcc, _)| matches!(fourcc, b"EXIF" | b"XMP " | b"ICCP"))
+            .map(|(_, payload)| payload.len() as u64 + 8)
+            .sum(),
+        _ => 0,
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WipeResult {
+    stripped_bytes: u64,
+    original_metadata_size: u64,
+}
+
+fn wipe_image_metadata(path: &Path, output_path: Option<&str>) -> anyhow::Result<WipeResult> {
+    let format = detect_image_format(path)?;
+    let original = fs::read(path)?;
+    let original_metadata_size = metadata_size(&original, format)?;
+
+    let wiped = match format {
+        // oxipng works on the original stream, so pixels stay bit-exact
+        ImageFormat::Png => {
+            let mut options = oxipng::Options::from_preset(2);
+            options.strip = oxipng::StripChunks::All;
+            oxipng::optimize_from_memory(&original, &options)?
+        }
+        // Fresh encodes carry no APP markers or EXIF chunks
+        _ => {
+            let img = image::load_from_memory_with_format(&original, format)?;
+            let config = CompressionConfig {
+                lossless: true,
+                preserve_exif: false,
+                ..CompressionConfig::default()
+            };
+            encode_image(&img, format, &config)?
+        }
+    };
+
+    let final_path = output_path.map(Path::new).unwrap_or(path);
+    if let Some(parent) = final_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&CompressionConfig::default(), final_path, &wiped)?;
+
+    Ok(WipeResult {
+        stripped_bytes: (original.len() as u64).saturating_sub(wiped.len() as u64),
+        original_metadata_size,
+    })
+}
+
+#[tauri::command]
+async fn wipe_metadata(
+    path: String,
+    output_path: Option<String>,
+) -> std::result::Result<WipeResult, String> {
+    wipe_image_metadata(Path::new(&path), output_path.as_deref()).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnimatedWebPResult {
+    frame_count: u32,
+    original_size: u64,
+    compressed_size: u64,
+    duration_ms: u64,
+}
+
+fn recompress_animated_webp(
+    path: &Path,
+    quality: u8,
+    method: u8,
+    output_path: Option<&str>,
+) -> anyhow::Result<AnimatedWebPResult> {
+    let original = fs::read(path)?;
+    let decoded = webp::AnimDecoder::new(&original)
+        .decode()
+        .map_err(|e| anyhow!("Animated WebP decode failed: {}", e))?;
+    // Frames must outlive the encoder, which borrows their pixels until encode()
+    let frames: Vec<_> = (0..decoded.len())
+        .filter_map(|index| decoded.get_frame(index))
+        .collect();
+    let first = frames
+        .first()
+        .ok_or(anyhow!("Animated WebP has no frames"))?;
+    let (width, height) = (first.width(), first.height());
+
+    let mut webp_config =
+        webp::WebPConfig::new().map_err(|_| anyhow!("Failed to create WebP config"))?;
+    webp_config.quality = quality.min(100) as f32;
+    webp_config.method = method.min(6) as i32;
+
+    let mut encoder = webp::AnimEncoder::new(width, height, &webp_config);
+    encoder.set_loop_count(decoded.loop_count as i32);
+
+    // The decoder reports when each frame ends, the encoder wants when it starts
+    let mut start_ms = 0;
+    let frame_count = frames.len();
+    for frame in &frames {
+        let end_ms = frame.get_time_ms();
+        encoder.add_frame(webp::AnimFrame::from_rgba(frame, width, height, start_ms));
+        start_ms = end_ms;
+    }
+    let encoded = encoder.encode();
+
+    let final_path = output_path.map(Path::new).unwrap_or(path);
+    if let Some(parent) = final_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&CompressionConfig::default(), final_path, &*encoded)?;
+
+    Ok(AnimatedWebPResult {
+        frame_count: frame_count as u32,
+        original_size: original.len() as u64,
+        compressed_size: encoded.len() as u64,
+        duration_ms: start_ms.max(0) as u64,
+    })
+}
+
+#[tauri::command]
+async fn compress_animated_webp(
+    path: String,
+    quality: u8,
+    method: u8,
+    output_path: Option<String>,
+) -> std::result::Result<AnimatedWebPResult, String> {
+    recompress_animated_webp(Path::new(&path), quality, method, output_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryEstimate {
+    decode_bytes: u64,
+    processing_bytes: u64,
+    encode_bytes: u64,
+    total_bytes: u64,
+    fits_in_available_ram: bool,
+}
+
+fn estimate_memory(path: &Path, config: &CompressionConfig) -> anyhow::Result<MemoryEstimate> {
+    let (width, height) = image::image_dimensions(path)?;
+    let format = match config.output_format.as_deref() {
+        Some(name) => format_from_name(name)?,
+        None => detect_image_format(path)?,
+    };
+
+    let decode_bytes = width as u64 * height as u64 * 4;
+    // Resized copy lives alongside the decoded one
+    let processing_bytes = decode_bytes;
+    let encode_bytes = match format {
+        ImageFormat::Png => decode_bytes * 2,
+        ImageFormat::Jpeg => decode_bytes / 4,
+        _ => decode_bytes,
+    };
+    let total_bytes = decode_bytes + processing_bytes + encode_bytes;
+
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+
+    Ok(MemoryEstimate {
+        decode_bytes,
+        processing_bytes,
+        encode_bytes,
+        total_bytes,
+        fits_in_available_ram: total_bytes <= system.available_memory(),
+    })
+}
+
+#[tauri::command]
+async fn estimate_memory_usage(
+    path: String,
+    config_json: String,
+) -> std::result::Result<MemoryEstimate, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    estimate_memory(Path::new(&path), &config).map_err(|e| e.to_string())
+}
+
+const DEFAULT_KEPT_PNG_CHUNKS: &[&str] = &["sRGB", "cHRM", "gAMA"];
+// Kept whatever the allow-list says: the palette, and the transparency that
+// goes with it or with a colour key. Dropping tRNS changes decoded pixels.
+const REQUIRED_PNG_CHUNKS: &[&str] = &["PLTE", "tRNS"];
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkStripResult {
+    chunks_kept: Vec<String>,
+    chunks_removed: Vec<String>,
+    original_size: u64,
+    output_size: u64,
+}
+
+fn strip_png_chunks(
+    path: &Path,
+    keep_chunks: &[String],
+    output_path: Option<&str>,
+) -> anyhow::Result<ChunkStripResult> {
+    if detect_image_format(path)? != ImageFormat::Png {
+        return Err(anyhow!("Chunk stripping is only supported for PNG"));
+    }
+    let original = fs::read(path)?;
+    let mut png = img_parts::png::Png::from_bytes(original.clone().into())?;
+
+    let mut chunks_kept: Vec<String> = Vec::new();
+    let mut chunks_removed: Vec<String> = Vec::new();
+    png.chunks_mut().retain(|chunk| {
+        let kind = chunk.kind();
+        let name = String::from_utf8_lossy(&kind).into_owned();
+        // Critical chunks (uppercase first letter: IHDR, PLTE, IDAT, IEND)
+        // are required to decode the image, so they always stay
+        let keep = kind[0].is_ascii_uppercase()
+            || REQUIRED_PNG_CHUNKS.contains(&name.as_str())
+            || keep_chunks.iter().any(|k| *k == name);
+        let list = if keep {
+            &mut chunks_kept
+        } else {
+            &mut chunks_removed
+        };
+        if !list.contains(&name) {
+            list.push(name);
+        }
+        keep
+    });
+
+    let mut output = Vec::new();
+    png.encoder().write_to(&mut output)?;
+
+    let final_path = output_path.map(Path::new).unwrap_or(path);
+    if let Some(parent) = final_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&CompressionConfig::default(), final_path, &output)?;
+
+    Ok(ChunkStripResult {
+        chunks_kept,
+        chunks_removed,
+        original_size: original.len() as u64,
+        output_size: output.len() as u64,
+    })
+}
+
+#[tauri::command]
+async fn strip_png_metadata(
+    path: String,
+    keep_chunks: Option<Vec<String>>,
+    output_path: Option<String>,
+) -> std::result::Result<ChunkStripResult, String> {
+    let keep_chunks = keep_chunks.unwrap_or_else(|| {
+        DEFAULT_KEPT_PNG_CHUNKS
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    });
+    strip_png_chunks(Path::new(&path), &keep_chunks, output_path.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+const HISTOGRAM_MAX_DIMENSION: u32 = 4096;
+
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HistogramData {
+    r: Option<Vec<u32>>,
+    g: Option<Vec<u32>>,
+    b: Option<Vec<u32>>,
+    luma: Option<Vec<u32>>,
+}
+
+fn compute_histogram(
+    img: &image::DynamicImage,
+    channels: &[String],
+) -> anyhow::Result<HistogramData> {
+    let mut data = HistogramData::default();
+    for channel in channels {
+        let slot = match channel.to_lowercase().as_str() {
+            "r" => &mut data.r,
+            "g" => &mut data.g,
+            "b" => &mut data.b,
+            "luma" => &mut data.luma,
+            other => return Err(anyhow!("Unknown histogram channel: {}", other)),
+        };
+        *slot = Some(vec![0; 256]);
+    }
+
+    let (width, height) = img.dimensions();
+    let rgba = if width > HISTOGRAM_MAX_DIMENSION || height > HISTOGRAM_MAX_DIMENSION {
+        img.resize(
+            HISTOGRAM_MAX_DIMENSION,
+            HISTOGRAM_MAX_DIMENSION,
+            image::imageops::FilterType::Nearest,
+        )
+        .to_rgba8()
+    } else {
+        img.to_rgba8()
+    };
+
+    for pixel in rgba.pixels() {
+        let [r, g, b, _] = pixel.0;
+        if let Some(counts) = data.r.as_mut() {
+            counts[r as usize] += 1;
+        }
+        if let Some(counts) = data.g.as_mut() {
+            counts[g as usize] += 1;
+        }
+        if let Some(counts) = data.b.as_mut() {
+            counts[b as usize] += 1;
+        }
+        if let Some(counts) = data.luma.as_mut() {
+            // Rec. 709 weights, matching image's own luma conversion
+            let luma = (2126 * r as u32 + 7152 * g as u32 + 722 * b as u32) / 10000;
+            counts[luma as usize] += 1;
+        }
+    }
+
+    Ok(data)
+}
+
+#[tauri::command]
+async fn get_image_histogram(
+    path: String,
+    channels: Vec<String>,
+) -> std::result::Result<HistogramData, String> {
+    let img = image::open(&path).map_err(|e| e.to_string())?;
+    compute_histogram(&img, &channels).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RenameSpec {
+    old_path: String,
+    new_name: String,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RenameResult {
+    old_path: String,
+    new_path: String,
+    status: String,
+}
+
+fn renamed_path(old_path: &Path, new_name: &str) -> std::path::PathBuf {
+    let mut name = sanitize_filename(new_name);
+    if Path::new(&name).extension().is_none() {
+        if let Some(ext) = old_path.extension().and_then(|e| e.to_str()) {
+            name = format!("{}.{}", name, ext);
+        }
+    }
+    old_path.with_file_name(name)
+}
+
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    move_file_with(from, to, |from, to| fs::rename(from, to))
+}
+
+// `rename` is a parameter so tests can stand in a cross-device failure
+fn move_file_with(
+    from: &Path,
+    to: &Path,
+    rename: impl FnOnce(&Path, &Path) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    match rename(from, to) {
+        Ok(()) => Ok(()),
+        // rename cannot cross filesystems, so fall back to copy + remove;
+        // any other failure (permissions, missing source) is final
+        Err(rename_err) if rename_err.kind() == std::io::ErrorKind::CrossesDevices => {
+            if fs::copy(from, to).is_err() {
+                return Err(rename_err);
+            }
+            fs::remove_file(from)
+        }
+        Err(rename_err) => Err(rename_err),
+    }
+}
+
+#[tauri::command]
+async fn batch_rename(
+    files: Vec<RenameSpec>,
+    overwrite: Option<bool>,
+) -> std::result::Result<Vec<RenameResult>, String> {
+    let overwrite = overwrite.unwrap_or(false);
+    let results = files
+        .into_iter()
+        .map(|spec| {
+            let old_path = Path::new(&spec.old_path);
+            let new_path = renamed_path(old_path, &spec.new_name);
+            let status = if new_path == old_path {
+                "renamed".to_string()
+            } else if new_path.exists() && !overwrite {
+                "skipped_exists".to_string()
+            } else {
+                match move_file(old_path, &new_path) {
+                    Ok(()) => "renamed".to_string(),
+                    Err(e) => format!("error: {}", e),
+                }
+            };
+            RenameResult {
+                new_path: new_path.to_string_lossy().to_string(),
+                old_path: spec.old_path,
+                status,
             }
-            Ok(())
         })
-        .plugin(tauri_plugin_fs::init())
-        .plugin(tauri_plugin_dialog::init())
+        .collect();
+    Ok(results)
+}
+
+// The WebP encoders only take RGB(A), so gray pixels headed back into a WebP
+// source are kept in that layout
+fn keep_webp_layout(img: image::DynamicImage, source_path: &Path) -> image::DynamicImage {
+    if is_gray_color_type(img.color())
+        && detect_image_format(source_path).ok() == Some(ImageFormat::WebP)
+    {
+        image::DynamicImage::ImageRgba8(img.to_rgba8())
+    } else {
+        img
+    }
+}
+
+fn transform_and_save(
+    path: &str,
+    output_path: Option<&str>,
+    compress_config_json: Option<&str>,
+    transform: impl FnOnce(image::DynamicImage) -> image::DynamicImage,
+) -> std::result::Result<ProcessedImageResult, String> {
+    let source_path = Path::new(path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let transformed = transform(img);
+    save_processed_image(&transformed, source_path, output_path, compress_config_json)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn invert_image(
+    path: String,
+    output_path: Option<String>,
+    compress_config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        compress_config_json.as_deref(),
+        |mut img| {
+            img.invert();
+            img
+        },
+    )
+}
+
+#[tauri::command]
+async fn grayscale_image(
+    path: String,
+    output_path: Option<String>,
+    compress_config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        compress_config_json.as_deref(),
+        |img| keep_webp_layout(img.grayscale(), Path::new(&path)),
+    )
+}
+
+#[tauri::command]
+async fn flip_horizontal(
+    path: String,
+    output_path: Option<String>,
+    compress_config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        compress_config_json.as_deref(),
+        |img| img.fliph(),
+    )
+}
+
+#[tauri::command]
+async fn flip_vertical(
+    path: String,
+    output_path: Option<String>,
+    compress_config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        compress_config_json.as_deref(),
+        |img| img.flipv(),
+    )
+}
+
+#[tauri::command]
+async fn compress_in_place_with_atomic_write(
+    path: String,
+    config_json: Option<String>,
+    maintain_aspect_ratio: Option<bool>,
+) -> std::result::Result<CompressionResult, String> {
+    let config = match config_json {
+        Some(json) => CompressionConfig::from_json(&json).map_err(|e| e.to_string())?,
+        None => CompressionConfig::default(),
+    };
+
+    let source_path = Path::new(&path);
+    let compressed =
+        compress_to_memory(source_path, &config, maintain_aspect_ratio.unwrap_or(true))
+            .map_err(|e| e.to_string())?;
+    let final_path = final_output_path(source_path, None, compressed.format);
+    if !config.dry_run {
+        write_atomic(&config, &final_path, &compressed.data).map_err(|e| e.to_string())?;
+    }
+
+    Ok(CompressionResult {
+        output_path: Some(final_path.to_string_lossy().to_string()),
+        path,
+        original_size: compressed.original_size,
+        compressed_size: compressed.data.len() as u64,
+        status: "success".to_string(),
+        exif_orientation: compressed.exif_orientation,
+        was_auto_rotated: compressed.was_auto_rotated,
+        ..CompressionResult::default()
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebPInfo {
+    is_animated: bool,
+    frame_count: u32,
+    loop_count: u32,
+    total_duration_ms: u32,
+    is_lossless: bool,
+    canvas_width: u32,
+    canvas_height: u32,
+    has_icc: bool,
+    has_exif: bool,
+    has_xmp: bool,
+}
+
+fn read_u24_le(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16
+}
+
+fn read_webp_info(path: &Path) -> anyhow::Result<WebPInfo> {
+    let bytes = fs::read(path)?;
+    let features = webp::BitstreamFeatures::new(&bytes)
+        .ok_or_else(|| anyhow!("Not a valid WebP file: {}", path.display()))?;
+    let chunks = riff_chunks(&bytes);
+    let has_chunk = |kind: &[u8; 4]| chunks.iter().any(|(fourcc, _)| fourcc == kind);
+
+    // ANIM: background colour (4 bytes) then a 16-bit loop count, 0 = forever
+    let loop_count = chunks
+        .iter()
+        .find(|(fourcc, payload)| fourcc == b"ANIM" && payload.len() >= 6)
+        .map(|(_, payload)| u16::from_le_bytes([payload[4], payload[5]]) as u32)
+        .unwrap_or(0);
+
+    // ANMF: x, y, width-1, height-1, duration (24 bits each), flags, then the
+    // frame's own VP8/VP8L chunk
+    let frames: Vec<&[u8]> = chunks
+        .iter()
+        .filter(|(fourcc, payload)| fourcc == b"ANMF" && payload.len() >= 16)
+        .map(|(_, payload)| *payload)
+        .collect();
+    let total_duration_ms = frames.iter().map(|frame| read_u24_le(&frame[12..15])).sum();
+    let is_lossless = if frames.is_empty() {
+        has_chunk(b"VP8L")
+    } else {
+        frames
+            .iter()
+            .all(|frame| frame.len() >= 20 && &frame[16..20] == b"VP8L")
+    };
+
+    Ok(WebPInfo {
+        is_animated: features.has_animation(),
+        frame_count: (frames.len() as u32).max(1),
+        loop_count,
+        total_duration_ms,
+        is_lossless,
+        canvas_width: features.width(),
+        canvas_height: features.height(),
+        has_icc: has_chunk(b"ICCP"),
+        has_exif: has_chunk(b"EXIF"),
+        has_xmp: has_chunk(b"XMP "),
+    })
+}
+
+#[tauri::command]
+async fn get_webp_info(path: String) -> std::result::Result<WebPInfo, String> {
+    read_webp_info(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+// Output formats acceptable to an HTTP Accept header, best first. Entries
+// are ordered by q value (ties keep header order) and wildcards expand to
+// WebP, JPEG, PNG. Types we cannot write, such as image/avif or the
+// text/html a browser lists first, are skipped; only a header naming no
+// writable image format is rejected.
+fn accepted_formats(accept: &str) -> anyhow::Result<Vec<ImageFormat>> {
+    let mut entries = Vec::new();
+    for (position, entry) in accept
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .enumerate()
+    {
+        let mut parts = entry.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or("").to_lowercase();
+        if !media_type.contains('/') {
+            return Err(anyhow!("invalid_accept: malformed media type '{}'", entry));
+        }
+        let mut q = 1.0f32;
+        for param in parts {
+            if let Some(value) = param.strip_prefix("q=") {
+                q = value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid_accept: bad q value in '{}'", entry))?;
+            }
+        }
+        if q > 0.0 {
+            entries.push((q, position, media_type));
+        }
+    }
+    entries.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut formats = Vec::new();
+    for (_, _, media_type) in entries {
+        let matched: &[ImageFormat] = match media_type.as_str() {
+            "image/webp" => &[ImageFormat::WebP],
+            "image/jpeg" | "image/jpg" => &[ImageFormat::Jpeg],
+            "image/png" => &[ImageFormat::Png],
+            "image/*" | "*/*" => &[ImageFormat::WebP, ImageFormat::Jpeg, ImageFormat::Png],
+            _ => &[],
+        };
+        for format in matched {
+            if !formats.contains(format) {
+                formats.push(*format);
+            }
+        }
+    }
+    if formats.is_empty() {
+        return Err(anyhow!(
+            "no_supported_format: no writable image format in '{}'",
+            accept
+        ));
+    }
+    Ok(formats)
+}
+
+fn compress_accepted(
+    path: &Path,
+    accept: &str,
+    quality: u8,
+    output_dir: &str,
+) -> anyhow::Result<CompressionResult> {
+    let source_format = detect_image_format(path)?;
+    // Same rule as best-format selection: JPEG would flatten transparency
+    let has_alpha = source_format != ImageFormat::Jpeg && image::open(path)?.color().has_alpha();
+    let format = accepted_formats(accept)?
+        .into_iter()
+        .find(|format| !(has_alpha && *format == ImageFormat::Jpeg))
+        .ok_or_else(|| anyhow!("no_supported_format: nothing usable in '{}'", accept))?;
+
+    let config = CompressionConfig {
+        quality_jpg: quality,
+        quality_webp: quality,
+        quality_png: quality,
+        output_format: Some(format_name(format).to_string()),
+        ..CompressionConfig::default()
+    };
+    let compressed = compress_to_memory(path, &config, false)?;
+
+    let output_path = converted_output_path(path, Some(output_dir), compressed.format);
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&output_path, &compressed.data)?;
+
+    Ok(CompressionResult {
+        path: path.to_string_lossy().to_string(),
+        output_path: Some(output_path.to_string_lossy().to_string()),
+        original_size: compressed.original_size,
+        compressed_size: compressed.data.len() as u64,
+        status: "success".to_string(),
+        exif_orientation: compressed.exif_orientation,
+        was_auto_rotated: compressed.was_auto_rotated,
+        selected_format: Some(format_name(compressed.format).to_string()),
+        mime_type: Some(mime_type(compressed.format).to_string()),
+        ..CompressionResult::default()
+    })
+}
+
+#[tauri::command]
+async fn compress_for_accept(
+    path: String,
+    accept: String,
+    quality: u8,
+    output_dir: String,
+) -> std::result::Result<CompressionResult, String> {
+    compress_accepted(Path::new(&path), &accept, quality, &output_dir).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PngChunkInfo {
+    chunk_type: String,
+    size_bytes: u32,
+    is_critical: bool,
+    is_safe_to_copy: bool,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PngChunksReport {
+    chunks: Vec<PngChunkInfo>,
+    chunk_count: u32,
+    total_size: u64,
+}
+
+fn list_png_chunks(path: &Path) -> anyhow::Result<PngChunksReport> {
+    let bytes = fs::read(path)?;
+    let total_size = bytes.len() as u64;
+    let png = img_parts::png::Png::from_bytes(bytes.into())?;
+
+    // Property bits live in the case of the type letters: uppercase first
+    // letter = critical, lowercase fourth letter = safe to copy
+    let chunks: Vec<PngChunkInfo> = png
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            let kind = chunk.kind();
+            PngChunkInfo {
+                chunk_type: String::from_utf8_lossy(&kind).into_owned(),
+                size_bytes: chunk.contents().len() as u32,
+                is_critical: kind[0].is_ascii_uppercase(),
+                is_safe_to_copy: kind[3].is_ascii_lowercase(),
+            }
+        })
+        .collect();
+
+    Ok(PngChunksReport {
+        chunk_count: chunks.len() as u32,
+        chunks,
+        total_size,
+    })
+}
+
+#[tauri::command]
+async fn get_png_chunks(path: String) -> std::result::Result<PngChunksReport, String> {
+    list_png_chunks(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportResult {
+    rows_written: u32,
+    file_size: u64,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Share of the original size saved, as shown in the report
+fn saved_percent(result: &CompressionResult) -> f64 {
+    if result.original_size == 0 {
+        return 0.0;
+    }
+    let saved = result.original_size.saturating_sub(result.compressed_size);
+    saved as f64 / result.original_size as f64 * 100.0
+}
+
+const HTML_REPORT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Ximage compression report</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }
+th { background: #f0f0f0; }
+td.num { text-align: right; }
+</style>
+</head>
+<body>
+<h1>Ximage compression report</h1>
+<table>
+<tr><th>Path</th><th>Original size</th><th>Compressed size</th><th>Ratio</th><th>Status</th></tr>
+{rows}</table>
+</body>
+</html>
+"#;
+
+fn render_report(results: &[CompressionResult], format: &str) -> anyhow::Result<String> {
+    match format.to_lowercase().as_str() {
+        "csv" => {
+            let mut csv = String::from("path,original_size,compressed_size,ratio,status\n");
+            for result in results {
+                csv.push_str(&format!(
+                    "{},{},{},{:.1}%,{}\n",
+                    csv_field(&result.path),
+                    result.original_size,
+                    result.compressed_size,
+                    saved_percent(result),
+                    csv_field(&result.status)
+                ));
+            }
+            Ok(csv)
+        }
+        "json" => Ok(serde_json::to_string_pretty(results)?),
+        "html" => {
+            let rows: String = results
+                .iter()
+                .map(|result| {
+                    format!(
+                        "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}%</td><td>{}</td></tr>\n",
+                        html_escape(&result.path),
+                        result.original_size,
+                        result.compressed_size,
+                        saved_percent(result),
+                        html_escape(&result.status)
+                    )
+                })
+                .collect();
+            Ok(HTML_REPORT_TEMPLATE.replace("{rows}", &rows))
+        }
+        other => Err(anyhow!("Unsupported report format: {}", other)),
+    }
+}
+
+#[tauri::command]
+async fn export_batch_report(
+    results_json: String,
+    format: String,
+    output_path: String,
+) -> std::result::Result<ReportResult, String> {
+    let results: Vec<CompressionResult> =
+        serde_json::from_str(&results_json).map_err(|e| format!("Invalid results: {}", e))?;
+    let report = render_report(&results, &format).map_err(|e| e.to_string())?;
+
+    let path = Path::new(&output_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, &report).map_err(|e| e.to_string())?;
+
+    Ok(ReportResult {
+        rows_written: results.len() as u32,
+        file_size: report.len() as u64,
+    })
+}
+
+const RECURSIVE_BATCH_SIZE: usize = 50;
+
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecursiveCompressionResult {
+    processed: u32,
+    skipped: u32,
+    errors: u32,
+    total_original_bytes: u64,
+    total_compressed_bytes: u64,
+}
+
+// Formats accepted both for uploaded files and when walking directories
+#[cfg(feature = "heif")]
+const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "heic", "heif"];
+#[cfg(not(feature = "heif"))]
+const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+fn is_compressible_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(ext))
+        })
+}
+
+#[tauri::command]
+async fn compress_recursive_directory(
+    app: tauri::AppHandle,
+    root: String,
+    config_json: String,
+    max_depth: Option<u32>,
+    follow_symlinks: Option<bool>,
+) -> std::result::Result<RecursiveCompressionResult, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let root_path = fs::canonicalize(&root).map_err(|e| e.to_string())?;
+    // Refuse to rewrite a whole filesystem ("/", "C:\") in one go
+    if root_path.parent().is_none() {
+        return Err(format!("Refusing to compress filesystem root: {}", root));
+    }
+
+    let mut walker =
+        walkdir::WalkDir::new(&root_path).follow_links(follow_symlinks.unwrap_or(false));
+    if let Some(depth) = max_depth {
+        walker = walker.max_depth(depth as usize);
+    }
+
+    let mut summary = RecursiveCompressionResult::default();
+    let mut images = Vec::new();
+    for entry in walker {
+        match entry {
+            Ok(entry) if entry.file_type().is_file() => {
+                if is_compressible_path(entry.path()) {
+                    images.push(entry.into_path());
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Skipping unreadable entry: {}", e);
+                summary.errors += 1;
+            }
+        }
+    }
+
+    let total = images.len() as u32;
+    let mut current = 0;
+    for batch in images.chunks(RECURSIVE_BATCH_SIZE) {
+        let outcomes = run_batch(batch.to_vec(), &BatchLimits::default(), |path| {
+            let outcome = compress_image(&path, &config, None, true);
+            (path, outcome)
+        })
+        .map_err(|e| e.to_string())?;
+
+        for (path, outcome) in outcomes {
+            match outcome {
+                Ok((original_size, compressed_size)) => {
+                    summary.processed += 1;
+                    summary.total_original_bytes += original_size;
+                    summary.total_compressed_bytes += compressed_size;
+                }
+                Err(e) => {
+                    log::warn!("Failed to compress {}: {}", path.display(), e);
+                    summary.errors += 1;
+                }
+            }
+            current += 1;
+            let _ = app.emit(
+                "compression-progress",
+                ProgressEvent {
+                    current,
+                    total,
+                    path: path.to_string_lossy().to_string(),
+                },
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SimilarImage {
+    path: String,
+    similarity: f64,
+    hash_distance: u32,
+}
+
+const SIMILARITY_PROGRESS_INTERVAL: usize = 100;
+
+// Default `threshold`: re-encodes and light edits of the same picture score
+// above it. Gradient hashes are not rotation invariant, so a rotated copy
+// scores around 0.5 and is treated as a different image.
+const NEAR_DUPLICATE_SIMILARITY: f64 = 0.9;
+
+// Search paths whose hash is at least `threshold` similar to the reference,
+// most similar first. `progress` gets each path with its 1-based position.
+fn find_similar_images(
+    reference_path: &str,
+    search_paths: Vec<String>,
+    threshold: f64,
+    top_n: Option<usize>,
+    mut progress: impl FnMut(usize, String),
+) -> anyhow::Result<Vec<SimilarImage>> {
+    let hasher = image_hasher::HasherConfig::new()
+        .hash_alg(image_hasher::HashAlg::DoubleGradient)
+        .to_hasher();
+    let reference = image::open(reference_path)?;
+    let reference_hash = hasher.hash_image(&reference);
+    let hash_bits = (reference_hash.as_bytes().len() * 8).max(1) as f64;
+
+    let mut matches = Vec::new();
+    for (index, path) in search_paths.into_iter().enumerate() {
+        // Non-image files are expected in mixed folders; just leave them out
+        if let Ok(img) = image::open(&path) {
+            let hash_distance = reference_hash.dist(&hasher.hash_image(&img));
+            let similarity = 1.0 - hash_distance as f64 / hash_bits;
+            if similarity >= threshold {
+                matches.push(SimilarImage {
+                    path: path.clone(),
+                    similarity,
+                    hash_distance,
+                });
+            }
+        }
+        progress(index + 1, path);
+    }
+
+    matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    if let Some(n) = top_n {
+        matches.truncate(n);
+    }
+    Ok(matches)
+}
+
+#[tauri::command]
+async fn get_similar_images(
+    app: tauri::AppHandle,
+    reference_path: String,
+    search_paths: Vec<String>,
+    threshold: Option<f64>,
+    top_n: Option<usize>,
+) -> std::result::Result<Vec<SimilarImage>, String> {
+    let total = search_paths.len() as u32;
+    find_similar_images(
+        &reference_path,
+        search_paths,
+        threshold.unwrap_or(NEAR_DUPLICATE_SIMILARITY),
+        top_n,
+        |done, path| {
+            if done % SIMILARITY_PROGRESS_INTERVAL == 0 || done as u32 == total {
+                let _ = app.emit(
+                    "similarity-progress",
+                    ProgressEvent {
+                        current: done as u32,
+                        total,
+                        path,
+                    },
+                );
+            }
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CropResult {
+    actual_x: u32,
+    actual_y: u32,
+    actual_width: u32,
+    actual_height: u32,
+}
+
+// Image size and MCU size in pixels from the JPEG frame header. The MCU spans
+// 8 pixels times the largest sampling factor in each direction.
+fn jpeg_frame_geometry(jpeg: &img_parts::jpeg::Jpeg) -> anyhow::Result<(u32, u32, u32, u32)> {
+    let frame = jpeg
+        .segments()
+        .iter()
+        .find(|segment| {
+            // SOFn markers, skipping DHT (C4), JPG (C8) and DAC (CC)
+            matches!(
+                segment.marker(),
+                0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF
+            )
+        })
+        .ok_or_else(|| anyhow!("JPEG has no frame header"))?;
+    let contents = frame.contents();
+    if contents.len() < 6 {
+        return Err(anyhow!("Truncated JPEG frame header"));
+    }
+    let height = u16::from_be_bytes([contents[1], contents[2]]) as u32;
+    let width = u16::from_be_bytes([contents[3], contents[4]]) as u32;
+    let components = contents[5] as usize;
+
+    let (mut max_h, mut max_v) = (1u32, 1u32);
+    for component in contents[6..].chunks_exact(3).take(components) {
+        max_h = max_h.max((component[1] >> 4) as u32);
+        max_v = max_v.max((component[1] & 0x0F) as u32);
+    }
+    Ok((width, height, 8 * max_h, 8 * max_v))
+}
+
+fn crop_jpeg_lossless_file(
+    path: &Path,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    output_path: &Path,
+) -> anyhow::Result<CropResult> {
+    if detect_image_format(path)? != ImageFormat::Jpeg {
+        return Err(anyhow!("Lossless crop is only supported for JPEG"));
+    }
+    let original = fs::read(path)?;
+    let jpeg = img_parts::jpeg::Jpeg::from_bytes(original.clone().into())?;
+    let (image_width, image_height, mcu_width, mcu_height) = jpeg_frame_geometry(&jpeg)?;
+    let fits = x
+        .checked_add(width)
+        .is_some_and(|right| right <= image_width)
+        && y.checked_add(height)
+            .is_some_and(|bottom| bottom <= image_height);
+    if width == 0 || height == 0 || !fits {
+        return Err(anyhow!("Crop rectangle is outside the image"));
+    }
+
+    // Blocks cannot be split without re-encoding, so widen the rectangle to
+    // MCU boundaries; the right and bottom edges may end at the image edge
+    let actual_x = x - x % mcu_width;
+    let actual_y = y - y % mcu_height;
+    let right = (x + width).div_ceil(mcu_width) * mcu_width;
+    let bottom = (y + height).div_ceil(mcu_height) * mcu_height;
+    let actual_width = right.min(image_width) - actual_x;
+    let actual_height = bottom.min(image_height) - actual_y;
+
+    let transform = turbojpeg::Transform {
+        crop: Some(turbojpeg::TransformCrop {
+            x: actual_x as usize,
+            y: actual_y as usize,
+            width: Some(actual_width as usize),
+            height: Some(actual_height as usize),
+        }),
+        ..turbojpeg::Transform::default()
+    };
+    let cropped = turbojpeg::transform(&transform, &original)?;
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output_path, &*cropped)?;
+
+    Ok(CropResult {
+        actual_x,
+        actual_y,
+        actual_width,
+        actual_height,
+    })
+}
+
+#[tauri::command]
+async fn crop_jpeg_lossless(
+    path: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    output_path: String,
+) -> std::result::Result<CropResult, String> {
+    crop_jpeg_lossless_file(
+        Path::new(&path),
+        x,
+        y,
+        width,
+        height,
+        Path::new(&output_path),
+    )
+    .map_err(|e| e.to_string())
+}
+
+const UPLOAD_SESSION_TTL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+const MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+// Live sessions each buffer up to MAX_UPLOAD_BYTES, so cap how many there are
+const MAX_UPLOAD_SESSIONS: usize = 16;
+
+struct UploadSession {
+    name: String,
+    format: String,
+    total_size: u64,
+    data: Vec<u8>,
+    // Byte ranges `start..end` written so far, in arrival order
+    received: Vec<(u64, u64)>,
+    last_activity: std::time::Instant,
+}
+
+lazy_static::lazy_static! {
+    static ref UPLOAD_SESSIONS: std::sync::Mutex<std::collections::HashMap<String, UploadSession>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+fn upload_sessions() -> std::result::Result<
+    std::sync::MutexGuard<'static, std::collections::HashMap<String, UploadSession>>,
+    String,
+> {
+    UPLOAD_SESSIONS
+        .lock()
+        .map_err(|_| "upload_state_unavailable: a previous upload panicked".to_string())
+}
+
+// Start of the first byte in `0..total` that no range covers, if any
+fn first_missing_byte(ranges: &[(u64, u64)], total: u64) -> Option<u64> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_unstable();
+    let mut covered = 0;
+    for (start, end) in sorted {
+        if start > covered {
+            break;
+        }
+        covered = covered.max(end);
+    }
+    (covered < total).then_some(covered)
+}
+
+// Drop sessions the frontend abandoned mid-upload
+fn expire_upload_sessions(sessions: &mut std::collections::HashMap<String, UploadSession>) {
+    sessions.retain(|_, session| session.last_activity.elapsed() < UPLOAD_SESSION_TTL);
+}
+
+// Add a session unless MAX_UPLOAD_SESSIONS others are still live; restarting
+// an existing id replaces it
+fn insert_upload_session(
+    sessions: &mut std::collections::HashMap<String, UploadSession>,
+    session_id: String,
+    session: UploadSession,
+) -> std::result::Result<(), String> {
+    expire_upload_sessions(sessions);
+    if !sessions.contains_key(&session_id) && sessions.len() >= MAX_UPLOAD_SESSIONS {
+        return Err(format!(
+            "too_many_uploads: at most {} uploads can be in progress",
+            MAX_UPLOAD_SESSIONS
+        ));
+    }
+    sessions.insert(session_id, session);
+    Ok(())
+}
+
+#[tauri::command]
+async fn begin_upload(
+    session_id: String,
+    name: String,
+    format: String,
+    total_size: u64,
+) -> std::result::Result<(), String> {
+    if total_size > MAX_UPLOAD_BYTES {
+        return Err("file_too_large".to_string());
+    }
+    let session = UploadSession {
+        name,
+        format,
+        total_size,
+        data: Vec::with_capacity(total_size as usize),
+        received: Vec::new(),
+        last_activity: std::time::Instant::now(),
+    };
+    insert_upload_session(&mut *upload_sessions()?, session_id, session)
+}
+
+#[tauri::command]
+async fn upload_chunk(
+    session_id: String,
+    chunk_b64: String,
+    offset: u64,
+) -> std::result::Result<(), String> {
+    let chunk = base64::engine::general_purpose::STANDARD
+        .decode(&chunk_b64)
+        .map_err(|e| format!("decode_failed: {}", e))?;
+
+    let mut sessions = upload_sessions()?;
+    expire_upload_sessions(&mut sessions);
+    let session = sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| format!("Unknown or expired upload session: {}", session_id))?;
+
+    let end = match offset.checked_add(chunk.len() as u64) {
+        Some(end) => end,
+        None => return Err(format!("Chunk offset {} is out of range", offset)),
+    };
+    if end > session.total_size {
+        return Err(format!(
+            "Chunk ends at {} past the declared size {}",
+            end, session.total_size
+        ));
+    }
+    // Chunks may arrive out of order, so place each one at its offset
+    if session.data.len() < end as usize {
+        session.data.resize(end as usize, 0);
+    }
+    session.data[offset as usize..end as usize].copy_from_slice(&chunk);
+    session.received.push((offset, end));
+    session.last_activity = std::time::Instant::now();
+    Ok(())
+}
+
+#[tauri::command]
+async fn finalize_upload(
+    session_id: String,
+    config_json: String,
+) -> std::result::Result<CompressionResult, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let session = {
+        let mut sessions = upload_sessions()?;
+        expire_upload_sessions(&mut sessions);
+        sessions
+            .remove(&session_id)
+            .ok_or_else(|| format!("Unknown or expired upload session: {}", session_id))?
+    };
+    if let Some(missing) = first_missing_byte(&session.received, session.total_size) {
+        return Err(format!(
+            "Upload incomplete: no data at byte {} of {}",
+            missing, session.total_size
+        ));
+    }
+
+    let temp_dir = std::env::temp_dir()
+        .join(UPLOAD_TEMP_DIR)
+        .join(Uuid::new_v4().to_string());
+    fs::create_dir_all(&temp_dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    track_temp_dir(&temp_dir);
+    let mut file_name = sanitize_filename(&session.name);
+    if Path::new(&file_name).extension().is_none() {
+        file_name = format!("{}.{}", file_name, session.format.to_lowercase());
+    }
+    let source_path = temp_dir.join(&file_name);
+    fs::write(&source_path, &session.data).map_err(|e| e.to_string())?;
+
+    let compressed = compress_to_memory(&source_path, &config, true).map_err(|e| e.to_string())?;
+    fs::remove_file(&source_path).ok();
+
+    // The compressed file stays in the temp directory for the frontend to pick up
+    let output_path = final_output_path(
+        &source_path,
+        Some(temp_dir.join("compressed").join(&file_name).as_path()),
+        compressed.format,
+    );
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&output_path, &compressed.data).map_err(|e| e.to_string())?;
+
+    Ok(CompressionResult {
+        path: session.name,
+        output_path: Some(output_path.to_string_lossy().to_string()),
+        original_size: compressed.original_size,
+        compressed_size: compressed.data.len() as u64,
+        status: "success".to_string(),
+        exif_orientation: compressed.exif_orientation,
+        was_auto_rotated: compressed.was_auto_rotated,
+        selected_format: Some(format_name(compressed.format).to_string()),
+        mime_type: Some(mime_type(compressed.format).to_string()),
+        ..CompressionResult::default()
+    })
+}
+
+// Annex K luminance quantization table, natural (row-major) order
+const STANDARD_LUMINANCE_TABLE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+// Natural-order index of each coefficient as stored in a DQT segment
+const JPEG_ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// Mean per-entry error above which the tables are treated as non-libjpeg
+const QUALITY_MATCH_TOLERANCE: f64 = 2.0;
+
+// Luminance table (id 0) in natural order from the DQT segments
+fn read_luminance_table(jpeg: &img_parts::jpeg::Jpeg) -> Option<[u16; 64]> {
+    for segment in jpeg.segments().iter().filter(|s| s.marker() == 0xDB) {
+        let contents = segment.contents();
+        let mut offset = 0;
+        while offset < contents.len() {
+            let precision = contents[offset] >> 4;
+            let id = contents[offset] & 0x0F;
+            let entry_size = if precision == 0 { 1 } else { 2 };
+            let end = offset + 1 + 64 * entry_size;
+            if end > contents.len() {
+                break;
+            }
+            if id == 0 {
+                let mut table = [0u16; 64];
+                for (i, &natural) in JPEG_ZIGZAG.iter().enumerate() {
+                    let at = offset + 1 + i * entry_size;
+                    table[natural] = if entry_size == 1 {
+                        contents[at] as u16
+                    } else {
+                        u16::from_be_bytes([contents[at], contents[at + 1]])
+                    };
+                }
+                return Some(table);
+            }
+            offset = end;
+        }
+    }
+    None
+}
+
+// libjpeg's quality to percentage scale mapping (jpeg_quality_scaling)
+fn quality_scale(quality: u8) -> u32 {
+    let quality = quality.clamp(1, 100) as u32;
+    if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    }
+}
+
+fn estimate_jpeg_quality(path: &Path) -> anyhow::Result<u8> {
+    let jpeg = img_parts::jpeg::Jpeg::from_bytes(fs::read(path)?.into())?;
+    let table = read_luminance_table(&jpeg)
+        .ok_or_else(|| anyhow!("JPEG has no luminance quantization table"))?;
+
+    // Closest libjpeg-scaled standard table
+    let (best_quality, best_error) = (1..=100u8)
+        .map(|quality| {
+            let scale = quality_scale(quality);
+            let error: u32 = STANDARD_LUMINANCE_TABLE
+                .iter()
+                .zip(table.iter())
+                .map(|(&standard, &actual)| {
+                    let expected = ((standard as u32 * scale + 50) / 100).clamp(1, 255);
+                    expected.abs_diff(actual as u32)
+                })
+                .sum();
+            (quality, error)
+        })
+        .min_by_key(|&(_, error)| error)
+        .unwrap_or((75, u32::MAX));
+    if best_error as f64 / 64.0 <= QUALITY_MATCH_TOLERANCE {
+        return Ok(best_quality);
+    }
+
+    // Custom tables (other encoders): invert the scaling from the mean ratio
+    let ratio: f64 = STANDARD_LUMINANCE_TABLE
+        .iter()
+        .zip(table.iter())
+        .map(|(&standard, &actual)| actual as f64 / standard as f64)
+        .sum::<f64>()
+        / 64.0;
+    let scale = ratio * 100.0;
+    let quality = if scale <= 100.0 {
+        (200.0 - scale) / 2.0
+    } else {
+        5000.0 / scale
+    };
+    Ok(quality.round().clamp(1.0, 100.0) as u8)
+}
+
+#[tauri::command]
+async fn get_jpeg_quality_estimate(path: String) -> std::result::Result<u8, String> {
+    estimate_jpeg_quality(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+// Unsharp mask: push each pixel away from its Gaussian-blurred value. Pixels
+// whose largest channel difference is under `threshold` are left alone so
+// flat areas and noise are not amplified. Alpha is never sharpened.
+fn unsharp_mask(
+    img: &image::DynamicImage,
+    sigma: f32,
+    amount: f32,
+    threshold: u8,
+) -> image::DynamicImage {
+    let sigma = sigma.clamp(0.5, 5.0);
+    let amount = amount.clamp(0.0, 3.0);
+    if amount <= 0.0 {
+        return img.clone();
+    }
+
+    let mut current = img.to_rgba8();
+    let blurred = imageproc::filter::gaussian_blur_f32(&current, sigma);
+    for (x, y, pixel) in current.enumerate_pixels_mut() {
+        let smooth = blurred.get_pixel(x, y);
+        let difference = (0..3)
+            .map(|c| pixel.0[c].abs_diff(smooth.0[c]))
+            .max()
+            .unwrap_or(0);
+        if difference < threshold {
+            continue;
+        }
+        for c in 0..3 {
+            let original = pixel.0[c] as f32;
+            let value = original + amount * (original - smooth.0[c] as f32);
+            pixel.0[c] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    image::DynamicImage::ImageRgba8(current)
+}
+
+#[tauri::command]
+async fn apply_sharpening(
+    path: String,
+    sigma: f32,
+    amount: f32,
+    output_path: Option<String>,
+    threshold: Option<u8>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(&path, output_path.as_deref(), None, |img| {
+        unsharp_mask(&img, sigma, amount, threshold.unwrap_or(0))
+    })
+}
+
+// Base64 characters needed to cover the 32 bytes sniffed for magic numbers
+const MAGIC_SNIFF_BASE64_LEN: usize = 44;
+
+#[tauri::command]
+async fn detect_image_format_from_bytes(
+    data_base64: String,
+) -> std::result::Result<String, String> {
+    // Accept data URIs as well as bare base64
+    let data = match data_base64.find("base64,") {
+        Some(index) => &data_base64[index + "base64,".len()..],
+        None => data_base64.as_str(),
+    };
+    let prefix: String = data
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .take(MAGIC_SNIFF_BASE64_LEN)
+        .collect();
+    // A short input can end mid-quantum; drop the partial group
+    let usable = prefix.len() - prefix.len() % 4;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(&prefix[..usable])
+        .map_err(|e| format!("decode_failed: {}", e))?;
+    Ok(detect_format_from_bytes(&bytes).to_string())
+}
+
+const DIRECTORY_STATS_PROGRESS_INTERVAL: u32 = 200;
+
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatStats {
+    count: u32,
+    total_bytes: u64,
+}
+
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DirectoryStats {
+    total_files: u32,
+    image_files: u32,
+    total_size_bytes: u64,
+    by_format: std::collections::HashMap<String, FormatStats>,
+    largest_file: String,
+    smallest_file: String,
+    estimated_savings_bytes: u64,
+}
+
+// Typical lossy savings per format, damped for busy images since high
+// entropy content leaves the encoder less to remove
+fn estimated_savings(path: &Path, size: u64) -> u64 {
+    let base = match detect_image_format(path) {
+        Ok(ImageFormat::Png) => 0.40,
+        Ok(ImageFormat::Jpeg) => 0.20,
+        Ok(ImageFormat::WebP) => 0.30,
+        _ => return 0,
+    };
+    let entropy = match image::open(path) {
+        Ok(img) => measure_image_entropy(&img),
+        Err(_) => return 0,
+    };
+    let damping = 1.0 - (entropy / 8.0).clamp(0.0, 1.0) * 0.5;
+    (size as f64 * base * damping) as u64
+}
+
+#[tauri::command]
+async fn get_directory_stats(
+    app: tauri::AppHandle,
+    directory: String,
+) -> std::result::Result<DirectoryStats, String> {
+    let files: Vec<(std::path::PathBuf, u64)> = walkdir::WalkDir::new(&directory)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let size = entry.metadata().ok()?.len();
+            Some((entry.into_path(), size))
+        })
+        .collect();
+
+    let total = files.len() as u32;
+    let mut stats = DirectoryStats {
+        total_files: total,
+        ..DirectoryStats::default()
+    };
+    let mut largest: Option<(u64, &Path)> = None;
+    let mut smallest: Option<(u64, &Path)> = None;
+
+    for (index, (path, size)) in files.iter().enumerate() {
+        stats.total_size_bytes += size;
+        if largest.map_or(true, |(s, _)| *size > s) {
+            largest = Some((*size, path.as_path()));
+        }
+        if smallest.map_or(true, |(s, _)| *size < s) {
+            smallest = Some((*size, path.as_path()));
+        }
+
+        if is_compressible_path(path) {
+            stats.image_files += 1;
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let format_stats = stats.by_format.entry(extension).or_default();
+            format_stats.count += 1;
+            format_stats.total_bytes += size;
+            stats.estimated_savings_bytes += estimated_savings(path, *size);
+        }
+
+        let done = index as u32 + 1;
+        if done % DIRECTORY_STATS_PROGRESS_INTERVAL == 0 || done == total {
+            let _ = app.emit(
+                "directory-stats-progress",
+                ProgressEvent {
+                    current: done,
+                    total,
+                    path: path.to_string_lossy().to_string(),
+                },
+            );
+        }
+    }
+
+    stats.largest_file = largest
+        .map(|(_, path)| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    stats.smallest_file = smallest
+        .map(|(_, path)| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(stats)
+}
+
+// "#RRGGBB" (leading '#' optional) or "transparent"
+fn parse_border_color(color: &str) -> anyhow::Result<image::Rgba<u8>> {
+    let color = color.trim();
+    if color.eq_ignore_ascii_case("transparent") {
+        return Ok(image::Rgba([0, 0, 0, 0]));
+    }
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "Invalid color '{}', expected #RRGGBB or transparent",
+            color
+        ));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    Ok(image::Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+fn add_border(
+    path: &Path,
+    border_px: u32,
+    color: &str,
+    output_path: Option<&str>,
+    output_format: Option<String>,
+) -> anyhow::Result<ProcessedImageResult> {
+    let border_px = border_px.clamp(1, 500);
+    let mut fill = parse_border_color(color)?;
+    let img = image::open(path)?;
+
+    let config = CompressionConfig {
+        lossless: true,
+        output_format,
+        ..CompressionConfig::default()
+    };
+    let format = match config.output_format.as_deref() {
+        Some(name) => format_from_name(name)?,
+        None => detect_image_format(path)?,
+    };
+
+    let (width, height) = img.dimensions();
+    let mut canvas = image::RgbaImage::new(width + 2 * border_px, height + 2 * border_px);
+    // JPEG has no alpha channel to show a transparent border with
+    if format == ImageFormat::Jpeg && fill.0[3] == 0 {
+        fill = image::Rgba([255, 255, 255, 255]);
+    }
+    for pixel in canvas.pixels_mut() {
+        *pixel = fill;
+    }
+    image::imageops::overlay(
+        &mut canvas,
+        &img.to_rgba8(),
+        border_px as i64,
+        border_px as i64,
+    );
+
+    let bordered = if format == ImageFormat::Jpeg {
+        image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(canvas).to_rgb8())
+    } else {
+        image::DynamicImage::ImageRgba8(canvas)
+    };
+    save_processed_image_with(&bordered, path, output_path, &config)
+}
+
+#[tauri::command]
+async fn apply_border(
+    path: String,
+    border_px: u32,
+    color: String,
+    output_path: Option<String>,
+    output_format: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    add_border(
+        Path::new(&path),
+        border_px,
+        &color,
+        output_path.as_deref(),
+        output_format,
+    )
+    .map_err(|e| e.to_string())
+}
+
+const MAX_PDF_PAGES: usize = 500;
+// Quality used when PNG/WebP pages are converted to JPEG for embedding
+const PDF_JPEG_QUALITY: u8 = 90;
+const PDF_IMAGE_DPI: f32 = 300.0;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PdfResult {
+    page_count: u32,
+    file_size: u64,
+}
+
+// Page size in millimetres: "A4" (default), "Letter" or "custom:WxH_mm"
+fn parse_page_size(page_size: Option<&str>) -> anyhow::Result<(f32, f32)> {
+    let page_size = page_size.unwrap_or("A4");
+    if page_size.eq_ignore_ascii_case("a4") {
+        return Ok((210.0, 297.0));
+    }
+    if page_size.eq_ignore_ascii_case("letter") {
+        return Ok((215.9, 279.4));
+    }
+    let custom = page_size
+        .strip_prefix("custom:")
+        .and_then(|dims| dims.strip_suffix("_mm"))
+        .and_then(|dims| dims.split_once('x'))
+        .and_then(|(w, h)| Some((w.trim().parse::<f32>().ok()?, h.trim().parse::<f32>().ok()?)))
+        .filter(|&(w, h)| w > 0.0 && h > 0.0);
+    custom.ok_or_else(|| anyhow!("Invalid page size '{}'", page_size))
+}
+
+// JPEG bytes ready for DCT embedding, plus their pixel size and colour space.
+// Gray and RGB JPEGs pass through untouched; everything else is re-encoded.
+fn pdf_jpeg_data(path: &Path) -> anyhow::Result<(Vec<u8>, u32, u32, printpdf::ColorSpace)> {
+    if detect_image_format(path)? == ImageFormat::Jpeg {
+        let bytes = fs::read(path)?;
+        let jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.clone().into())?;
+        let components = jpeg
+            .segments()
+            .iter()
+            .find(|segment| matches!(segment.marker(), 0xC0..=0xC2))
+            .and_then(|segment| segment.contents().get(5).copied());
+        let color_space = match components {
+            Some(1) => Some(printpdf::ColorSpace::Greyscale),
+            Some(3) => Some(printpdf::ColorSpace::Rgb),
+            _ => None,
+        };
+        if let Some(color_space) = color_space {
+            let (width, height) = image::image_dimensions(path)?;
+            return Ok((bytes, width, height, color_space));
+        }
+    }
+
+    let img = image::open(path)?;
+    let rgb = image::DynamicImage::ImageRgb8(img.to_rgb8());
+    let data = encode_at_quality(&rgb, ImageFormat::Jpeg, PDF_JPEG_QUALITY)?;
+    Ok((data, rgb.width(), rgb.height(), printpdf::ColorSpace::Rgb))
+}
+
+fn build_pdf(
+    paths: &[String],
+    output_path: &Path,
+    page_size: Option<&str>,
+) -> anyhow::Result<PdfResult> {
+    if paths.is_empty() {
+        return Err(anyhow!("No images given"));
+    }
+    if paths.len() > MAX_PDF_PAGES {
+        return Err(anyhow!(
+            "Too many pages: {} (max {})",
+            paths.len(),
+            MAX_PDF_PAGES
+        ));
+    }
+    let (page_w, page_h) = parse_page_size(page_size)?;
+
+    let (doc, first_page, first_layer) = printpdf::PdfDocument::new(
+        "Ximage",
+        printpdf::Mm(page_w),
+        printpdf::Mm(page_h),
+        "Image",
+    );
+    for (index, path) in paths.iter().enumerate() {
+        let (page, layer) = if index == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(printpdf::Mm(page_w), printpdf::Mm(page_h), "Image")
+        };
+        let layer = doc.get_page(page).get_layer(layer);
+
+        let (data, width, height, color_space) = pdf_jpeg_data(Path::new(path))?;
+        let image = printpdf::Image::from(printpdf::ImageXObject {
+            width: printpdf::Px(width as usize),
+            height: printpdf::Px(height as usize),
+            color_space,
+            bits_per_component: printpdf::ColorBits::Bit8,
+            interpolate: true,
+            image_data: data,
+            image_filter: Some(printpdf::ImageFilter::DCT),
+            smask: None,
+            clipping_bbox: None,
+        });
+
+        // Fit inside the page keeping the aspect ratio, centred
+        let natural_w = width as f32 / PDF_IMAGE_DPI * 25.4;
+        let natural_h = height as f32 / PDF_IMAGE_DPI * 25.4;
+        let scale = (page_w / natural_w).min(page_h / natural_h);
+        image.add_to_layer(
+            layer,
+            printpdf::ImageTransform {
+                translate_x: Some(printpdf::Mm((page_w - natural_w * scale) / 2.0)),
+                translate_y: Some(printpdf::Mm((page_h - natural_h * scale) / 2.0)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(PDF_IMAGE_DPI),
+                ..printpdf::ImageTransform::default()
+            },
+        );
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(output_path)?;
+    doc.save(&mut std::io::BufWriter::new(file))?;
+
+    Ok(PdfResult {
+        page_count: paths.len() as u32,
+        file_size: fs::metadata(output_path)?.len(),
+    })
+}
+
+#[tauri::command]
+async fn convert_to_pdf(
+    paths: Vec<String>,
+    output_path: String,
+    page_size: Option<String>,
+) -> std::result::Result<PdfResult, String> {
+    build_pdf(&paths, Path::new(&output_path), page_size.as_deref()).map_err(|e| e.to_string())
+}
+
+// Radial darkening: pixels beyond `radius` (a fraction of the half-diagonal)
+// are scaled by 1 - strength * t^2, where t runs from 0 at the radius to 1 at
+// the corners. Alpha is left as is.
+fn vignette(img: &image::DynamicImage, strength: f32, radius: f32) -> image::DynamicImage {
+    let strength = strength.clamp(0.0, 1.0);
+    let radius = radius.clamp(0.0, 1.0);
+    if strength <= 0.0 || radius >= 1.0 {
+        return img.clone();
+    }
+
+    let (width, height) = img.dimensions();
+    let center_x = (width as f32 - 1.0) / 2.0;
+    let center_y = (height as f32 - 1.0) / 2.0;
+    let half_diagonal = (center_x * center_x + center_y * center_y).sqrt().max(1.0);
+    let mask = image::ImageBuffer::from_fn(width, height, |x, y| {
+        let dx = x as f32 - center_x;
+        let dy = y as f32 - center_y;
+        let distance = (dx * dx + dy * dy).sqrt() / half_diagonal;
+        let t = ((distance - radius) / (1.0 - radius)).max(0.0);
+        image::Luma([(1.0 - strength * t * t).clamp(0.0, 1.0)])
+    });
+
+    let mut current = img.to_rgba8();
+    for (x, y, pixel) in current.enumerate_pixels_mut() {
+        let multiplier = mask.get_pixel(x, y).0[0];
+        for c in 0..3 {
+            pixel.0[c] = (pixel.0[c] as f32 * multiplier).round() as u8;
+        }
+    }
+    image::DynamicImage::ImageRgba8(current)
+}
+
+#[tauri::command]
+async fn apply_vignette(
+    path: String,
+    strength: f32,
+    radius: f32,
+    output_path: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(&path, output_path.as_deref(), None, |img| {
+        vignette(&img, strength, radius)
+    })
+}
+
+// Headroom on top of the bytes a job expects to write
+const DISK_SPACE_BUFFER: f64 = 1.2;
+const DISK_CHECK_MIN_BATCH: usize = 10;
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiskSpaceInfo {
+    available_bytes: u64,
+    total_bytes: u64,
+    has_sufficient_space: bool,
+    recommended_free_bytes: u64,
+}
+
+// sysinfo covers statvfs and GetDiskFreeSpaceEx; the disk is the one with
+// the longest mount point containing the directory
+fn disk_space_for(directory: &Path, required_bytes: u64) -> anyhow::Result<DiskSpaceInfo> {
+    let directory = fs::canonicalize(directory)?;
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    let disk = disks
+        .list()
+        .iter()
+        .filter(|disk| directory.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .ok_or_else(|| anyhow!("No disk found for {}", directory.display()))?;
+
+    let recommended_free_bytes = (required_bytes as f64 * DISK_SPACE_BUFFER).ceil() as u64;
+    Ok(DiskSpaceInfo {
+        available_bytes: disk.available_space(),
+        total_bytes: disk.total_space(),
+        has_sufficient_space: disk.available_space() >= recommended_free_bytes,
+        recommended_free_bytes,
+    })
+}
+
+#[tauri::command]
+async fn check_disk_space(
+    output_dir: String,
+    required_bytes: u64,
+) -> std::result::Result<DiskSpaceInfo, String> {
+    disk_space_for(Path::new(&output_dir), required_bytes).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_quality_defaults() -> QualityDefaults {
+    QUALITY_DEFAULTS
+}
+
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompressionJob {
+    id: String,
+    source_path: String,
+    // Required: jobs run unattended, so none may write over its source
+    output_path: String,
+    config_json: String,
+    priority: u8,
+}
+
+// Heap entry: higher priority first, then first-queued first
+struct QueuedJob {
+    queue_id: String,
+    sequence: u64,
+    job: CompressionJob,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.job
+            .priority
+            .cmp(&other.job.priority)
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueStatus {
+    total: u32,
+    pending: u32,
+    completed: u32,
+    failed: u32,
+    cancelled: u32,
+    #[serde(skip)]
+    finished_at: Option<std::time::Instant>,
+}
+
+impl QueueStatus {
+    // One job left the queue, by running or by being flushed
+    fn job_finished(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+        if self.pending == 0 {
+            self.finished_at = Some(std::time::Instant::now());
+        }
+    }
+}
+
+// Finished queues stay queryable this long, then are forgotten
+const QUEUE_STATUS_TTL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+fn expire_queue_statuses(
+    statuses: &mut std::collections::HashMap<String, QueueStatus>,
+    ttl: std::time::Duration,
+) {
+    statuses.retain(|_, status| status.finished_at.is_none_or(|at| at.elapsed() < ttl));
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobCompleteEvent {
+    queue_id: String,
+    job_id: String,
+    status: String,
+    original_size: u64,
+    compressed_size: u64,
+}
+
+lazy_static::lazy_static! {
+    static ref JOB_QUEUE: std::sync::Mutex<std::collections::BinaryHeap<QueuedJob>> =
+        std::sync::Mutex::new(std::collections::BinaryHeap::new());
+    static ref JOB_QUEUE_SIGNAL: std::sync::Condvar = std::sync::Condvar::new();
+    static ref QUEUE_STATUSES: std::sync::Mutex<std::collections::HashMap<String, QueueStatus>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+static JOB_SEQUENCE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+static JOB_WORKER: std::sync::Once = std::sync::Once::new();
+
+const QUEUE_UNAVAILABLE: &str = "queue_unavailable: the job queue lock is poisoned";
+
+fn job_queue() -> std::result::Result<
+    std::sync::MutexGuard<'static, std::collections::BinaryHeap<QueuedJob>>,
+    String,
+> {
+    JOB_QUEUE.lock().map_err(|_| QUEUE_UNAVAILABLE.to_string())
+}
+
+fn queue_statuses() -> std::result::Result<
+    std::sync::MutexGuard<'static, std::collections::HashMap<String, QueueStatus>>,
+    String,
+> {
+    QUEUE_STATUSES.lock().map_err(|_| QUEUE_UNAVAILABLE.to_string())
+}
+
+// Block until a job is queued
+fn next_queued_job() -> std::result::Result<QueuedJob, String> {
+    let mut queue = job_queue()?;
+    loop {
+        match queue.pop() {
+            Some(queued) => return Ok(queued),
+            None => {
+                queue = JOB_QUEUE_SIGNAL
+                    .wait(queue)
+                    .map_err(|_| QUEUE_UNAVAILABLE.to_string())?
+            }
+        }
+    }
+}
+
+fn run_queued_job(job: &CompressionJob) -> anyhow::Result<(u64, u64)> {
+    let config = CompressionConfig::from_json(&job.config_json)?;
+    compress_image(
+        Path::new(&job.source_path),
+        &config,
+        Some(Path::new(&job.output_path)),
+        true,
+    )
+}
+
+// Single background thread draining JOB_QUEUE for the app's lifetime
+fn job_worker(app: tauri::AppHandle) {
+    loop {
+        let queued = match next_queued_job() {
+            Ok(queued) => queued,
+            Err(e) => {
+                log::error!("Job worker stopped: {}", e);
+                return;
+            }
+        };
+
+        // A panicking job must not take the only worker thread down with it
+        let outcome = std::panic::catch_unwind(|| run_queued_job(&queued.job))
+            .unwrap_or_else(|_| Err(anyhow!("Job panicked")));
+        match queue_statuses() {
+            Ok(mut statuses) => {
+                if let Some(status) = statuses.get_mut(&queued.queue_id) {
+                    status.job_finished();
+                    match outcome {
+                        Ok(_) => status.completed += 1,
+                        Err(_) => status.failed += 1,
+                    }
+                }
+            }
+            Err(e) => log::error!("Job {} status not recorded: {}", queued.job.id, e),
+        }
+
+        let (status, original_size, compressed_size) = match outcome {
+            Ok((original, compressed)) => ("success".to_string(), original, compressed),
+            Err(e) => (format!("error: {}", e), 0, 0),
+        };
+        let _ = app.emit(
+            "job-complete",
+            JobCompleteEvent {
+                queue_id: queued.queue_id,
+                job_id: queued.job.id,
+                status,
+                original_size,
+                compressed_size,
+            },
+        );
+    }
+}
+
+#[tauri::command]
+async fn queue_compression_jobs(
+    app: tauri::AppHandle,
+    jobs: Vec<CompressionJob>,
+) -> std::result::Result<String, String> {
+    JOB_WORKER.call_once(|| {
+        std::thread::spawn(move || job_worker(app));
+    });
+
+    let queue_id = Uuid::new_v4().to_string();
+    let count = jobs.len() as u32;
+    {
+        let mut statuses = queue_statuses()?;
+        expire_queue_statuses(&mut statuses, QUEUE_STATUS_TTL);
+        statuses.insert(
+            queue_id.clone(),
+            QueueStatus {
+                total: count,
+                pending: count,
+                finished_at: (count == 0).then(std::time::Instant::now),
+                ..QueueStatus::default()
+            },
+        );
+    }
+
+    let mut queue = job_queue()?;
+    for job in jobs {
+        queue.push(QueuedJob {
+            queue_id: queue_id.clone(),
+            sequence: JOB_SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+            job,
+        });
+    }
+    JOB_QUEUE_SIGNAL.notify_all();
+    Ok(queue_id)
+}
+
+#[tauri::command]
+async fn get_queue_status(queue_id: String) -> std::result::Result<QueueStatus, String> {
+    let mut statuses = queue_statuses()?;
+    expire_queue_statuses(&mut statuses, QUEUE_STATUS_TTL);
+    statuses
+        .get(&queue_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown or expired queue: {}", queue_id))
+}
+
+// Drop every job that has not started yet; running jobs still finish
+#[tauri::command]
+async fn flush_queue() -> std::result::Result<u32, String> {
+    let flushed: Vec<QueuedJob> = job_queue()?.drain().collect();
+    let mut statuses = queue_statuses()?;
+    for queued in &flushed {
+        if let Some(status) = statuses.get_mut(&queued.queue_id) {
+            status.job_finished();
+            status.cancelled += 1;
+        }
+    }
+    Ok(flushed.len() as u32)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PngSizeBreakdown {
+    idat_compressed_bytes: u64,
+    metadata_bytes: u64,
+    other_critical_bytes: u64,
+    total_bytes: u64,
+    metadata_percent: f64,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JpegSizeBreakdown {
+    scan_data_bytes: u64,
+    app_markers_bytes: u64,
+    quantization_tables_bytes: u64,
+    huffman_tables_bytes: u64,
+    other_bytes: u64,
+    total_bytes: u64,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "format", rename_all = "camelCase")]
+enum CompressionBreakdown {
+    Png(PngSizeBreakdown),
+    Jpeg(JpegSizeBreakdown),
+}
+
+// Chunk sizes include the 12 bytes of length, type and CRC framing
+fn png_size_breakdown(bytes: Vec<u8>) -> anyhow::Result<PngSizeBreakdown> {
+    let total_bytes = bytes.len() as u64;
+    let png = img_parts::png::Png::from_bytes(bytes.into())?;
+
+    // The 8-byte signature counts as critical so the parts sum to the total
+    let mut idat_compressed_bytes = 0;
+    let mut metadata_bytes = 0;
+    let mut other_critical_bytes = 8;
+    for chunk in png.chunks() {
+        let size = chunk.contents().len() as u64 + 12;
+        match &chunk.kind() {
+            b"IDAT" => idat_compressed_bytes += size,
+            b"IHDR" | b"PLTE" | b"IEND" => other_critical_bytes += size,
+            _ => metadata_bytes += size,
+        }
+    }
+
+    Ok(PngSizeBreakdown {
+        idat_compressed_bytes,
+        metadata_bytes,
+        other_critical_bytes,
+        total_bytes,
+        metadata_percent: if total_bytes > 0 {
+            metadata_bytes as f64 / total_bytes as f64 * 100.0
+        } else {
+            0.0
+        },
+    })
+}
+
+// Walks the marker stream directly so entropy-coded scan data is measured
+// separately from the SOS header that precedes it
+fn jpeg_size_breakdown(bytes: &[u8]) -> anyhow::Result<JpegSizeBreakdown> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Err(anyhow!("Not a JPEG file"));
+    }
+
+    let mut breakdown = JpegSizeBreakdown {
+        scan_data_bytes: 0,
+        app_markers_bytes: 0,
+        quantization_tables_bytes: 0,
+        huffman_tables_bytes: 0,
+        other_bytes: 2,
+        total_bytes: bytes.len() as u64,
+    };
+    let mut offset = 2;
+    while offset + 1 < bytes.len() {
+        if bytes[offset] != 0xFF {
+            return Err(anyhow!("Corrupt JPEG marker at offset {}", offset));
+        }
+        let marker = bytes[offset + 1];
+        if marker == 0xFF {
+            // Fill byte
+            breakdown.other_bytes += 1;
+            offset += 1;
+            continue;
+        }
+        if marker == 0xD9 || marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            breakdown.other_bytes += 2;
+            offset += 2;
+            if marker == 0xD9 {
+                break;
+            }
+            continue;
+        }
+
+        if offset + 4 > bytes.len() {
+            return Err(anyhow!("Truncated JPEG segment at offset {}", offset));
+        }
+        let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let end = (offset + 2 + length).min(bytes.len());
+        let size = (end - offset) as u64;
+        match marker {
+            0xE0..=0xEF | 0xFE => breakdown.app_markers_bytes += size,
+            0xDB => breakdown.quantization_tables_bytes += size,
+            0xC4 => breakdown.huffman_tables_bytes += size,
+            _ => breakdown.other_bytes += size,
+        }
+        offset = end;
+
+        if marker == 0xDA {
+            // Entropy-coded data runs until a marker other than a stuffed
+            // zero or a restart marker
+            let scan_start = offset;
+            while offset + 1 < bytes.len() {
+                let next = bytes[offset + 1];
+                if bytes[offset] == 0xFF && next != 0x00 && !(0xD0..=0xD7).contains(&next) {
+                    break;
+                }
+                offset += 1;
+            }
+            if offset + 1 >= bytes.len() {
+                offset = bytes.len();
+            }
+            breakdown.scan_data_bytes += (offset - scan_start) as u64;
+        }
+    }
+    // Trailing bytes after EOI
+    breakdown.other_bytes += (bytes.len() - offset.min(bytes.len())) as u64;
+
+    Ok(breakdown)
+}
+
+#[tauri::command]
+async fn analyze_compression_overhead(
+    path: String,
+) -> std::result::Result<CompressionBreakdown, String> {
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+    match detect_format_from_bytes(&bytes) {
+        "png" => png_size_breakdown(bytes).map(CompressionBreakdown::Png),
+        "jpeg" => jpeg_size_breakdown(&bytes).map(CompressionBreakdown::Jpeg),
+        other => Err(anyhow!("Unsupported format for size breakdown: {}", other)),
+    }
+    .map_err(|e| e.to_string())
+}
+
+// Percentiles used as black and white points, ignoring outlier pixels
+const AUTO_LEVELS_LOW_PERCENTILE: f64 = 0.02;
+const AUTO_LEVELS_HIGH_PERCENTILE: f64 = 0.98;
+// Spreads narrower than this are treated as flat images
+const AUTO_LEVELS_MIN_SPREAD: u8 = 4;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoLevelsResult {
+    min_before: u8,
+    max_before: u8,
+    applied: bool,
+    status: String,
+    output_path: Option<String>,
+}
+
+// Luma values at the low and high percentiles
+fn luma_percentiles(img: &image::DynamicImage) -> (u8, u8) {
+    let luma = img.to_luma8();
+    let mut histogram = [0u64; 256];
+    for pixel in luma.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total = luma.pixels().len() as f64;
+    let percentile = |fraction: f64| {
+        let target = (total * fraction).ceil().max(1.0) as u64;
+        let mut cumulative = 0;
+        for (value, &count) in histogram.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return value as u8;
+            }
+        }
+        255
+    };
+    (
+        percentile(AUTO_LEVELS_LOW_PERCENTILE),
+        percentile(AUTO_LEVELS_HIGH_PERCENTILE),
+    )
+}
+
+// The luma-derived mapping is applied to every colour channel alike so the
+// stretch does not shift hues
+fn stretch_levels(img: &image::DynamicImage, low: u8, high: u8) -> image::DynamicImage {
+    let scale = 255.0 / (high - low) as f32;
+    let lut: Vec<u8> = (0..=255u8)
+        .map(|value| {
+            let stretched = value.saturating_sub(low) as f32 * scale;
+            stretched.round().clamp(0.0, 255.0) as u8
+        })
+        .collect();
+
+    let mut current = img.to_rgba8();
+    for pixel in current.pixels_mut() {
+        for c in 0..3 {
+            pixel.0[c] = lut[pixel.0[c] as usize];
+        }
+    }
+    image::DynamicImage::ImageRgba8(current)
+}
+
+#[tauri::command]
+async fn apply_auto_levels(
+    path: String,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<AutoLevelsResult, String> {
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (low, high) = luma_percentiles(&img);
+    if high <= low || high - low < AUTO_LEVELS_MIN_SPREAD {
+        return Ok(AutoLevelsResult {
+            min_before: low,
+            max_before: high,
+            applied: false,
+            status: "skipped_flat".to_string(),
+            output_path: None,
+        });
+    }
+
+    let stretched = stretch_levels(&img, low, high);
+    let saved = save_processed_image(
+        &stretched,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(AutoLevelsResult {
+        min_before: low,
+        max_before: high,
+        applied: true,
+        status: "success".to_string(),
+        output_path: Some(saved.output_path),
+    })
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SharpenConfig {
+    sigma: f32,
+    amount: f32,
+    threshold: u8,
+}
+
+impl Default for SharpenConfig {
+    fn default() -> Self {
+        SharpenConfig {
+            sigma: 1.0,
+            amount: 0.5,
+            threshold: 0,
+        }
+    }
+}
+
+// Compression settings with a `sharpen` object alongside the regular fields
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SharpenCompressRequest {
+    #[serde(default)]
+    sharpen: SharpenConfig,
+    #[serde(flatten)]
+    config: CompressionConfig,
+}
+
+#[tauri::command]
+async fn apply_unsharp_mask_before_compress(
+    path: String,
+    config_json: String,
+    output_path: Option<String>,
+) -> std::result::Result<CompressionResult, String> {
+    let request = serde_json::from_str::<SharpenCompressRequest>(&config_json)
+        .map_err(|e| format!("Invalid compression config: {}", e))?;
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let sharpened = unsharp_mask(
+        &img,
+        request.sharpen.sigma,
+        request.sharpen.amount,
+        request.sharpen.threshold,
+    );
+    drop(img);
+
+    // The sharpened pixels go straight into the encoder; no intermediate file
+    let (original_size, compressed_size) = compress_image_with(
+        source_path,
+        &request.config,
+        output_path.as_deref().map(Path::new),
+        true,
+        Some(sharpened),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(CompressionResult {
+        path,
+        output_path,
+        original_size,
+        compressed_size,
+        status: "success".to_string(),
+        ..CompressionResult::default()
+    })
+}
+
+const CONTACT_SHEET_PADDING: u32 = 4;
+const CONTACT_SHEET_LABEL_HEIGHT: u32 = 16;
+const CONTACT_SHEET_LABEL_SCALE: f32 = 12.0;
+// DejaVu Sans Mono advance width as a fraction of the font size
+const CONTACT_SHEET_GLYPH_WIDTH: f32 = 0.6;
+
+static CONTACT_SHEET_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContactSheetResult {
+    image_count: u32,
+    cols: u32,
+    rows: u32,
+    output_width: u32,
+    output_height: u32,
+}
+
+// Shorten a label to `max_chars`, marking the cut with an ellipsis
+fn fit_label(name: &str, max_chars: usize) -> String {
+    if name.chars().count() <= max_chars {
+        return name.to_string();
+    }
+    let kept: String = name.chars().take(max_chars.saturating_sub(1)).collect();
+    format!("{}…", kept)
+}
+
+fn build_contact_sheet(
+    directory: &Path,
+    cols: u32,
+    thumb_size: u32,
+    output_path: &Path,
+    label_filenames: bool,
+) -> anyhow::Result<ContactSheetResult> {
+    let output_format = match detect_image_format(output_path)? {
+        format @ (ImageFormat::Png | ImageFormat::Jpeg) => format,
+        _ => return Err(anyhow!("Contact sheets are saved as PNG or JPEG")),
+    };
+    let cols = cols.max(1);
+    let thumb_size = thumb_size.clamp(32, 512);
+
+    let mut paths: Vec<_> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_compressible_path(path))
+        .collect();
+    paths.sort();
+
+    let thumbnails: Vec<(String, image::DynamicImage)> = paths
+        .iter()
+        .filter_map(|path| match image::open(path) {
+            Ok(img) => {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                Some((name, img.thumbnail(thumb_size, thumb_size)))
+            }
+            Err(e) => {
+                log::warn!("Skipping {} in contact sheet: {}", path.display(), e);
+                None
+            }
+        })
+        .collect();
+    if thumbnails.is_empty() {
+        return Err(anyhow!("No images found in {}", directory.display()));
+    }
+
+    let count = thumbnails.len() as u32;
+    let cols = cols.min(count);
+    let rows = count.div_ceil(cols);
+    let label_height = if label_filenames {
+        CONTACT_SHEET_LABEL_HEIGHT
+    } else {
+        0
+    };
+    let cell_width = thumb_size + CONTACT_SHEET_PADDING;
+    let cell_height = thumb_size + label_height + CONTACT_SHEET_PADDING;
+    let output_width = cols * cell_width + CONTACT_SHEET_PADDING;
+    let output_height = rows * cell_height + CONTACT_SHEET_PADDING;
+
+    let font = rusttype::Font::try_from_bytes(CONTACT_SHEET_FONT)
+        .ok_or_else(|| anyhow!("Bundled contact sheet font is invalid"))?;
+    let scale = rusttype::Scale::uniform(CONTACT_SHEET_LABEL_SCALE);
+    let max_label_chars =
+        (thumb_size as f32 / (CONTACT_SHEET_LABEL_SCALE * CONTACT_SHEET_GLYPH_WIDTH)) as usize;
+
+    let mut sheet = image::RgbImage::from_pixel(output_width, output_height, image::Rgb([255; 3]));
+    for (index, (name, thumbnail)) in thumbnails.iter().enumerate() {
+        let index = index as u32;
+        let cell_x = CONTACT_SHEET_PADDING + (index % cols) * cell_width;
+        let cell_y = CONTACT_SHEET_PADDING + (index / cols) * cell_height;
+
+        // Centre the thumbnail in its square slot
+        let (width, height) = thumbnail.dimensions();
+        let x = cell_x + (thumb_size - width) / 2;
+        let y = cell_y + (thumb_size - height) / 2;
+        image::imageops::overlay(&mut sheet, &thumbnail.to_rgb8(), x as i64, y as i64);
+
+        if label_filenames {
+            imageproc::drawing::draw_text_mut(
+                &mut sheet,
+                image::Rgb([0, 0, 0]),
+                cell_x as i32,
+                (cell_y + thumb_size + 2) as i32,
+                scale,
+                &font,
+                &fit_label(name, max_label_chars),
+            );
+        }
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    image::DynamicImage::ImageRgb8(sheet).save_with_format(output_path, output_format)?;
+
+    Ok(ContactSheetResult {
+        image_count: count,
+        cols,
+        rows,
+        output_width,
+        output_height,
+    })
+}
+
+#[tauri::command]
+async fn generate_contact_sheet(
+    directory: String,
+    cols: u32,
+    thumb_size: u32,
+    output_path: String,
+    label_filenames: bool,
+) -> std::result::Result<ContactSheetResult, String> {
+    build_contact_sheet(
+        Path::new(&directory),
+        cols,
+        thumb_size,
+        Path::new(&output_path),
+        label_filenames,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CoverArtResult {
+    format: String,
+    width: u32,
+    height: u32,
+    size_bytes: u64,
+    output_path: String,
+}
+
+// The front cover when the file labels one, otherwise the first picture
+fn read_cover_art(audio_path: &Path) -> anyhow::Result<Vec<u8>> {
+    let extension = audio_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let data = match extension.as_str() {
+        "mp3" => {
+            let tag = id3::Tag::read_from_path(audio_path)?;
+            let pictures: Vec<_> = tag.pictures().collect();
+            pictures
+                .iter()
+                .find(|picture| picture.picture_type == id3::frame::PictureType::CoverFront)
+                .or(pictures.first())
+                .map(|picture| picture.data.clone())
+        }
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(audio_path)?;
+            let pictures: Vec<_> = tag.pictures().collect();
+            pictures
+                .iter()
+                .find(|picture| picture.picture_type == metaflac::block::PictureType::CoverFront)
+                .or(pictures.first())
+                .map(|picture| picture.data.clone())
+        }
+        "m4a" => {
+            let tag = mp4ameta::Tag::read_from_path(audio_path)?;
+            tag.artwork().map(|artwork| artwork.data.to_vec())
+        }
+        other => return Err(anyhow!("Unsupported audio format: {}", other)),
+    };
+    data.ok_or_else(|| anyhow!("No embedded cover art in {}", audio_path.display()))
+}
+
+fn extract_cover_art_file(
+    audio_path: &Path,
+    output_path: &Path,
+    config_json: Option<&str>,
+) -> anyhow::Result<CoverArtResult> {
+    let data = read_cover_art(audio_path)?;
+    let format = match image::guess_format(&data)? {
+        format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP) => format,
+        other => return Err(anyhow!("Unsupported cover art format: {:?}", other)),
+    };
+    let (width, height) = image::load_from_memory_with_format(&data, format)?.dimensions();
+
+    // The extension follows the embedded image so later decoding matches
+    let final_path = final_output_path(output_path, None, format);
+    if let Some(parent) = final_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&final_path, &data)?;
+
+    if let Some(json) = config_json {
+        let config = CompressionConfig::from_json(json)?;
+        compress_image(&final_path, &config, None, true)?;
+    }
+
+    Ok(CoverArtResult {
+        format: format_name(format).to_string(),
+        width,
+        height,
+        size_bytes: fs::metadata(&final_path)?.len(),
+        output_path: final_path.to_string_lossy().to_string(),
+    })
+}
+
+#[tauri::command]
+async fn extract_cover_art(
+    audio_path: String,
+    output_path: String,
+    config_json: Option<String>,
+) -> std::result::Result<CoverArtResult, String> {
+    extract_cover_art_file(
+        Path::new(&audio_path),
+        Path::new(&output_path),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AlgorithmComparison {
+    algorithm: String,
+    quality_used: u8,
+    output_bytes: u64,
+    encode_ms: u64,
+    score: f64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ComparisonCandidate {
+    Jpeg(u8),
+    Webp(i32),
+    Png(u8),
+}
+
+const COMPARISON_WEBP_METHODS: std::ops::RangeInclusive<i32> = 0..=4;
+const COMPARISON_PNG_PRESETS: std::ops::RangeInclusive<u8> = 0..=4;
+
+// Every candidate is scored with the JPEG candidate formula against the
+// encode the regular pipeline would produce for that format. Candidates run
+// in parallel, so `encode_ms` includes some contention.
+fn compare_algorithms(
+    img: &image::DynamicImage,
+    quality: u8,
+    format: Option<ImageFormat>,
+) -> anyhow::Result<Vec<AlgorithmComparison>> {
+    let (jpeg_base, jpeg_qualities) = jpeg_candidates(quality, DEFAULT_JPEG_QUALITY_RANGE);
+    let mut candidates = Vec::new();
+    if format.is_none() || format == Some(ImageFormat::Jpeg) {
+        candidates.extend(jpeg_qualities.into_iter().map(ComparisonCandidate::Jpeg));
+    }
+    if format.is_none() || format == Some(ImageFormat::WebP) {
+        candidates.extend(COMPARISON_WEBP_METHODS.map(ComparisonCandidate::Webp));
+    }
+    if format.is_none() || format == Some(ImageFormat::Png) {
+        candidates.extend(COMPARISON_PNG_PRESETS.map(ComparisonCandidate::Png));
+    }
+
+    let config = CompressionConfig {
+        quality_webp: quality,
+        quality_png: quality,
+        ..CompressionConfig::default()
+    };
+    let encoded: Vec<(ComparisonCandidate, usize, u64)> = candidates
+        .into_par_iter()
+        .map(|candidate| -> anyhow::Result<(ComparisonCandidate, usize, u64)> {
+            let started = std::time::Instant::now();
+            let data = match candidate {
+                ComparisonCandidate::Jpeg(q) => encode_at_quality(img, ImageFormat::Jpeg, q)?,
+                ComparisonCandidate::Webp(method) => encode_webp(img, &config, method)?,
+                ComparisonCandidate::Png(preset) => encode_png_quantized(img, &config, preset)?,
+            };
+            Ok((candidate, data.len(), started.elapsed().as_millis() as u64))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let baseline_size = |baseline: ComparisonCandidate| {
+        encoded
+            .iter()
+            .find(|(candidate, _, _)| *candidate == baseline)
+            .map(|&(_, size, _)| size)
+            .unwrap_or(0)
+    };
+    let jpeg_baseline = baseline_size(ComparisonCandidate::Jpeg(jpeg_base));
+    let webp_baseline = baseline_size(ComparisonCandidate::Webp(DEFAULT_WEBP_METHOD));
+    let png_baseline = baseline_size(ComparisonCandidate::Png(DEFAULT_PNG_QUANTIZED_PRESET));
+    let webp_quality = quality.min(QUALITY_DEFAULTS.webp_max);
+    let png_quality = quality.clamp(QUALITY_DEFAULTS.png_min, QUALITY_DEFAULTS.png_max);
+
+    Ok(encoded
+        .iter()
+        .map(|&(candidate, size, encode_ms)| {
+            let (algorithm, quality_used, baseline) = match candidate {
+                ComparisonCandidate::Jpeg(q) => (format!("jpeg:q{}", q), q, jpeg_baseline),
+                ComparisonCandidate::Webp(method) => (
+                    format!("webp:method{}", method),
+                    webp_quality,
+                    webp_baseline,
+                ),
+                ComparisonCandidate::Png(preset) => {
+                    (format!("png:preset{}", preset), png_quality, png_baseline)
+                }
+            };
+            AlgorithmComparison {
+                algorithm,
+                quality_used,
+                output_bytes: size as u64,
+                encode_ms,
+                score: candidate_score(quality_used, size, baseline),
+            }
+        })
+        .collect())
+}
+
+#[tauri::command]
+async fn compare_compression_algorithms(
+    path: String,
+    quality: u8,
+    format: Option<String>,
+) -> std::result::Result<Vec<AlgorithmComparison>, String> {
+    let format = match format.as_deref() {
+        Some(name) => Some(format_from_name(name).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let img = image::open(&path).map_err(|e| e.to_string())?;
+    compare_algorithms(&img, quality, format).map_err(|e| e.to_string())
+}
+
+const MAX_WATCHERS: usize = 10;
+// Quiet period before a changed file is compressed, so copies in progress
+// are not picked up half written
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+const WATCH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+lazy_static::lazy_static! {
+    static ref WATCHERS: std::sync::Mutex<std::collections::HashMap<String, notify::RecommendedWatcher>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+fn watchers() -> std::result::Result<
+    std::sync::MutexGuard<'static, std::collections::HashMap<String, notify::RecommendedWatcher>>,
+    String,
+> {
+    WATCHERS
+        .lock()
+        .map_err(|_| "watchers_unavailable: the watcher table lock is poisoned".to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WatchCompressedEvent {
+    watcher_id: String,
+    path: String,
+    original_size: u64,
+    compressed_size: u64,
+    status: String,
+}
+
+// Size and mtime identify a file this watcher wrote itself
+fn file_signature(path: &Path) -> Option<(u64, std::time::SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+// Runs until the watcher is dropped, which closes the event channel;
+// changes still inside the debounce window at that point are skipped
+fn watch_worker(
+    app: tauri::AppHandle,
+    watcher_id: String,
+    config: CompressionConfig,
+    events: std::sync::mpsc::Receiver<std::path::PathBuf>,
+) {
+    let mut pending: std::collections::HashMap<std::path::PathBuf, std::time::Instant> =
+        std::collections::HashMap::new();
+    // Compressing in place fires another modify event for the output
+    let mut written: std::collections::HashMap<std::path::PathBuf, (u64, std::time::SystemTime)> =
+        std::collections::HashMap::new();
+
+    loop {
+        match events.recv_timeout(WATCH_POLL_INTERVAL) {
+            Ok(path) => {
+                pending.insert(path, std::time::Instant::now());
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+
+        let ready: Vec<_> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= WATCH_DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in ready {
+            pending.remove(&path);
+            let signature = file_signature(&path);
+            if signature.is_none() || written.get(&path) == signature.as_ref() {
+                continue;
+            }
+
+            let (original_size, compressed_size, status) =
+                match compress_image(&path, &config, None, true) {
+                    Ok((original, compressed)) => (original, compressed, "success".to_string()),
+                    Err(e) => {
+                        log::warn!("Watch compression failed for {}: {}", path.display(), e);
+                        (0, 0, format!("error: {}", e))
+                    }
+                };
+            if let Some(signature) = file_signature(&path) {
+                written.insert(path.clone(), signature);
+            }
+            let _ = app.emit(
+                "watch-compressed",
+                WatchCompressedEvent {
+                    watcher_id: watcher_id.clone(),
+                    path: path.to_string_lossy().to_string(),
+                    original_size,
+                    compressed_size,
+                    status,
+                },
+            );
+        }
+    }
+}
+
+#[tauri::command]
+async fn watch_directory(
+    app: tauri::AppHandle,
+    directory: String,
+    config_json: String,
+) -> std::result::Result<String, String> {
+    use notify::Watcher;
+
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let directory = Path::new(&directory);
+    if !directory.is_dir() {
+        return Err(format!("Not a directory: {}", directory.display()));
+    }
+
+    let mut watchers = watchers()?;
+    if watchers.len() >= MAX_WATCHERS {
+        return Err(format!(
+            "At most {} directories can be watched",
+            MAX_WATCHERS
+        ));
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !matches!(
+            event.kind,
+            notify::EventKind::Create(_) | notify::EventKind::Modify(_)
+        ) {
+            return;
+        }
+        for path in event.paths {
+            if is_compressible_path(&path) {
+                let _ = sender.send(path);
+            }
+        }
+    })
+    .map_err(|e| e.to_string())?;
+    watcher
+        .watch(directory, notify::RecursiveMode::NonRecursive)
+        .map_err(|e| e.to_string())?;
+
+    let watcher_id = Uuid::new_v4().to_string();
+    let worker_id = watcher_id.clone();
+    std::thread::spawn(move || watch_worker(app, worker_id, config, receiver));
+    watchers.insert(watcher_id.clone(), watcher);
+    Ok(watcher_id)
+}
+
+#[tauri::command]
+async fn stop_watch(watcher_id: String) -> std::result::Result<bool, String> {
+    // Dropping the watcher ends its event stream and the worker with it
+    Ok(watchers()?.remove(&watcher_id).is_some())
+}
+
+// Reported PSNR for identical inputs, where the true value is infinite
+const MAX_PSNR: f64 = 100.0;
+const SSIM_WINDOW: usize = 8;
+const SSIM_STRIDE: usize = 4;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VisualDiffScore {
+    psnr: f64,
+    ssim: f64,
+    mean_absolute_error: f64,
+    max_absolute_error: u8,
+    identical_pixel_count: u32,
+    total_pixels: u32,
+    luma_psnr: f64,
+    chroma_psnr: Option<f64>,
+}
+
+fn psnr_from_mse(mse: f64, peak: f64) -> f64 {
+    if mse <= 0.0 {
+        MAX_PSNR
+    } else {
+        (10.0 * (peak * peak / mse).log10()).min(MAX_PSNR)
+    }
+}
+
+fn plane_mse(a: &[f64], b: &[f64]) -> f64 {
+    let sum: f64 = a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum();
+    sum / a.len().max(1) as f64
+}
+
+// BT.709 Y, Cb and Cr planes, with chroma offset to the 0-255 range
+fn ycbcr_planes(img: &image::RgbImage) -> [Vec<f64>; 3] {
+    let mut planes = [Vec::new(), Vec::new(), Vec::new()];
+    for pixel in img.pixels() {
+        let [r, g, b] = pixel.0.map(|c| c as f64);
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        planes[0].push(y);
+        planes[1].push((b - y) / 1.8556 + 128.0);
+        planes[2].push((r - y) / 1.5748 + 128.0);
+    }
+    planes
+}
+
+// Mean SSIM over 8x8 windows with a stride of 4, on one plane
+fn plane_ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+    let count = (window_w * window_h) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in (0..=height - window_h).step_by(SSIM_STRIDE) {
+        for left in (0..=width - window_w).step_by(SSIM_STRIDE) {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in top..top + window_h {
+                for x in left..left + window_w {
+                    let (va, vb) = (a[y * width + x], b[y * width + x]);
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let var_a = sum_aa / count - mean_a * mean_a;
+            let var_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows.max(1) as f64
+}
+
+fn visual_diff(
+    mut a: image::DynamicImage,
+    mut b: image::DynamicImage,
+    resize_to_match: bool,
+) -> anyhow::Result<VisualDiffScore> {
+    if a.dimensions() != b.dimensions() {
+        if !resize_to_match {
+            return Err(anyhow!(
+                "Image sizes differ: {:?} vs {:?}",
+                a.dimensions(),
+                b.dimensions()
+            ));
+        }
+        let area = |img: &image::DynamicImage| img.width() as u64 * img.height() as u64;
+        let filter = image::imageops::FilterType::Lanczos3;
+        if area(&a) < area(&b) {
+            a = a.resize_exact(b.width(), b.height(), filter);
+        } else {
+            b = b.resize_exact(a.width(), a.height(), filter);
+        }
+    }
+    let has_color = a.color().has_color() || b.color().has_color();
+    let (rgb_a, rgb_b) = (a.to_rgb8(), b.to_rgb8());
+    let (width, height) = rgb_a.dimensions();
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Images are empty"));
+    }
+
+    let mut squared_error = 0.0;
+    let mut absolute_error = 0.0;
+    let mut max_absolute_error = 0;
+    let mut identical_pixel_count = 0;
+    for (pa, pb) in rgb_a.pixels().zip(rgb_b.pixels()) {
+        if pa == pb {
+            identical_pixel_count += 1;
+        }
+        for c in 0..3 {
+            let diff = pa.0[c].abs_diff(pb.0[c]);
+            max_absolute_error = max_absolute_error.max(diff);
+            absolute_error += diff as f64;
+            squared_error += diff as f64 * diff as f64;
+        }
+    }
+    let total_pixels = width * height;
+    let samples = total_pixels as f64 * 3.0;
+
+    let planes_a = ycbcr_planes(&rgb_a);
+    let planes_b = ycbcr_planes(&rgb_b);
+    let chroma_psnr = has_color.then(|| {
+        let mse =
+            (plane_mse(&planes_a[1], &planes_b[1]) + plane_mse(&planes_a[2], &planes_b[2])) / 2.0;
+        psnr_from_mse(mse, 255.0)
+    });
+
+    Ok(VisualDiffScore {
+        psnr: psnr_from_mse(squared_error / samples, 255.0),
+        ssim: plane_ssim(&planes_a[0], &planes_b[0], width as usize, height as usize),
+        mean_absolute_error: absolute_error / samples,
+        max_absolute_error,
+        identical_pixel_count,
+        total_pixels,
+        luma_psnr: psnr_from_mse(plane_mse(&planes_a[0], &planes_b[0]), 255.0),
+        chroma_psnr,
+    })
+}
+
+#[tauri::command]
+async fn compute_visual_diff_score(
+    path_a: String,
+    path_b: String,
+    resize_to_match: Option<bool>,
+) -> std::result::Result<VisualDiffScore, String> {
+    let a = image::open(&path_a).map_err(|e| e.to_string())?;
+    let b = image::open(&path_b).map_err(|e| e.to_string())?;
+    visual_diff(a, b, resize_to_match.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+const INCHES_PER_METRE: f64 = 39.3701;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DpiResult {
+    new_dpi_x: u32,
+    new_dpi_y: u32,
+    format: String,
+    output_path: Option<String>,
+    warning: Option<String>,
+}
+
+// Rewrite the JFIF density fields, adding a JFIF APP0 segment when missing
+fn set_jpeg_dpi(bytes: Vec<u8>, dpi_x: u16, dpi_y: u16) -> anyhow::Result<Vec<u8>> {
+    let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.into())?;
+    let density = |contents: &mut Vec<u8>| {
+        // Units 1 = dots per inch
+        contents[7] = 1;
+        contents[8..10].copy_from_slice(&dpi_x.to_be_bytes());
+        contents[10..12].copy_from_slice(&dpi_y.to_be_bytes());
+    };
+
+    let segments = jpeg.segments_mut();
+    let existing = segments.iter().position(|segment| {
+        segment.marker() == img_parts::jpeg::markers::APP0
+            && segment.contents().starts_with(b"JFIF\0")
+            && segment.contents().len() >= 14
+    });
+    match existing {
+        Some(index) => {
+            let mut contents = segments[index].contents().to_vec();
+            density(&mut contents);
+            segments[index] = img_parts::jpeg::JpegSegment::new_with_contents(
+                img_parts::jpeg::markers::APP0,
+                contents.into(),
+            );
+        }
+        None => {
+            // JFIF 1.02, no thumbnail
+            let mut contents = b"JFIF\0\x01\x02\0\0\0\0\0\0\0".to_vec();
+            density(&mut contents);
+            segments.insert(
+                0,
+                img_parts::jpeg::JpegSegment::new_with_contents(
+                    img_parts::jpeg::markers::APP0,
+                    contents.into(),
+                ),
+            );
+        }
+    }
+
+    let mut output = Vec::new();
+    jpeg.encoder().write_to(&mut output)?;
+    Ok(output)
+}
+
+// Replace any pHYs chunk with one in pixels per metre, right after IHDR
+fn set_png_dpi(bytes: Vec<u8>, dpi_x: u32, dpi_y: u32) -> anyhow::Result<Vec<u8>> {
+    let to_ppm = |dpi: u32| (dpi as f64 * INCHES_PER_METRE).round() as u32;
+    let mut contents = Vec::with_capacity(9);
+    contents.extend_from_slice(&to_ppm(dpi_x).to_be_bytes());
+    contents.extend_from_slice(&to_ppm(dpi_y).to_be_bytes());
+    // Unit 1 = metre
+    contents.push(1);
+
+    let mut png = img_parts::png::Png::from_bytes(bytes.into())?;
+    let chunks = png.chunks_mut();
+    chunks.retain(|chunk| chunk.kind() != *b"pHYs");
+    let position = 1.min(chunks.len());
+    chunks.insert(
+        position,
+        img_parts::png::PngChunk::new(*b"pHYs", contents.into()),
+    );
+
+    let mut output = Vec::new();
+    png.encoder().write_to(&mut output)?;
+    Ok(output)
+}
+
+fn set_dpi_file(
+    path: &Path,
+    dpi_x: u32,
+    dpi_y: u32,
+    output_path: Option<&str>,
+) -> anyhow::Result<DpiResult> {
+    let bytes = fs::read(path)?;
+    let format = detect_format_from_bytes(&bytes);
+    let output = match format {
+        "jpeg" => {
+            // JFIF stores densities as 16-bit values
+            let (x, y) = (u16::try_from(dpi_x), u16::try_from(dpi_y));
+            let (Ok(x), Ok(y)) = (x, y) else {
+                return Err(anyhow!("JPEG DPI must be at most {}", u16::MAX));
+            };
+            set_jpeg_dpi(bytes, x, y)?
+        }
+        "png" => set_png_dpi(bytes, dpi_x, dpi_y)?,
+        "webp" => {
+            // WebP has no standard density field
+            return Ok(DpiResult {
+                new_dpi_x: dpi_x,
+                new_dpi_y: dpi_y,
+                format: format.to_string(),
+                output_path: None,
+                warning: Some("format_not_supported".to_string()),
+            });
+        }
+        other => return Err(anyhow!("Unsupported format for DPI: {}", other)),
+    };
+
+    let target = output_path.map(Path::new).unwrap_or(path);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&CompressionConfig::default(), target, &output)?;
+
+    Ok(DpiResult {
+        new_dpi_x: dpi_x,
+        new_dpi_y: dpi_y,
+        format: format.to_string(),
+        output_path: Some(target.to_string_lossy().to_string()),
+        warning: None,
+    })
+}
+
+#[tauri::command]
+async fn set_image_dpi(
+    path: String,
+    dpi_x: u32,
+    dpi_y: Option<u32>,
+    output_path: Option<String>,
+) -> std::result::Result<DpiResult, String> {
+    if dpi_x == 0 || dpi_y == Some(0) {
+        return Err("DPI must be positive".to_string());
+    }
+    set_dpi_file(
+        Path::new(&path),
+        dpi_x,
+        dpi_y.unwrap_or(dpi_x),
+        output_path.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Extensions are always lowercased; the other steps are opt-in
+fn normalized_filename(
+    name: &str,
+    rename_to_lowercase: bool,
+    replace_spaces: bool,
+    strip_special_chars: bool,
+) -> String {
+    let path = Path::new(name);
+    let (stem, extension) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => (
+            stem.to_string_lossy().into_owned(),
+            Some(ext.to_string_lossy().to_lowercase()),
+        ),
+        _ => (name.to_string(), None),
+    };
+
+    let mut stem = if rename_to_lowercase {
+        stem.to_lowercase()
+    } else {
+        stem
+    };
+    if replace_spaces {
+        stem = stem.replace(char::is_whitespace, "_");
+    }
+    let keep = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+    let mut extension = extension;
+    if strip_special_chars {
+        stem.retain(keep);
+        extension = extension.map(|mut ext| {
+            ext.retain(keep);
+            ext
+        });
+    }
+    if stem.is_empty() {
+        stem = "unnamed_file".to_string();
+    }
+
+    match extension.filter(|ext| !ext.is_empty()) {
+        Some(ext) => format!("{}.{}", stem, ext),
+        None => stem,
+    }
+}
+
+// `name`, or `stem_N.ext` for the first N not yet taken. Names compare
+// case-insensitively for case-insensitive filesystems.
+fn unique_filename(name: &str, taken: &mut std::collections::HashSet<String>) -> String {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut candidate = name.to_string();
+    let mut suffix = 1;
+    while taken.contains(&candidate.to_lowercase()) {
+        candidate = format!("{}_{}{}", stem, suffix, extension);
+        suffix += 1;
+    }
+    taken.insert(candidate.to_lowercase());
+    candidate
+}
+
+#[tauri::command]
+async fn normalize_filenames(
+    directory: String,
+    rename_to_lowercase: bool,
+    replace_spaces: bool,
+    strip_special_chars: bool,
+) -> std::result::Result<Vec<RenameResult>, String> {
+    let directory = Path::new(&directory);
+    let mut names: Vec<String> = fs::read_dir(directory)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let targets: Vec<String> = names
+        .iter()
+        .map(|name| {
+            normalized_filename(
+                name,
+                rename_to_lowercase,
+                replace_spaces,
+                strip_special_chars,
+            )
+        })
+        .collect();
+    // Files that keep their name claim it before any renamed file does
+    let mut taken: std::collections::HashSet<String> = names
+        .iter()
+        .zip(&targets)
+        .filter(|(name, target)| name == target)
+        .map(|(name, _)| name.to_lowercase())
+        .collect();
+
+    let mut results = Vec::new();
+    let mut pending = Vec::new();
+    for (name, target) in names.iter().zip(&targets) {
+        let old_path = directory.join(name);
+        if name == target {
+            results.push(RenameResult {
+                old_path: old_path.to_string_lossy().to_string(),
+                new_path: old_path.to_string_lossy().to_string(),
+                status: "unchanged".to_string(),
+            });
+            continue;
+        }
+        let new_path = directory.join(unique_filename(target, &mut taken));
+        pending.push((old_path, new_path));
+    }
+
+    // Two phases, so a rename chain like a -> b, b -> c never overwrites a
+    // file that has not moved yet
+    let mut staged = Vec::new();
+    for (old_path, new_path) in pending {
+        let temp_path = directory.join(format!(
+            "{}.tmp.{}",
+            old_path.file_name().unwrap_or_default().to_string_lossy(),
+            Uuid::new_v4()
+        ));
+        match fs::rename(&old_path, &temp_path) {
+            Ok(()) => staged.push((old_path, temp_path, new_path)),
+            Err(e) => results.push(RenameResult {
+                old_path: old_path.to_string_lossy().to_string(),
+                new_path: new_path.to_string_lossy().to_string(),
+                status: format!("error: {}", e),
+            }),
+        }
+    }
+
+    for (old_path, temp_path, new_path) in staged {
+        let status = if new_path.exists() {
+            // Appeared since the listing; put the file back
+            fs::rename(&temp_path, &old_path).ok();
+            "skipped_exists".to_string()
+        } else {
+            match fs::rename(&temp_path, &new_path) {
+                Ok(()) => "renamed".to_string(),
+                Err(e) => {
+                    fs::rename(&temp_path, &old_path).ok();
+                    format!("error: {}", e)
+                }
+            }
+        };
+        results.push(RenameResult {
+            old_path: old_path.to_string_lossy().to_string(),
+            new_path: new_path.to_string_lossy().to_string(),
+            status,
+        });
+    }
+
+    Ok(results)
+}
+
+// Size of the widely shipped HP/Microsoft sRGB IEC61966-2.1 profile
+const SRGB_PROFILE_SIZE: usize = 3144;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IccProfileInfo {
+    has_profile: bool,
+    profile_name: Option<String>,
+    color_space: Option<String>,
+    rendering_intent: Option<String>,
+    profile_size_bytes: u32,
+    is_srgb: bool,
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> Option<u32> {
+    let slice = bytes.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+// Text of the `desc` tag, stored as `desc` (ICC v2, ASCII) or `mluc`
+// (ICC v4, UTF-16BE records; the first record is used)
+fn icc_description(profile: &[u8]) -> Option<String> {
+    // A corrupt count must not walk past the 12-byte entries the data can hold
+    let tag_count =
+        (read_u32_be(profile, 128)? as usize).min(profile.len().saturating_sub(132) / 12);
+    let (offset, size) = (0..tag_count).find_map(|index| {
+        let entry = 132 + index * 12;
+        if profile.get(entry..entry + 4)? != b"desc" {
+            return None;
+        }
+        let offset = read_u32_be(profile, entry + 4)?;
+        let size = read_u32_be(profile, entry + 8)?;
+        Some((offset, size))
+    })?;
+    let tag = profile.get(offset as usize..(offset as usize).checked_add(size as usize)?)?;
+
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let length = read_u32_be(tag, 8)? as usize;
+            let ascii = tag.get(12..12 + length)?;
+            String::from_utf8_lossy(ascii).into_owned()
+        }
+        b"mluc" => {
+            let length = read_u32_be(tag, 20)? as usize;
+            let start = read_u32_be(tag, 24)? as usize;
+            let utf16: Vec<u16> = tag
+                .get(start..start + length)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn icc_profile_info(path: &Path) -> anyhow::Result<IccProfileInfo> {
+    let bytes = fs::read(path)?;
+    let profile = match detect_format_from_bytes(&bytes) {
+        "jpeg" => img_parts::jpeg::Jpeg::from_bytes(bytes.into())?.icc_profile(),
+        "png" => img_parts::png::Png::from_bytes(bytes.into())?.icc_profile(),
+        "webp" => img_parts::webp::WebP::from_bytes(bytes.into())?.icc_profile(),
+        other => return Err(anyhow!("Unsupported format for ICC profiles: {}", other)),
+    };
+    let Some(profile) = profile else {
+        return Ok(IccProfileInfo {
+            has_profile: false,
+            profile_name: None,
+            color_space: None,
+            rendering_intent: None,
+            profile_size_bytes: 0,
+            is_srgb: false,
+        });
+    };
+
+    let profile_name = icc_description(&profile);
+    let color_space = profile
+        .get(16..20)
+        .map(|signature| String::from_utf8_lossy(signature).trim().to_string());
+    let rendering_intent = read_u32_be(&profile, 64).map(|intent| {
+        match intent {
+            0 => "perceptual",
+            1 => "relative_colorimetric",
+            2 => "saturation",
+            3 => "absolute_colorimetric",
+            _ => "unknown",
+        }
+        .to_string()
+    });
+    let is_srgb = profile.len() == SRGB_PROFILE_SIZE
+        || profile_name
+            .as_deref()
+            .map(|name| name.contains("sRGB"))
+            .unwrap_or(false);
+
+    Ok(IccProfileInfo {
+        has_profile: true,
+        profile_name,
+        color_space,
+        rendering_intent,
+        profile_size_bytes: profile.len() as u32,
+        is_srgb,
+    })
+}
+
+#[tauri::command]
+async fn get_icc_profile_info(path: String) -> std::result::Result<IccProfileInfo, String> {
+    icc_profile_info(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+fn parse_rgb_color(color: &str) -> std::result::Result<[u8; 3], String> {
+    match parse_border_color(color).map_err(|e| e.to_string())? {
+        image::Rgba([r, g, b, 255]) => Ok([r, g, b]),
+        _ => Err(format!("Invalid color '{}', expected #RRGGBB", color)),
+    }
+}
+
+fn apply_effect(
+    path: &str,
+    output_path: Option<&str>,
+    config_json: Option<&str>,
+    transform: PixelTransform,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(path, output_path, config_json, |img| {
+        apply_pixel_transform(img, &transform)
+    })
+}
+
+#[tauri::command]
+async fn apply_sepia(
+    path: String,
+    intensity: f32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    apply_effect(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        PixelTransform::Sepia { intensity },
+    )
+}
+
+#[tauri::command]
+async fn apply_grayscale_weighted(
+    path: String,
+    standard: Option<LumaStandard>,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    apply_effect(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        PixelTransform::Grayscale {
+            standard: standard.unwrap_or(LumaStandard::Bt709),
+        },
+    )
+}
+
+#[tauri::command]
+async fn apply_duotone(
+    path: String,
+    highlight_color: String,
+    shadow_color: String,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    let transform = PixelTransform::Duotone {
+        highlight: parse_rgb_color(&highlight_color)?,
+        shadow: parse_rgb_color(&shadow_color)?,
+    };
+    apply_effect(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        transform,
+    )
+}
+
+#[tauri::command]
+async fn apply_color_balance(
+    path: String,
+    shadows_rgb: [i16; 3],
+    midtones_rgb: [i16; 3],
+    highlights_rgb: [i16; 3],
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    apply_effect(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        PixelTransform::ColorBalance {
+            shadows: shadows_rgb,
+            midtones: midtones_rgb,
+            highlights: highlights_rgb,
+        },
+    )
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatCapability {
+    name: String,
+    can_decode: bool,
+    can_encode: bool,
+    lossy: bool,
+    lossless: bool,
+    animation: bool,
+    requires_system_lib: bool,
+    system_lib_available: bool,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FormatSupportMatrix {
+    formats: Vec<FormatCapability>,
+    // Whether JpegBackend::MozJpeg can be selected
+    mozjpeg_available: bool,
+}
+
+#[tauri::command]
+async fn check_format_support() -> std::result::Result<FormatSupportMatrix, String> {
+    // JPEG, PNG and WebP codecs are statically built into the binary
+    let builtin = |name: &str, lossy: bool, lossless: bool| FormatCapability {
+        name: name.to_string(),
+        can_decode: true,
+        can_encode: true,
+        lossy,
+        lossless,
+        animation: false,
+        requires_system_lib: false,
+        system_lib_available: true,
+    };
+    // libheif is linked dynamically with the `heif` feature, so a binary
+    // built with it only starts when the library is present
+    let heif = cfg!(feature = "heif");
+    let webp = FormatCapability {
+        animation: true,
+        ..builtin("webp", true, true)
+    };
+
+    Ok(FormatSupportMatrix {
+        formats: vec![
+            builtin("jpeg", true, false),
+            builtin("png", true, true),
+            webp,
+            FormatCapability {
+                name: "heif".to_string(),
+                can_decode: heif,
+                can_encode: false,
+                lossy: true,
+                lossless: false,
+                animation: false,
+                requires_system_lib: true,
+                system_lib_available: heif,
+            },
+        ],
+        mozjpeg_available: cfg!(feature = "mozjpeg"),
+    })
+}
+
+fn clipboard_image() -> std::result::Result<Option<image::DynamicImage>, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+    let data = match clipboard.get_image() {
+        Ok(data) => data,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    let rgba = image::RgbaImage::from_raw(
+        data.width as u32,
+        data.height as u32,
+        data.bytes.into_owned(),
+    )
+    .ok_or_else(|| "Clipboard image size mismatch".to_string())?;
+    Ok(Some(image::DynamicImage::ImageRgba8(rgba)))
+}
+
+#[tauri::command]
+async fn compress_clipboard_to_path(
+    app: tauri::AppHandle,
+    output_path: String,
+    config_json: String,
+) -> std::result::Result<CompressionResult, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+
+    let result = match clipboard_image()? {
+        None => CompressionResult {
+            path: "clipboard".to_string(),
+            status: "clipboard_empty_error".to_string(),
+            ..CompressionResult::default()
+        },
+        Some(img) => {
+            // The extension picks the format unless the config names one
+            let mut target = std::path::PathBuf::from(&output_path);
+            if target.extension().is_none() {
+                target.set_extension("png");
+            }
+            let (width, height) = img.dimensions();
+            let img = resize_for_config(img, &config, true);
+            let saved = save_processed_image_with(&img, &target, None, &config)
+                .map_err(|e| e.to_string())?;
+            let format = detect_image_format(Path::new(&saved.output_path)).ok();
+            CompressionResult {
+                path: "clipboard".to_string(),
+                output_path: Some(saved.output_path),
+                // Raw RGBA size of the clipboard bitmap
+                original_size: width as u64 * height as u64 * 4,
+                compressed_size: saved.size_bytes,
+                status: "success".to_string(),
+                selected_format: format.map(|f| format_name(f).to_string()),
+                mime_type: format.map(|f| mime_type(f).to_string()),
+                ..CompressionResult::default()
+            }
+        }
+    };
+
+    let _ = app.emit("clipboard-compressed", result.clone());
+    Ok(result)
+}
+
+const MAX_ANIMATION_FRAMES: usize = 1000;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameInfo {
+    index: u32,
+    path: String,
+    width: u32,
+    height: u32,
+    delay_ms: u32,
+    size_bytes: u64,
+}
+
+// Full-canvas frames with their display durations
+fn decode_webp_frames(bytes: &[u8]) -> anyhow::Result<Vec<(image::DynamicImage, u32)>> {
+    let features =
+        webp::BitstreamFeatures::new(bytes).ok_or_else(|| anyhow!("Not a valid WebP file"))?;
+    if !features.has_animation() {
+        return Ok(vec![(
+            image::load_from_memory_with_format(bytes, ImageFormat::WebP)?,
+            0,
+        )]);
+    }
+
+    let animation = webp::AnimDecoder::new(bytes)
+        .decode()
+        .map_err(|e| anyhow!("WebP animation decode failed: {}", e))?;
+    if animation.len() > MAX_ANIMATION_FRAMES {
+        return Err(anyhow!(
+            "Animation has {} frames; at most {} are supported",
+            animation.len(),
+            MAX_ANIMATION_FRAMES
+        ));
+    }
+
+    // Frame timestamps mark when each frame ends
+    let mut previous_end = 0;
+    let mut frames = Vec::with_capacity(animation.len());
+    for index in 0..animation.len() {
+        let frame = animation
+            .get_frame(index)
+            .ok_or_else(|| anyhow!("Missing WebP frame {}", index))?;
+        let (width, height) = (frame.width(), frame.height());
+        let pixels = frame.get_image().to_vec();
+        let img = match frame.get_layout() {
+            webp::PixelLayout::Rgba => {
+                image::RgbaImage::from_raw(width, height, pixels).map(image::DynamicImage::from)
+            }
+            webp::PixelLayout::Rgb => {
+                image::RgbImage::from_raw(width, height, pixels).map(image::DynamicImage::from)
+            }
+        }
+        .ok_or_else(|| anyhow!("WebP frame size mismatch"))?;
+        let end = frame.get_time_ms().max(previous_end);
+        frames.push((img, (end - previous_end) as u32));
+        previous_end = end;
+    }
+    Ok(frames)
+}
+
+fn split_webp_file(
+    path: &Path,
+    output_dir: &Path,
+    format: Option<String>,
+    config_json: Option<&str>,
+) -> anyhow::Result<Vec<FrameInfo>> {
+    let format = format.unwrap_or_else(|| "png".to_string());
+    let output_format = format_from_name(&format)?;
+    let config = match config_json {
+        Some(json) => CompressionConfig::from_json(json)?,
+        None => CompressionConfig {
+            lossless: true,
+            ..CompressionConfig::default()
+        },
+    };
+    let config = CompressionConfig {
+        output_format: Some(format),
+        ..config
+    };
+
+    let frames = decode_webp_frames(&fs::read(path)?)?;
+    fs::create_dir_all(output_dir)?;
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, (img, delay_ms))| {
+            let frame_path = output_dir.join(format!(
+                "{}_{:04}.{}",
+                stem,
+                index,
+                format_extension(output_format)
+            ));
+            let saved = save_processed_image_with(img, &frame_path, None, &config)?;
+            Ok(FrameInfo {
+                index: index as u32,
+                path: saved.output_path,
+                width: saved.width,
+                height: saved.height,
+                delay_ms: *delay_ms,
+                size_bytes: saved.size_bytes,
+            })
+        })
+        .collect()
+}
+
+fn assemble_webp_file(
+    frame_paths: &[String],
+    delays_ms: &[u32],
+    loop_count: u32,
+    output_path: &Path,
+) -> anyhow::Result<AnimatedWebPResult> {
+    if frame_paths.is_empty() {
+        return Err(anyhow!("No frames given"));
+    }
+    if frame_paths.len() > MAX_ANIMATION_FRAMES {
+        return Err(anyhow!(
+            "At most {} frames are supported",
+            MAX_ANIMATION_FRAMES
+        ));
+    }
+    if delays_ms.len() != frame_paths.len() {
+        return Err(anyhow!(
+            "Got {} delays for {} frames",
+            delays_ms.len(),
+            frame_paths.len()
+        ));
+    }
+
+    // The encoder borrows every frame buffer until it finishes
+    let frames = frame_paths
+        .iter()
+        .map(|path| Ok(image::open(path)?.to_rgba8()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let (width, height) = frames[0].dimensions();
+    if let Some(index) = frames
+        .iter()
+        .position(|f| f.dimensions() != (width, height))
+    {
+        return Err(anyhow!(
+            "Frame {} is {:?}, expected {}x{}",
+            index,
+            frames[index].dimensions(),
+            width,
+            height
+        ));
+    }
+
+    let mut config = webp::WebPConfig::new().map_err(|_| anyhow!("WebP config failed"))?;
+    config.quality = QUALITY_DEFAULTS.webp_default as f32;
+    let mut encoder = webp::AnimEncoder::new(width, height, &config);
+    encoder.set_loop_count(loop_count.min(u16::MAX as u32) as i32);
+    // Timestamps are frame start times
+    let mut timestamp = 0u32;
+    for (frame, delay) in frames.iter().zip(delays_ms) {
+        encoder.add_frame(webp::AnimFrame::from_rgba(
+            frame.as_raw(),
+            width,
+            height,
+            timestamp as i32,
+        ));
+        timestamp = timestamp.saturating_add(*delay);
+    }
+    let data = encoder
+        .try_encode()
+        .map_err(|e| anyhow!("WebP animation encode failed: {:?}", e))?;
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output_path, &*data)?;
+
+    // Frames can come in any format, so their file sizes stand in for the
+    // uncompressed input
+    let original_size = frame_paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    Ok(AnimatedWebPResult {
+        frame_count: frames.len() as u32,
+        original_size,
+        compressed_size: data.len() as u64,
+        duration_ms: timestamp as u64,
+    })
+}
+
+#[tauri::command]
+async fn split_animated_webp(
+    path: String,
+    output_dir: String,
+    format: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<Vec<FrameInfo>, String> {
+    split_webp_file(
+        Path::new(&path),
+        Path::new(&output_dir),
+        format,
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn assemble_animated_webp(
+    frame_paths: Vec<String>,
+    delays_ms: Vec<u32>,
+    loop_count: u32,
+    output_path: String,
+) -> std::result::Result<AnimatedWebPResult, String> {
+    assemble_webp_file(
+        &frame_paths,
+        &delays_ms,
+        loop_count,
+        Path::new(&output_path),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Empty when the config is valid; malformed JSON is an error instead
+#[tauri::command]
+async fn validate_config_json(
+    config_json: String,
+) -> std::result::Result<Vec<ConfigValidationError>, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    Ok(validate_config(&config).err().unwrap_or_default())
+}
+
+// Twice the triangle area below which three corners count as collinear
+const QUAD_COLLINEAR_EPSILON: f32 = 1.0;
+const MAX_PERSPECTIVE_DIMENSION: u32 = 16384;
+
+// Maps the quad (top-left, top-right, bottom-right, bottom-left) onto a
+// `width` x `height` rectangle; errors carry a code prefix for the frontend
+fn perspective_correct(
+    img: &image::DynamicImage,
+    src_quad: [(f32, f32); 4],
+    (width, height): (u32, u32),
+) -> std::result::Result<image::DynamicImage, String> {
+    use imageproc::geometric_transformations::{warp_into, Interpolation, Projection};
+
+    if width == 0
+        || height == 0
+        || width > MAX_PERSPECTIVE_DIMENSION
+        || height > MAX_PERSPECTIVE_DIMENSION
+    {
+        return Err(format!(
+            "invalid_size: output must be 1-{} pixels per side",
+            MAX_PERSPECTIVE_DIMENSION
+        ));
+    }
+    let (img_w, img_h) = img.dimensions();
+    for (index, &(x, y)) in src_quad.iter().enumerate() {
+        if !(0.0..=img_w as f32).contains(&x) || !(0.0..=img_h as f32).contains(&y) {
+            return Err(format!(
+                "point_outside_image: corner {} ({}, {}) is outside {}x{}",
+                index, x, y, img_w, img_h
+            ));
+        }
+    }
+    for skip in 0..4 {
+        let [a, b, c]: [(f32, f32); 3] = std::array::from_fn(|i| src_quad[(skip + 1 + i) % 4]);
+        let cross = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+        if cross.abs() < QUAD_COLLINEAR_EPSILON {
+            return Err("degenerate_quad: three corners are collinear".to_string());
+        }
+    }
+
+    let (w, h) = (width as f32, height as f32);
+    let projection =
+        Projection::from_control_points(src_quad, [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)])
+            .ok_or_else(|| "degenerate_quad: no homography maps these corners".to_string())?;
+
+    let source = img.to_rgba8();
+    let mut output = image::RgbaImage::new(width, height);
+    warp_into(
+        &source,
+        &projection,
+        Interpolation::Bilinear,
+        image::Rgba([0, 0, 0, 0]),
+        &mut output,
+    );
+    Ok(image::DynamicImage::ImageRgba8(output))
+}
+
+#[tauri::command]
+async fn apply_perspective_correction(
+    path: String,
+    src_quad: [(f32, f32); 4],
+    dst_size: (u32, u32),
+    output_path: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let corrected = perspective_correct(&img, src_quad, dst_size)?;
+    save_processed_image(&corrected, source_path, output_path.as_deref(), None)
+        .map_err(|e| e.to_string())
+}
+
+const ZOPFLI_ITERATIONS: u8 = 15;
+
+fn optimize_png_bytes(
+    data: &[u8],
+    effort: u8,
+    try_zopfli: bool,
+    strip_safe_metadata: bool,
+    threads: Option<u8>,
+) -> anyhow::Result<Vec<u8>> {
+    let effort = effort.min(6);
+    let mut options = oxipng::Options::from_preset(effort);
+    // Safe keeps every chunk that affects rendering, colour profiles included
+    options.strip = if strip_safe_metadata {
+        oxipng::StripChunks::Safe
+    } else {
+        oxipng::StripChunks::None
+    };
+    if try_zopfli && effort == 6 {
+        options.deflate = oxipng::Deflaters::Zopfli {
+            iterations: std::num::NonZeroU8::new(ZOPFLI_ITERATIONS).unwrap(),
+        };
+    }
+
+    // oxipng parallelises on the current rayon pool
+    match threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.max(1) as usize)
+            .build()?
+            .install(|| oxipng::optimize_from_memory(data, &options))
+            .map_err(Into::into),
+        None => Ok(oxipng::optimize_from_memory(data, &options)?),
+    }
+}
+
+#[tauri::command]
+async fn optimize_png_losslessly(
+    path: String,
+    effort: u8,
+    output_path: Option<String>,
+    try_zopfli: Option<bool>,
+    strip_safe_metadata: Option<bool>,
+    threads: Option<u8>,
+) -> std::result::Result<CompressionResult, String> {
+    let source_path = Path::new(&path);
+    let original = fs::read(source_path).map_err(|e| e.to_string())?;
+    if detect_format_from_bytes(&original) != "png" {
+        return Err(format!("Not a PNG file: {}", path));
+    }
+    let optimized = optimize_png_bytes(
+        &original,
+        effort,
+        try_zopfli.unwrap_or(false),
+        strip_safe_metadata.unwrap_or(false),
+        threads,
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Never grow the file; an output path still receives the original
+    let (data, status) = if optimized.len() < original.len() {
+        (&optimized, "success")
+    } else {
+        (&original, "already_optimal")
+    };
+    let target = output_path.as_deref().map(Path::new).unwrap_or(source_path);
+    if target != source_path || status == "success" {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        write_atomic(&CompressionConfig::default(), target, data).map_err(|e| e.to_string())?;
+    }
+
+    Ok(CompressionResult {
+        output_path: Some(target.to_string_lossy().to_string()),
+        original_size: original.len() as u64,
+        compressed_size: data.len() as u64,
+        status: status.to_string(),
+        selected_format: Some("png".to_string()),
+        mime_type: Some("image/png".to_string()),
+        path,
+        ..CompressionResult::default()
+    })
+}
+
+const LARGEST_FILES_PROGRESS_INTERVAL: u32 = 1000;
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileInfo {
+    path: String,
+    name: String,
+    size_bytes: u64,
+    format: String,
+    modified_ms: Option<u64>,
+}
+
+fn file_info(path: &Path, size_bytes: u64) -> FileInfo {
+    let modified_ms = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as u64);
+    FileInfo {
+        path: path.to_string_lossy().to_string(),
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size_bytes,
+        format: detect_image_format(path)
+            .map(|format| format_name(format).to_string())
+            .unwrap_or_else(|_| "unknown".to_string()),
+        modified_ms,
+    }
+}
+
+#[tauri::command]
+async fn get_largest_files(
+    app: tauri::AppHandle,
+    directory: String,
+    n: u32,
+    recursive: bool,
+    max_depth: Option<u32>,
+) -> std::result::Result<Vec<FileInfo>, String> {
+    let n = n.clamp(1, 500) as usize;
+    let depth = if recursive {
+        max_depth.map(|depth| depth as usize).unwrap_or(usize::MAX)
+    } else {
+        1
+    };
+
+    // Min-heap of the n largest so far; memory stays O(n) however big the tree
+    let mut largest = std::collections::BinaryHeap::with_capacity(n + 1);
+    let mut scanned = 0u32;
+    for entry in walkdir::WalkDir::new(&directory).max_depth(depth) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() || !is_compressible_path(entry.path()) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        largest.push(std::cmp::Reverse((metadata.len(), entry.into_path())));
+        if largest.len() > n {
+            largest.pop();
+        }
+
+        scanned += 1;
+        if scanned % LARGEST_FILES_PROGRESS_INTERVAL == 0 {
+            // The total is unknown until the walk ends
+            let _ = app.emit(
+                "largest-files-progress",
+                ProgressEvent {
+                    current: scanned,
+                    total: 0,
+                    path: directory.clone(),
+                },
+            );
+        }
+    }
+
+    Ok(largest
+        .into_sorted_vec()
+        .into_iter()
+        .map(|std::cmp::Reverse((size, path))| file_info(&path, size))
+        .collect())
+}
+
+// gAMA stores gamma times 100000; 100000 means linear samples
+const PNG_LINEAR_GAMMA: u32 = 100_000;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ColorSpaceResult {
+    source_space: ColorSpace,
+    target_space: ColorSpace,
+    status: String,
+    output_path: Option<String>,
+}
+
+// Sources are taken as sRGB unless a PNG declares linear gamma
+fn detect_color_space(path: &Path) -> ColorSpace {
+    if detect_image_format(path).ok() != Some(ImageFormat::Png) {
+        return ColorSpace::Srgb;
+    }
+    let gamma = fs::read(path)
+        .ok()
+        .and_then(|bytes| img_parts::png::Png::from_bytes(bytes.into()).ok())
+        .and_then(|png| {
+            let contents = png.chunk_by_type(*b"gAMA")?.contents().to_vec();
+            Some(u32::from_be_bytes(contents.get(..4)?.try_into().ok()?))
+        });
+    if gamma == Some(PNG_LINEAR_GAMMA) {
+        ColorSpace::Linear
+    } else {
+        ColorSpace::Srgb
+    }
+}
+
+// Convert to sRGB, linear light or weighted grayscale and save in the
+// source format. True CMYK output is not supported: no output ICC profile is
+// embedded, so print conversions should happen downstream.
+#[tauri::command]
+async fn convert_color_space(
+    path: String,
+    target_space: String,
+    output_path: Option<String>,
+) -> std::result::Result<ColorSpaceResult, String> {
+    let target: ColorSpace = serde_json::from_value(serde_json::Value::String(target_space))
+        .map_err(|e| format!("invalid_target_space: {}", e))?;
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let source = detect_color_space(source_path);
+
+    let already_converted = if target.is_grayscale() {
+        is_gray_color_type(img.color())
+    } else {
+        target == source
+    };
+    if already_converted {
+        return Ok(ColorSpaceResult {
+            source_space: source,
+            target_space: target,
+            status: "already_in_target_space".to_string(),
+            output_path: None,
+        });
+    }
+
+    let converted = keep_webp_layout(convert_to_color_space(img, source, target), source_path);
+    let saved = save_processed_image(&converted, source_path, output_path.as_deref(), None)
+        .map_err(|e| e.to_string())?;
+    Ok(ColorSpaceResult {
+        source_space: source,
+        target_space: target,
+        status: "success".to_string(),
+        output_path: Some(saved.output_path),
+    })
+}
+
+const MAX_UNSHARP_STEPS: usize = 10;
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UnsharpStep {
+    sigma: f32,
+    amount: f32,
+    #[serde(default)]
+    threshold: u8,
+}
+
+// Multi-scale sharpening: each pass sharpens the previous pass's output, so
+// e.g. a wide low-amount step followed by a fine one gives a "clarity" look
+#[tauri::command]
+async fn apply_unsharp_mask_chain(
+    path: String,
+    steps: Vec<UnsharpStep>,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    if steps.is_empty() {
+        return Err("no_steps: at least one unsharp step is required".to_string());
+    }
+    if steps.len() > MAX_UNSHARP_STEPS {
+        return Err(format!(
+            "too_many_steps: {} steps given, at most {} allowed",
+            steps.len(),
+            MAX_UNSHARP_STEPS
+        ));
+    }
+
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        |img| unsharp_chain(img, &steps),
+    )
+}
+
+fn unsharp_chain(img: image::DynamicImage, steps: &[UnsharpStep]) -> image::DynamicImage {
+    steps.iter().fold(img, |current, step| {
+        unsharp_mask(&current, step.sigma, step.amount, step.threshold)
+    })
+}
+
+// Hashes travel as 16 hex digits, the form compute_phash returns
+fn parse_phash(hash: &str) -> std::result::Result<u64, String> {
+    if hash.len() != 16 {
+        return Err(format!(
+            "invalid_hash: expected 16 hex digits, got {:?}",
+            hash
+        ));
+    }
+    u64::from_str_radix(hash, 16).map_err(|e| format!("invalid_hash: {:?}: {}", hash, e))
+}
+
+#[tauri::command]
+async fn compute_phash(path: String) -> std::result::Result<String, String> {
+    let img = image::open(&path).map_err(|e| e.to_string())?;
+    Ok(format!("{:016x}", phash(&img)))
+}
+
+#[tauri::command]
+fn phash_distance(hash_a: String, hash_b: String) -> std::result::Result<u32, String> {
+    Ok((parse_phash(&hash_a)? ^ parse_phash(&hash_b)?).count_ones())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AppInfo {
+    version: String,
+    platform: String,
+    arch: String,
+    supported_formats: Vec<String>,
+    // Batches are not capped; 0 means no limit
+    max_batch_size: u32,
+    temp_dir: String,
+    has_mozjpeg: bool,
+    has_heif: bool,
+    build_profile: String,
+}
+
+// Capabilities negotiation: everything here is fixed at compile time
+#[tauri::command]
+fn get_app_info() -> std::result::Result<AppInfo, String> {
+    Ok(AppInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        supported_formats: SUPPORTED_EXTENSIONS
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+        max_batch_size: 0,
+        temp_dir: std::env::temp_dir()
+            .join(UPLOAD_TEMP_DIR)
+            .to_string_lossy()
+            .to_string(),
+        has_mozjpeg: cfg!(feature = "mozjpeg"),
+        has_heif: cfg!(feature = "heif"),
+        build_profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+        .to_string(),
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BudgetedCompressionResult {
+    #[serde(flatten)]
+    result: CompressionResult,
+    budget_allocated: u64,
+}
+
+// Share `total_budget_bytes` across the batch in proportion to original size,
+// largest files first. After each file the allocation is recomputed from what
+// is actually left, so savings on one file loosen the limit for the rest and
+// overshoots tighten it. `config_json` picks the output format; quality is
+// searched per file. Outputs go to `output_dir` (next to each source when
+// omitted) as `{stem}_compressed.{ext}`, so sources are never overwritten;
+// files that cannot fit their allocation are reported but not written.
+#[tauri::command]
+async fn compress_with_budget(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    total_budget_bytes: u64,
+    config_json: String,
+    output_dir: Option<String>,
+) -> std::result::Result<Vec<BudgetedCompressionResult>, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let total = paths.len() as u32;
+    compress_within_budget(
+        paths,
+        total_budget_bytes,
+        &config,
+        output_dir.as_deref(),
+        |done, path| {
+            let _ = app.emit(
+                "compression-progress",
+                ProgressEvent {
+                    current: done as u32,
+                    total,
+                    path,
+                },
+            );
+        },
+    )
+}
+
+// `progress` gets each path with its 1-based position once it is done
+fn compress_within_budget(
+    paths: Vec<String>,
+    total_budget_bytes: u64,
+    config: &CompressionConfig,
+    output_dir: Option<&str>,
+    mut progress: impl FnMut(usize, String),
+) -> std::result::Result<Vec<BudgetedCompressionResult>, String> {
+    if let Some(dir) = output_dir {
+        if !config.dry_run {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut files: Vec<(String, u64)> = paths
+        .into_iter()
+        .map(|path| {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            (path, size)
+        })
+        .collect();
+    files.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut remaining_budget = total_budget_bytes;
+    let mut remaining_original: u64 = files.iter().map(|(_, size)| size).sum();
+    let mut results = Vec::with_capacity(files.len());
+    for (index, (path, original_size)) in files.into_iter().enumerate() {
+        let allocated = if remaining_original == 0 {
+            0
+        } else {
+            (remaining_budget as u128 * original_size as u128 / remaining_original as u128) as u64
+        };
+
+        let source = Path::new(&path);
+        let format = match config.output_format.as_deref() {
+            Some(name) => format_from_name(name),
+            None => detect_image_format(source),
+        };
+        let encoded = format.and_then(|format| {
+            let output_path = output_dir.map(|dir| {
+                let name = size_target_output_path(source, format);
+                Path::new(dir).join(name.file_name().unwrap_or_default())
+            });
+            encode_to_size_target(source, allocated, 0.0, format, output_path.as_deref())
+        });
+        let written = encoded.and_then(|(mut result, data)| {
+            if result.status == "target_not_reached" {
+                result.status = "over_budget".to_string();
+                result.output_path = None;
+            } else if let (false, Some(output_path)) = (config.dry_run, &result.output_path) {
+                write_output(config, Path::new(output_path), &data)?;
+            }
+            Ok(result)
+        });
+        let result = match written {
+            Ok(result) => result,
+            Err(e) => CompressionResult {
+                path: path.clone(),
+                original_size,
+                // The untouched original still counts against the budget
+                compressed_size: original_size,
+                status: format!("error: {}", e),
+                ..CompressionResult::default()
+            },
+        };
+
+        remaining_budget = remaining_budget.saturating_sub(result.compressed_size);
+        remaining_original -= original_size;
+        results.push(BudgetedCompressionResult {
+            result,
+            budget_allocated: allocated,
+        });
+        progress(index + 1, path);
+    }
+
+    Ok(results)
+}
+
+const BILATERAL_TILE_SIZE: u32 = 512;
+// Largest RGB distance, sqrt(3 * 255^2), rounded up
+const BILATERAL_MAX_DISTANCE: usize = 442;
+
+// One separable bilateral pass, along rows or columns. Each tap is weighted
+// by the cached spatial kernel times the range kernel of its RGB distance to
+// the centre pixel.
+fn bilateral_pass(
+    source: &image::RgbaImage,
+    spatial: &[f32],
+    range: &[f32],
+    horizontal: bool,
+) -> image::RgbaImage {
+    let radius = (spatial.len() / 2) as i64;
+    let (width, height) = source.dimensions();
+    let mut output = image::RgbaImage::new(width, height);
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        let centre = source.get_pixel(x, y).0;
+        let mut sum = [0f32; 4];
+        let mut total = 0f32;
+        for (tap, &spatial_weight) in spatial.iter().enumerate() {
+            let offset = tap as i64 - radius;
+            let (sx, sy) = if horizontal {
+                ((x as i64 + offset).clamp(0, width as i64 - 1) as u32, y)
+            } else {
+                (x, (y as i64 + offset).clamp(0, height as i64 - 1) as u32)
+            };
+            let sample = source.get_pixel(sx, sy).0;
+            let distance = sample
+                .iter()
+                .zip(&centre)
+                .take(3)
+                .map(|(&a, &b)| (a as f32 - b as f32).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            let weight = spatial_weight * range[distance.round() as usize];
+            for (acc, &value) in sum.iter_mut().zip(&sample) {
+                *acc += value as f32 * weight;
+            }
+            total += weight;
+        }
+        // The centre tap always has weight 1, so total is never zero
+        *pixel = image::Rgba(sum.map(|value| (value / total).round().clamp(0.0, 255.0) as u8));
+    }
+    output
+}
+
+// Separable bilateral approximation: a horizontal then a vertical pass, run
+// on 512x512 tiles padded by the kernel radius so seams match an untiled
+// pass exactly
+fn bilateral_filter(
+    img: &image::DynamicImage,
+    diameter: u32,
+    sigma_color: f32,
+    sigma_space: f32,
+) -> image::DynamicImage {
+    let diameter = diameter.clamp(3, 31) | 1;
+    let radius = diameter / 2;
+    let sigma_space = sigma_space.max(0.1);
+    let sigma_color = sigma_color.max(0.1);
+    let spatial: Vec<f32> = (0..diameter)
+        .map(|tap| {
+            let offset = tap as f32 - radius as f32;
+            (-offset * offset / (2.0 * sigma_space * sigma_space)).exp()
+        })
+        .collect();
+    let range: Vec<f32> = (0..=BILATERAL_MAX_DISTANCE)
+        .map(|distance| {
+            let distance = distance as f32;
+            (-distance * distance / (2.0 * sigma_color * sigma_color)).exp()
+        })
+        .collect();
+
+    let source = img.to_rgba8();
+    let (width, height) = source.dimensions();
+    let tiles: Vec<(u32, u32)> = (0..height)
+        .step_by(BILATERAL_TILE_SIZE as usize)
+        .flat_map(|y| {
+            (0..width)
+                .step_by(BILATERAL_TILE_SIZE as usize)
+                .map(move |x| (x, y))
+        })
+        .collect();
+    let filtered: Vec<(u32, u32, image::RgbaImage)> = tiles
+        .into_par_iter()
+        .map(|(x, y)| {
+            let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let right = (x + BILATERAL_TILE_SIZE + radius).min(width);
+            let bottom = (y + BILATERAL_TILE_SIZE + radius).min(height);
+            let padded = image::imageops::crop_imm(&source, left, top, right - left, bottom - top)
+                .to_image();
+            let rows = bilateral_pass(&padded, &spatial, &range, true);
+            let both = bilateral_pass(&rows, &spatial, &range, false);
+
+            let tile_width = BILATERAL_TILE_SIZE.min(width - x);
+            let tile_height = BILATERAL_TILE_SIZE.min(height - y);
+            let tile = image::imageops::crop_imm(&both, x - left, y - top, tile_width, tile_height)
+                .to_image();
+            (x, y, tile)
+        })
+        .collect();
+
+    let mut output = image::RgbaImage::new(width, height);
+    for (x, y, tile) in filtered {
+        image::imageops::replace(&mut output, &tile, x as i64, y as i64);
+    }
+    image::DynamicImage::ImageRgba8(output)
+}
+
+#[tauri::command]
+async fn apply_bilateral_filter(
+    path: String,
+    diameter: u32,
+    sigma_color: f32,
+    sigma_space: f32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        |img| bilateral_filter(&img, diameter, sigma_color, sigma_space),
+    )
+}
+
+const ESTIMATE_PROGRESS_INTERVAL: u32 = 50;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SizeEstimate {
+    path: String,
+    current_bytes: u64,
+    estimated_bytes: u64,
+    savings_percent: f64,
+    confidence: String,
+}
+
+// Same-format JPEG estimates track the real encoder closely; palette
+// quantisation makes PNG the least predictable
+fn estimate_confidence(source: ImageFormat, target: ImageFormat) -> &'static str {
+    match (source, target) {
+        (ImageFormat::Jpeg, ImageFormat::Jpeg) => "high",
+        (ImageFormat::Png, ImageFormat::Png) => "low",
+        _ => "medium",
+    }
+}
+
+fn estimate_file(path: &str, config: &CompressionConfig) -> anyhow::Result<SizeEstimate> {
+    let source_path = Path::new(path);
+    let current_bytes = fs::metadata(source_path)?.len();
+    let source_format = detect_image_format(source_path)?;
+    let target_format = match config.output_format.as_deref() {
+        Some(name) => format_from_name(name)?,
+        None => source_format,
+    };
+    let img = resize_for_config(image::open(source_path)?, config, true);
+    let estimated_bytes = estimate_compressed_size(&img, target_format, config)?;
+
+    // Negative when the output is predicted to grow
+    let savings_percent = if current_bytes > 0 {
+        (1.0 - estimated_bytes as f64 / current_bytes as f64) * 100.0
+    } else {
+        0.0
+    };
+    Ok(SizeEstimate {
+        path: path.to_string(),
+        current_bytes,
+        estimated_bytes,
+        savings_percent,
+        confidence: estimate_confidence(source_format, target_format).to_string(),
+    })
+}
+
+#[tauri::command]
+async fn get_compression_estimate_batch(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    config_json: String,
+) -> std::result::Result<Vec<SizeEstimate>, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let total = paths.len() as u32;
+    let done = std::sync::atomic::AtomicU32::new(0);
+
+    Ok(paths
+        .par_iter()
+        .map(|path| {
+            let estimate = estimate_file(path, &config).unwrap_or_else(|e| {
+                log::warn!("Could not estimate {}: {}", path, e);
+                SizeEstimate {
+                    path: path.clone(),
+                    current_bytes: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                    estimated_bytes: 0,
+                    savings_percent: 0.0,
+                    confidence: "error".to_string(),
+                }
+            });
+
+            let current = done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            if current % ESTIMATE_PROGRESS_INTERVAL == 0 || current == total {
+                let _ = app.emit(
+                    "estimate-progress",
+                    ProgressEvent {
+                        current,
+                        total,
+                        path: path.clone(),
+                    },
+                );
+            }
+            estimate
+        })
+        .collect())
+}
+
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum EdgeSharpenMethod {
+    Laplacian,
+    Roberts,
+    Sobel,
+}
+
+// Negative Laplacian: adding its response to the image sharpens it
+const LAPLACIAN_KERNEL: [i32; 9] = [0, -1, 0, -1, 4, -1, 0, -1, 0];
+// Roberts cross, embedded in 3x3 kernels for filter3x3
+const ROBERTS_X_KERNEL: [i32; 9] = [0, 0, 0, 0, 1, 0, 0, 0, -1];
+const ROBERTS_Y_KERNEL: [i32; 9] = [0, 0, 0, 0, 0, 1, 0, -1, 0];
+// Sobel responses reach 4 * 255; scale them back to pixel units
+const SOBEL_SCALE: f32 = 0.25;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EdgeSharpenResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    pixels_modified_percent: f64,
+}
+
+// Per-pixel luma boost, added to all three colour channels so hues stay put.
+// Gradient methods boost by the gradient magnitude, signed by the Laplacian
+// so the dark side of an edge gets darker and the light side lighter.
+fn edge_sharpen(
+    img: &image::DynamicImage,
+    method: EdgeSharpenMethod,
+    strength: f32,
+    threshold: u8,
+) -> (image::DynamicImage, f64) {
+    use imageproc::filter::filter3x3;
+
+    let strength = strength.clamp(0.0, 5.0);
+    let luma = img.to_luma8();
+    let laplacian = filter3x3::<_, _, i16>(&luma, &LAPLACIAN_KERNEL);
+    let boosts: Vec<f32> = match method {
+        EdgeSharpenMethod::Laplacian => laplacian
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 * strength)
+            .collect(),
+        EdgeSharpenMethod::Roberts | EdgeSharpenMethod::Sobel => {
+            let (gx, gy, scale, min_magnitude) = match method {
+                EdgeSharpenMethod::Sobel => (
+                    imageproc::gradients::horizontal_sobel(&luma),
+                    imageproc::gradients::vertical_sobel(&luma),
+                    SOBEL_SCALE,
+                    threshold as f32,
+                ),
+                _ => (
+                    filter3x3::<_, _, i16>(&luma, &ROBERTS_X_KERNEL),
+                    filter3x3::<_, _, i16>(&luma, &ROBERTS_Y_KERNEL),
+                    1.0,
+                    0.0,
+                ),
+            };
+            gx.pixels()
+                .zip(gy.pixels())
+                .zip(laplacian.pixels())
+                .map(|((x, y), lap)| {
+                    let magnitude = (x.0[0] as f32).hypot(y.0[0] as f32) * scale;
+                    if magnitude < min_magnitude {
+                        0.0
+                    } else {
+                        magnitude * strength * lap.0[0].signum() as f32
+                    }
+                })
+                .collect()
+        }
+    };
+
+    let mut rgba = img.to_rgba8();
+    let mut modified = 0usize;
+    for (pixel, &boost) in rgba.pixels_mut().zip(&boosts) {
+        if boost.abs() < 0.5 {
+            continue;
+        }
+        let before = *pixel;
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = (*channel as f32 + boost).round().clamp(0.0, 255.0) as u8;
+        }
+        if *pixel != before {
+            modified += 1;
+        }
+    }
+    let percent = modified as f64 / boosts.len().max(1) as f64 * 100.0;
+    (image::DynamicImage::ImageRgba8(rgba), percent)
+}
+
+#[tauri::command]
+async fn apply_edge_sharpen(
+    path: String,
+    method: String,
+    output_path: Option<String>,
+    strength: Option<f32>,
+    threshold: Option<u8>,
+    config_json: Option<String>,
+) -> std::result::Result<EdgeSharpenResult, String> {
+    let method: EdgeSharpenMethod = serde_json::from_value(serde_json::Value::String(method))
+        .map_err(|e| format!("invalid_method: {}", e))?;
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (sharpened, pixels_modified_percent) = edge_sharpen(
+        &img,
+        method,
+        strength.unwrap_or(1.0),
+        threshold.unwrap_or(0),
+    );
+    drop(img);
+
+    let result = save_processed_image(
+        &sharpened,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(EdgeSharpenResult {
+        result,
+        pixels_modified_percent,
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CorruptionReport {
+    path: String,
+    is_valid: bool,
+    error_kind: Option<String>,
+    file_size_bytes: u64,
+    truncated: bool,
+}
+
+// Whether the stream stops before its end marker: JPEG EOI (FF D9) after the
+// last scan, PNG IEND, or a RIFF size running past the end of a WebP. Data
+// appended after EOI is fine, and the last SOS (FF DA) keeps an EXIF
+// thumbnail's EOI from vouching for a cut-off main image.
+fn is_truncated(bytes: &[u8], format: ImageFormat) -> bool {
+    match format {
+        ImageFormat::Jpeg => {
+            let last_marker = |marker: u8| bytes.windows(2).rposition(|w| w == [0xFF, marker]);
+            match (last_marker(0xDA), last_marker(0xD9)) {
+                (Some(scan), Some(eoi)) => eoi < scan,
+                (_, eoi) => eoi.is_none(),
+            }
+        }
+        ImageFormat::Png => match img_parts::png::Png::from_bytes(bytes.to_vec().into()) {
+            Ok(png) => png.chunk_by_type(*b"IEND").is_none(),
+            Err(_) => true,
+        },
+        ImageFormat::WebP => {
+            bytes.len() < 12
+                || u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize + 8
+                    > bytes.len()
+        }
+        _ => false,
+    }
+}
+
+fn image_error_kind(err: &image::ImageError) -> &'static str {
+    match err {
+        image::ImageError::Decoding(_) => "decode_error",
+        image::ImageError::Unsupported(_) => "unsupported_format",
+        image::ImageError::Limits(_) => "limits_exceeded",
+        image::ImageError::IoError(_) => "io_error",
+        _ => "other",
+    }
+}
+
+fn check_image_integrity(path: &str) -> CorruptionReport {
+    let report =
+        |is_valid: bool, error_kind: Option<&str>, size: u64, truncated: bool| CorruptionReport {
+            path: path.to_string(),
+            is_valid,
+            error_kind: error_kind.map(str::to_string),
+            file_size_bytes: size,
+            truncated,
+        };
+
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return report(false, Some("io_error"), 0, false),
+    };
+    let size = bytes.len() as u64;
+    let truncated = detect_image_format(Path::new(path))
+        .map(|format| is_truncated(&bytes, format))
+        .unwrap_or(false);
+
+    // Some decoders pad out short streams, so a truncated file can still
+    // decode; it is reported as invalid all the same
+    match image::load_from_memory(&bytes) {
+        Err(e) => report(false, Some(image_error_kind(&e)), size, truncated),
+        Ok(img) if img.dimensions().0 == 0 || img.dimensions().1 == 0 => {
+            report(false, Some("zero_dimensions"), size, truncated)
+        }
+        Ok(_) if truncated => report(false, Some("truncated"), size, true),
+        Ok(_) => report(true, None, size, false),
+    }
+}
+
+#[tauri::command]
+async fn detect_corrupt_images(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+) -> std::result::Result<Vec<CorruptionReport>, String> {
+    let total = paths.len() as u32;
+    let done = std::sync::atomic::AtomicU32::new(0);
+    // par_iter keeps the input order when collecting
+    Ok(paths
+        .par_iter()
+        .map(|path| {
+            let report = check_image_integrity(path);
+            let current = done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            let _ = app.emit(
+                "validation-progress",
+                ProgressEvent {
+                    current,
+                    total,
+                    path: path.clone(),
+                },
+            );
+            report
+        })
+        .collect())
+}
+
+const STANDARD_ICO_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+const MAX_ICO_SOURCES: usize = 16;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IcoResult {
+    sizes: Vec<(u32, u32)>,
+    output_size_bytes: u64,
+}
+
+fn nearest_ico_size(side: u32) -> u32 {
+    *STANDARD_ICO_SIZES
+        .iter()
+        .min_by_key(|&&size| size.abs_diff(side))
+        .unwrap_or(&256)
+}
+
+// Square images at a standard size, or a power of two up to 256, go in as
+// they are. Anything else is fitted with Lanczos3 into the nearest standard
+// size and centred on a transparent square.
+fn ico_frame(img: &image::DynamicImage) -> image::RgbaImage {
+    let (width, height) = img.dimensions();
+    let side = width.max(height);
+    let accepted = STANDARD_ICO_SIZES.contains(&side) || (side.is_power_of_two() && side <= 256);
+    if width == height && accepted {
+        return img.to_rgba8();
+    }
+
+    let target = nearest_ico_size(side);
+    let fitted = img
+        .resize(target, target, image::imageops::FilterType::Lanczos3)
+        .to_rgba8();
+    let mut square = image::RgbaImage::new(target, target);
+    let x = (target - fitted.width()) / 2;
+    let y = (target - fitted.height()) / 2;
+    image::imageops::replace(&mut square, &fitted, x as i64, y as i64);
+    square
+}
+
+fn write_ico(paths: &[String], output_path: &Path) -> anyhow::Result<IcoResult> {
+    if paths.is_empty() {
+        return Err(anyhow!("No source images given"));
+    }
+    if paths.len() > MAX_ICO_SOURCES {
+        return Err(anyhow!(
+            "ICO files are limited to {} source images, got {}",
+            MAX_ICO_SOURCES,
+            paths.len()
+        ));
+    }
+
+    let mut frames = Vec::new();
+    if let [path] = paths {
+        // One source: downsample it to every standard size it can fill
+        let img = image::open(path)?;
+        let side = img.width().max(img.height());
+        for &size in STANDARD_ICO_SIZES.iter().filter(|&&s| s <= side.max(16)) {
+            let resized = img.resize(size, size, image::imageops::FilterType::Lanczos3);
+            frames.push(ico_frame(&resized));
+        }
+    } else {
+        for path in paths {
+            frames.push(ico_frame(&image::open(path)?));
+        }
+    }
+
+    frames.sort_by_key(|frame| frame.width());
+    frames.dedup_by(|a, b| {
+        let duplicate = a.dimensions() == b.dimensions();
+        if duplicate {
+            log::warn!("Dropping duplicate {}x{} ICO entry", a.width(), a.height());
+        }
+        duplicate
+    });
+
+    let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+    let mut sizes = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let (width, height) = frame.dimensions();
+        let image = ico::IconImage::from_rgba_data(width, height, frame.into_raw());
+        icon_dir.add_entry(ico::IconDirEntry::encode(&image)?);
+        sizes.push((width, height));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut data = Vec::new();
+    icon_dir.write(&mut data)?;
+    fs::write(output_path, &data)?;
+
+    Ok(IcoResult {
+        sizes,
+        output_size_bytes: data.len() as u64,
+    })
+}
+
+#[tauri::command]
+async fn create_ico_from_images(
+    paths: Vec<String>,
+    output_path: String,
+) -> std::result::Result<IcoResult, String> {
+    write_ico(&paths, Path::new(&output_path)).map_err(|e| e.to_string())
+}
+
+// Works for CUR files too: they share the ICO directory layout. The ico crate
+// decodes both PNG and BMP entries to RGBA.
+fn extract_ico_file(
+    path: &Path,
+    output_dir: &Path,
+    format: Option<String>,
+) -> anyhow::Result<Vec<FrameInfo>> {
+    let format = format.unwrap_or_else(|| "png".to_string());
+    let output_format = format_from_name(&format)?;
+    let config = CompressionConfig {
+        lossless: true,
+        output_format: Some(format),
+        ..CompressionConfig::default()
+    };
+
+    let icon_dir = ico::IconDir::read(fs::File::open(path)?)
+        .map_err(|e| anyhow!("Not an ICO or CUR file: {}", e))?;
+    fs::create_dir_all(output_dir)?;
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("icon");
+
+    let mut used_names = std::collections::HashSet::new();
+    let mut frames = Vec::with_capacity(icon_dir.entries().len());
+    for (index, entry) in icon_dir.entries().iter().enumerate() {
+        let icon = entry.decode()?;
+        let (width, height) = (icon.width(), icon.height());
+        let rgba = image::RgbaImage::from_raw(width, height, icon.rgba_data().to_vec())
+            .ok_or_else(|| anyhow!("ICO entry {} has a pixel size mismatch", index))?;
+
+        // Icons may hold one size at several bit depths
+        let mut name = format!("{}_{}x{}", stem, width, height);
+        if !used_names.insert(name.clone()) {
+            name = format!("{}_{}", name, index);
+            used_names.insert(name.clone());
+        }
+        let frame_path = output_dir.join(format!("{}.{}", name, format_extension(output_format)));
+        let saved = save_processed_image_with(
+            &image::DynamicImage::ImageRgba8(rgba),
+            &frame_path,
+            None,
+            &config,
+        )?;
+        frames.push(FrameInfo {
+            index: index as u32,
+            path: saved.output_path,
+            width,
+            height,
+            delay_ms: 0,
+            size_bytes: saved.size_bytes,
+        });
+    }
+    Ok(frames)
+}
+
+#[tauri::command]
+async fn extract_ico_frames(
+    path: String,
+    output_dir: String,
+    format: Option<String>,
+) -> std::result::Result<Vec<FrameInfo>, String> {
+    extract_ico_file(Path::new(&path), Path::new(&output_dir), format).map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RoundCornersResult {
+    width: u32,
+    height: u32,
+    corner_radius: u32,
+    output_path: Option<String>,
+}
+
+// Mask that is opaque everywhere except outside the four corner arcs
+fn round_corner_mask(width: u32, height: u32, radius: u32) -> image::GrayImage {
+    use imageproc::drawing::{draw_filled_circle_mut, draw_filled_rect_mut};
+    use imageproc::rect::Rect;
+
+    let mut mask = image::GrayImage::from_pixel(width, height, image::Luma([255]));
+    let (w, h, r) = (width as i32, height as i32, radius as i32);
+    for (corner_x, corner_y, centre_x, centre_y) in [
+        (0, 0, r, r),
+        (w - r, 0, w - 1 - r, r),
+        (0, h - r, r, h - 1 - r),
+        (w - r, h - r, w - 1 - r, h - 1 - r),
+    ] {
+        draw_filled_rect_mut(
+            &mut mask,
+            Rect::at(corner_x, corner_y).of_size(radius, radius),
+            image::Luma([0]),
+        );
+        draw_filled_circle_mut(&mut mask, (centre_x, centre_y), r, image::Luma([255]));
+    }
+    mask
+}
+
+// Saved as PNG, or WebP when `output_path` asks for it; JPEG has no alpha
+// channel to carry the transparent corners
+#[tauri::command]
+async fn apply_round_corners(
+    path: String,
+    radius: u32,
+    output_path: Option<String>,
+) -> std::result::Result<RoundCornersResult, String> {
+    let source_path = Path::new(&path);
+    let format = match output_path.as_deref() {
+        Some(out) => detect_image_format(Path::new(out)).map_err(|e| e.to_string())?,
+        None => ImageFormat::Png,
+    };
+    if format == ImageFormat::Jpeg {
+        return Err("unsupported_format: JPEG cannot store transparent corners".to_string());
+    }
+
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (width, height) = img.dimensions();
+    if radius == 0 {
+        return Ok(RoundCornersResult {
+            width,
+            height,
+            corner_radius: 0,
+            output_path: None,
+        });
+    }
+
+    let radius = radius.clamp(1, (width.min(height) / 2).max(1));
+    let mask = round_corner_mask(width, height, radius);
+    let mut rgba = img.into_rgba8();
+    for (pixel, coverage) in rgba.pixels_mut().zip(mask.pixels()) {
+        pixel.0[3] = (pixel.0[3] as u16 * coverage.0[0] as u16 / 255) as u8;
+    }
+
+    let config = CompressionConfig {
+        lossless: true,
+        output_format: Some(format_name(format).to_string()),
+        ..CompressionConfig::default()
+    };
+    let saved = save_processed_image_with(
+        &image::DynamicImage::ImageRgba8(rgba),
+        source_path,
+        output_path.as_deref(),
+        &config,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(RoundCornersResult {
+        width,
+        height,
+        corner_radius: radius,
+        output_path: Some(saved.output_path),
+    })
+}
+
+const MAX_QUALITY_LEVELS: usize = 20;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QualityDataPoint {
+    quality: u8,
+    size_bytes: u64,
+    ssim: f64,
+    encode_ms: u64,
+}
+
+// Quality-vs-size curve for slider UIs. Each level is one direct encode (no
+// JPEG candidate search) decoded again for a luma SSIM against the source.
+#[tauri::command]
+async fn measure_encode_quality(
+    app: tauri::AppHandle,
+    path: String,
+    quality_levels: Vec<u8>,
+    format: String,
+) -> std::result::Result<Vec<QualityDataPoint>, String> {
+    if !matches!(format.as_str(), "jpeg" | "webp" | "png") {
+        return Err(format!(
+            "unsupported_format: expected jpeg, webp or png, got {}",
+            format
+        ));
+    }
+    let mut levels: Vec<u8> = quality_levels
+        .into_iter()
+        .take(MAX_QUALITY_LEVELS)
+        .map(|quality| quality.clamp(1, 100))
+        .collect();
+    levels.sort_unstable();
+    levels.dedup();
+
+    let source_path = Path::new(&path);
+    let original = image::open(source_path).map_err(|e| format!("decode_failed: {}", e))?;
+    let (width, height) = (original.width() as usize, original.height() as usize);
+    let [original_luma, _, _] = ycbcr_planes(&original.to_rgb8());
+
+    let total = levels.len() as u32;
+    let mut points = Vec::with_capacity(levels.len());
+    for (index, quality) in levels.into_iter().enumerate() {
+        let config = CompressionConfig {
+            lossless: false,
+            quality_jpg: quality,
+            quality_webp: quality,
+            quality_png: quality,
+            // SSIM compares the planes pixel for pixel, so never resize
+            resize_width: None,
+            resize_height: None,
+            output_format: Some(format.clone()),
+            jpeg_strategy: Some(JpegStrategy::Direct),
+            ..CompressionConfig::default()
+        };
+        let started = std::time::Instant::now();
+        let compressed =
+            compress_to_memory_with(source_path, &config, true, Some(original.clone()))
+                .map_err(|e| e.to_string())?;
+        let encode_ms = started.elapsed().as_millis() as u64;
+
+        let decoded = image::load_from_memory(&compressed.data).map_err(|e| e.to_string())?;
+        if decoded.dimensions() != original.dimensions() {
+            return Err(format!(
+                "dimension_mismatch: encoded {:?}, source {:?}",
+                decoded.dimensions(),
+                original.dimensions()
+            ));
+        }
+        let [luma, _, _] = ycbcr_planes(&decoded.to_rgb8());
+        points.push(QualityDataPoint {
+            quality,
+            size_bytes: compressed.data.len() as u64,
+            ssim: plane_ssim(&original_luma, &luma, width, height),
+            encode_ms,
+        });
+
+        let _ = app.emit(
+            "quality-curve-progress",
+            ProgressEvent {
+                current: index as u32 + 1,
+                total,
+                path: path.clone(),
+            },
+        );
+    }
+    Ok(points)
+}
+
+#[derive(serde::Serialize)]
+struct ViewportRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ZoomPanResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    crop: ViewportRect,
+}
+
+// At zoom 1 the viewport is the largest rectangle with the output's aspect
+// ratio; zooming shrinks it. It is centred `pan` (-0.5 to 0.5) of the image
+// size away from the middle and slid back inside the bounds if needed.
+fn zoom_pan_viewport(
+    (width, height): (u32, u32),
+    (out_width, out_height): (u32, u32),
+    zoom: f32,
+    pan_x: f32,
+    pan_y: f32,
+) -> ViewportRect {
+    let out_aspect = out_width as f32 / out_height as f32;
+    let (base_w, base_h) = if width as f32 / height as f32 > out_aspect {
+        (height as f32 * out_aspect, height as f32)
+    } else {
+        (width as f32, width as f32 / out_aspect)
+    };
+    let axis = |size: u32, base: f32, pan: f32| {
+        let length = ((base / zoom).round() as u32).clamp(1, size);
+        let centre = size as f32 * (0.5 + pan.clamp(-0.5, 0.5));
+        let start = (centre - length as f32 / 2.0).round().max(0.0) as u32;
+        (start.min(size - length), length)
+    };
+    let (x, w) = axis(width, base_w, pan_x);
+    let (y, h) = axis(height, base_h, pan_y);
+    ViewportRect { x, y, w, h }
+}
+
+#[tauri::command]
+async fn apply_zoom_pan_crop(
+    path: String,
+    zoom: f32,
+    pan_x: f32,
+    pan_y: f32,
+    output_size: (u32, u32),
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ZoomPanResult, String> {
+    // Zooming out would need padding around the image
+    if !zoom.is_finite() || zoom < 1.0 {
+        return Err(format!(
+            "invalid_zoom: zoom must be at least 1.0, got {}",
+            zoom
+        ));
+    }
+    let (out_width, out_height) = output_size;
+    if out_width == 0 || out_height == 0 {
+        return Err("invalid_size: output size must be non-zero".to_string());
+    }
+
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (width, height) = img.dimensions();
+    let crop = zoom_pan_viewport((width, height), output_size, zoom, pan_x, pan_y);
+    let viewport = img.crop_imm(crop.x, crop.y, crop.w, crop.h).resize_exact(
+        out_width,
+        out_height,
+        image::imageops::FilterType::Lanczos3,
+    );
+    drop(img);
+
+    let result = save_processed_image(
+        &viewport,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(ZoomPanResult { result, crop })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MosaicResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    regions_applied: u32,
+}
+
+// Pixelate each `[x, y, width, height]` region, clipped to the image. Regions
+// left empty by clipping are skipped.
+fn mosaic_regions(img: &mut image::DynamicImage, regions: &[[u32; 4]], tile_size: u32) -> u32 {
+    let nearest = image::imageops::FilterType::Nearest;
+    let tile_size = tile_size.clamp(2, 64);
+    let (width, height) = img.dimensions();
+    let mut applied = 0;
+    for &[x, y, w, h] in regions {
+        if x >= width || y >= height {
+            continue;
+        }
+        let w = w.min(width - x);
+        let h = h.min(height - y);
+        if w == 0 || h == 0 {
+            continue;
+        }
+
+        let blocks = img.crop_imm(x, y, w, h).resize_exact(
+            (w / tile_size).max(1),
+            (h / tile_size).max(1),
+            nearest,
+        );
+        let mosaic = blocks.resize_exact(w, h, nearest);
+        // Replace rather than overlay: blending semi-transparent blocks over
+        // the original would let the censored detail show through
+        image::imageops::replace(img, &mosaic, x as i64, y as i64);
+        applied += 1;
+    }
+    applied
+}
+
+#[tauri::command]
+async fn apply_mosaic_censor(
+    path: String,
+    regions: Vec<[u32; 4]>,
+    tile_size: u32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<MosaicResult, String> {
+    let source_path = Path::new(&path);
+    let mut img = image::open(source_path).map_err(|e| e.to_string())?;
+    let regions_applied = mosaic_regions(&mut img, &regions, tile_size);
+    let result = save_processed_image(
+        &img,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(MosaicResult {
+        result,
+        regions_applied,
+    })
+}
+
+// Parse and range-check a config, returning it with every default filled in
+#[tauri::command]
+fn parse_compression_config(config_json: String) -> std::result::Result<CompressionConfig, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    validate_config(&config).map_err(|errors| {
+        let details: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+        format!("Invalid compression config: {}", details.join("; "))
+    })?;
+    Ok(config)
+}
+
+// The fields that differ between two configs, for the preset editor
+#[tauri::command]
+fn diff_compression_configs(
+    a_json: String,
+    b_json: String,
+) -> std::result::Result<Vec<ConfigFieldChange>, String> {
+    let a = CompressionConfig::from_json(&a_json).map_err(|e| e.to_string())?;
+    let b = CompressionConfig::from_json(&b_json).map_err(|e| e.to_string())?;
+    Ok(config_diff(&a, &b))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TempDirInfo {
+    path: String,
+    size_bytes: u64,
+    age_seconds: u64,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TempDirUsage {
+    base_dir: String,
+    tracked_dirs: u32,
+    total_bytes: u64,
+    oldest_dir_age_seconds: u64,
+    dirs: Vec<TempDirInfo>,
+}
+
+// Per-operation directories under Ximage-compress and Ximage-upload. Ages
+// come from TEMP_DIRS when the directory is tracked and from its mtime
+// otherwise, so orphans left by earlier runs are counted too.
+#[tauri::command]
+async fn get_temp_directory_usage() -> std::result::Result<TempDirUsage, String> {
+    let base_dir = std::env::temp_dir();
+    let tracked = tracked_temp_dirs();
+    let now = std::time::SystemTime::now();
+    let age = |time: std::time::SystemTime| now.duration_since(time).map_or(0, |d| d.as_secs());
+
+    let mut dirs = Vec::new();
+    let mut tracked_dirs = 0;
+    for root in [COMPRESS_TEMP_DIR, UPLOAD_TEMP_DIR] {
+        let Ok(entries) = fs::read_dir(base_dir.join(root)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let key = path.to_string_lossy().to_string();
+            let created = match tracked.get(&key) {
+                Some(&created) => {
+                    tracked_dirs += 1;
+                    Some(created)
+                }
+                None => entry.metadata().and_then(|m| m.modified()).ok(),
+            };
+            let size_bytes = walkdir::WalkDir::new(&path)
+                .into_iter()
+                .flatten()
+                .filter(|file| file.file_type().is_file())
+                .filter_map(|file| file.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            dirs.push(TempDirInfo {
+                path: key,
+                size_bytes,
+                age_seconds: created.map_or(0, age),
+            });
+        }
+    }
+
+    Ok(TempDirUsage {
+        base_dir: base_dir.to_string_lossy().to_string(),
+        tracked_dirs,
+        total_bytes: dirs.iter().map(|dir| dir.size_bytes).sum(),
+        oldest_dir_age_seconds: dirs.iter().map(|dir| dir.age_seconds).max().unwrap_or(0),
+        dirs,
+    })
+}
+
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum EqualizationMethod {
+    Global,
+    Clahe,
+}
+
+const DEFAULT_CLAHE_TILE_SIZE: u32 = 64;
+const DEFAULT_CLAHE_CLIP_LIMIT: f32 = 2.0;
+
+// Equalizing lookup table from a histogram: the CDF stretched to 0-255
+fn equalization_lut(histogram: &[u32; 256]) -> [u8; 256] {
+    let total: u32 = histogram.iter().sum();
+    let cdf_min = histogram
+        .iter()
+        .copied()
+        .find(|&count| count > 0)
+        .unwrap_or(0);
+    let range = (total - cdf_min).max(1) as f32;
+    let mut cumulative = 0;
+    std::array::from_fn(|value| {
+        cumulative += histogram[value];
+        (cumulative.saturating_sub(cdf_min) as f32 / range * 255.0).round() as u8
+    })
+}
+
+// CLAHE clipping: counts above `clip_limit` times the mean bin height are cut
+// and spread evenly over all bins
+fn clip_histogram(histogram: &mut [u32; 256], clip_limit: f32) {
+    let total: u32 = histogram.iter().sum();
+    let limit = ((clip_limit * total as f32 / 256.0).ceil() as u32).max(1);
+    let mut excess = 0;
+    for count in histogram.iter_mut() {
+        if *count > limit {
+            excess += *count - limit;
+            *count = limit;
+        }
+    }
+    let (share, remainder) = (excess / 256, (excess % 256) as usize);
+    for (value, count) in histogram.iter_mut().enumerate() {
+        *count += share + u32::from(value < remainder);
+    }
+}
+
+// Equalize BT.601 luma only. Shifting R, G and B by the same amount moves Y
+// by that amount and leaves U and V alone, so the conversion back to RGB is
+// just adding the luma change to each channel.
+fn equalize_histogram(
+    img: &image::DynamicImage,
+    method: EqualizationMethod,
+    tile_size: u32,
+    clip_limit: f32,
+) -> image::DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let luma: Vec<u8> = rgba
+        .pixels()
+        .map(|p| {
+            let [r, g, b, _] = p.0.map(|c| c as f32);
+            (0.299 * r + 0.587 * g + 0.114 * b)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+        .collect();
+
+    let mapped: Vec<f32> = match method {
+        EqualizationMethod::Global => {
+            let mut histogram = [0u32; 256];
+            for &value in &luma {
+                histogram[value as usize] += 1;
+            }
+            let lut = equalization_lut(&histogram);
+            luma.iter()
+                .map(|&value| lut[value as usize] as f32)
+                .collect()
+        }
+        EqualizationMethod::Clahe => {
+            let tile = tile_size.clamp(8, width.max(height).max(8));
+            let clip_limit = clip_limit.clamp(1.0, 40.0);
+            let (tiles_x, tiles_y) = (width.div_ceil(tile), height.div_ceil(tile));
+            let mut luts = Vec::with_capacity((tiles_x * tiles_y) as usize);
+            for tile_y in 0..tiles_y {
+                for tile_x in 0..tiles_x {
+                    let mut histogram = [0u32; 256];
+                    for y in tile_y * tile..((tile_y + 1) * tile).min(height) {
+                        for x in tile_x * tile..((tile_x + 1) * tile).min(width) {
+                            histogram[luma[(y * width + x) as usize] as usize] += 1;
+                        }
+                    }
+                    clip_histogram(&mut histogram, clip_limit);
+                    luts.push(equalization_lut(&histogram));
+                }
+            }
+
+            // Bilinear blend of the four nearest tile mappings, measured
+            // between tile centres
+            let neighbours = |position: u32, count: u32| {
+                let f =
+                    ((position as f32 + 0.5) / tile as f32 - 0.5).clamp(0.0, (count - 1) as f32);
+                let low = f.floor() as u32;
+                (low, (low + 1).min(count - 1), f - low as f32)
+            };
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .zip(&luma)
+                .map(|((x, y), &value)| {
+                    let (x0, x1, wx) = neighbours(x, tiles_x);
+                    let (y0, y1, wy) = neighbours(y, tiles_y);
+                    let at = |tx: u32, ty: u32| {
+                        luts[(ty * tiles_x + tx) as usize][value as usize] as f32
+                    };
+                    let top = at(x0, y0) * (1.0 - wx) + at(x1, y0) * wx;
+                    let bottom = at(x0, y1) * (1.0 - wx) + at(x1, y1) * wx;
+                    top * (1.0 - wy) + bottom * wy
+                })
+                .collect()
+        }
+    };
+
+    for ((pixel, &before), &after) in rgba.pixels_mut().zip(&luma).zip(&mapped) {
+        let delta = after - before as f32;
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = (*channel as f32 + delta).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    image::DynamicImage::ImageRgba8(rgba)
+}
+
+#[tauri::command]
+async fn apply_histogram_equalization(
+    path: String,
+    method: String,
+    output_path: Option<String>,
+    tile_size: Option<u32>,
+    clip_limit: Option<f32>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    let method: EqualizationMethod = serde_json::from_value(serde_json::Value::String(method))
+        .map_err(|e| format!("invalid_method: {}", e))?;
+    transform_and_save(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        |img| {
+            equalize_histogram(
+                &img,
+                method,
+                tile_size.unwrap_or(DEFAULT_CLAHE_TILE_SIZE),
+                clip_limit.unwrap_or(DEFAULT_CLAHE_CLIP_LIMIT),
+            )
+        },
+    )
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GammaResult {
+    status: String,
+    output_path: Option<String>,
+}
+
+// `per_channel_gamma` overrides `gamma` with separate R, G and B values for
+// colour cast correction. Gamma above 1 brightens midtones, below 1 darkens.
+#[tauri::command]
+async fn apply_gamma_correction(
+    path: String,
+    gamma: f32,
+    output_path: Option<String>,
+    per_channel_gamma: Option<[f32; 3]>,
+    config_json: Option<String>,
+) -> std::result::Result<GammaResult, String> {
+    let gamma = per_channel_gamma
+        .unwrap_or([gamma; 3])
+        .map(|value| value.clamp(0.1, 10.0));
+    if gamma.iter().all(|&value| value == 1.0) {
+        return Ok(GammaResult {
+            status: "no_op".to_string(),
+            output_path: None,
+        });
+    }
+
+    let saved = apply_effect(
+        &path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+        PixelTransform::Gamma { gamma },
+    )?;
+    Ok(GammaResult {
+        status: "success".to_string(),
+        output_path: Some(saved.output_path),
+    })
+}
+
+const DEBLOCK_BLOCK_SIZE: u32 = 8;
+// 1-2-1 smoothing across a vertical boundary (horizontal taps) or across a
+// horizontal boundary (vertical taps)
+const DEBLOCK_HORIZONTAL_KERNEL: [f32; 9] = [0.0, 0.0, 0.0, 0.25, 0.5, 0.25, 0.0, 0.0, 0.0];
+const DEBLOCK_VERTICAL_KERNEL: [f32; 9] = [0.0, 0.25, 0.0, 0.0, 0.5, 0.0, 0.0, 0.25, 0.0];
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeblockResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    blocks_processed: u32,
+    boundaries_smoothed: u32,
+}
+
+// One boundary direction. Each 8-pixel boundary segment is scored by its luma
+// step minus the activity just inside the two blocks; a step that stands out
+// from otherwise flat blocks is a compression artifact rather than an edge.
+// Segments scoring above `threshold` have the two pixels either side blended
+// towards the smoothed image, the outer pair at half weight.
+fn deblock_pass(
+    rgba: &mut image::RgbaImage,
+    vertical_boundaries: bool,
+    strength: f32,
+    threshold: u8,
+) -> u32 {
+    let kernel = if vertical_boundaries {
+        &DEBLOCK_HORIZONTAL_KERNEL
+    } else {
+        &DEBLOCK_VERTICAL_KERNEL
+    };
+    let smoothed = imageproc::filter::filter3x3::<_, f32, u8>(&*rgba, kernel);
+    let luma = image::imageops::grayscale(&*rgba);
+    let (width, height) = rgba.dimensions();
+    let (along, across) = if vertical_boundaries {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    // Map (offset across the boundary, position along it) to (x, y)
+    let at = |c: u32, a: u32| {
+        if vertical_boundaries {
+            (c, a)
+        } else {
+            (a, c)
+        }
+    };
+
+    let mut smoothed_count = 0;
+    for boundary in
+        (DEBLOCK_BLOCK_SIZE..across.saturating_sub(1)).step_by(DEBLOCK_BLOCK_SIZE as usize)
+    {
+        for start in (0..along).step_by(DEBLOCK_BLOCK_SIZE as usize) {
+            let end = (start + DEBLOCK_BLOCK_SIZE).min(along);
+            let (mut step, mut activity) = (0.0, 0.0);
+            for a in start..end {
+                let sample = |c: u32| {
+                    let (x, y) = at(c, a);
+                    luma.get_pixel(x, y).0[0] as f32
+                };
+                let (p1, p0, q0, q1) = (
+                    sample(boundary - 2),
+                    sample(boundary - 1),
+                    sample(boundary),
+                    sample(boundary + 1),
+                );
+                step += (p0 - q0).abs();
+                activity += ((p1 - p0).abs() + (q0 - q1).abs()) / 2.0;
+            }
+            if (step - activity) / (end - start) as f32 <= threshold as f32 {
+                continue;
+            }
+
+            for a in start..end {
+                for (c, weight) in [
+                    (boundary - 2, 0.5),
+                    (boundary - 1, 1.0),
+                    (boundary, 1.0),
+                    (boundary + 1, 0.5),
+                ] {
+                    let (x, y) = at(c, a);
+                    let target = smoothed.get_pixel(x, y);
+                    let pixel = rgba.get_pixel_mut(x, y);
+                    for (channel, &smooth) in pixel.0.iter_mut().zip(&target.0).take(3) {
+                        let blended =
+                            *channel as f32 + (smooth as f32 - *channel as f32) * strength * weight;
+                        *channel = blended.round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+            smoothed_count += 1;
+        }
+    }
+    smoothed_count
+}
+
+// `strength` (0-1) scales the blend; `threshold` defaults to 16
+#[tauri::command]
+async fn apply_dct_deblock(
+    path: String,
+    strength: f32,
+    output_path: Option<String>,
+    threshold: Option<u8>,
+    config_json: Option<String>,
+) -> std::result::Result<DeblockResult, String> {
+    let strength = strength.clamp(0.0, 1.0);
+    let threshold = threshold.unwrap_or(16);
+    let source_path = Path::new(&path);
+    let mut rgba = image::open(source_path)
+        .map_err(|e| e.to_string())?
+        .into_rgba8();
+
+    let (width, height) = rgba.dimensions();
+    let blocks_processed = width.div_ceil(DEBLOCK_BLOCK_SIZE) * height.div_ceil(DEBLOCK_BLOCK_SIZE);
+    let boundaries_smoothed = deblock_pass(&mut rgba, true, strength, threshold)
+        + deblock_pass(&mut rgba, false, strength, threshold);
+
+    let result = save_processed_image(
+        &image::DynamicImage::ImageRgba8(rgba),
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(DeblockResult {
+        result,
+        blocks_processed,
+        boundaries_smoothed,
+    })
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SubimageResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    cropped_x: u32,
+    cropped_y: u32,
+    cropped_width: u32,
+    cropped_height: u32,
+    output_width: u32,
+    output_height: u32,
+}
+
+// Crop for the before/after zoom comparison. Upscales are nearest-neighbour
+// so individual pixels stay visible, unless `smooth_scale` asks for Lanczos3.
+#[tauri::command]
+async fn extract_subimage(
+    path: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    scale: Option<f32>,
+    output_path: Option<String>,
+    smooth_scale: Option<bool>,
+    config_json: Option<String>,
+) -> std::result::Result<SubimageResult, String> {
+    let scale = scale.unwrap_or(1.0);
+    if !scale.is_finite() || !(0.1..=10.0).contains(&scale) {
+        return Err(format!(
+            "invalid_scale: scale must be between 0.1 and 10.0, got {}",
+            scale
+        ));
+    }
+
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (image_width, image_height) = img.dimensions();
+    let fits = x
+        .checked_add(width)
+        .is_some_and(|right| right <= image_width)
+        && y.checked_add(height)
+            .is_some_and(|bottom| bottom <= image_height);
+    if width == 0 || height == 0 || !fits {
+        return Err(format!(
+            "out_of_bounds: {}x{} at ({}, {}) does not fit a {}x{} image",
+            width, height, x, y, image_width, image_height
+        ));
+    }
+
+    let mut subimage = img.crop_imm(x, y, width, height);
+    drop(img);
+    let output_width = ((width as f32 * scale).round() as u32).max(1);
+    let output_height = ((height as f32 * scale).round() as u32).max(1);
+    if (output_width, output_height) != (width, height) {
+        let filter = if scale > 1.0 && !smooth_scale.unwrap_or(false) {
+            image::imageops::FilterType::Nearest
+        } else {
+            image::imageops::FilterType::Lanczos3
+        };
+        subimage = subimage.resize_exact(output_width, output_height, filter);
+    }
+
+    let result = save_processed_image(
+        &subimage,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(SubimageResult {
+        result,
+        cropped_x: x,
+        cropped_y: y,
+        cropped_width: width,
+        cropped_height: height,
+        output_width,
+        output_height,
+    })
+}
+
+// Written in full (12 bytes) at the start of every bundle
+const BUNDLE_MAGIC: &[u8; 12] = b"XIMAGEBUNDLE";
+const BUNDLE_WEBP_METHOD: i32 = 6;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleResult {
+    image_count: u32,
+    total_bundle_bytes: u64,
+    per_image_sizes: Vec<(String, u64)>,
+}
+
+// Little-endian layout: magic, image count, then per image a name length,
+// the UTF-8 name, a data length and the WebP bytes. Names are the source
+// stems with a `.webp` extension, suffixed when two sources share a stem.
+fn pack_webp_bundle(paths: &[String], output_path: &Path) -> anyhow::Result<BundleResult> {
+    let mut bundle = BUNDLE_MAGIC.to_vec();
+    bundle.extend_from_slice(&u32::try_from(paths.len())?.to_le_bytes());
+    let mut per_image_sizes: Vec<(String, u64)> = Vec::with_capacity(paths.len());
+    // Exact, so unpacking gives back the colour under transparent pixels too
+    let config = CompressionConfig {
+        webp_mode: Some(WebpMode::Lossless),
+        webp_exact: true,
+        ..CompressionConfig::default()
+    };
+    for path in paths {
+        let source = Path::new(path);
+        let img = image::open(source).map_err(|e| anyhow!("{}: {}", path, e))?;
+        let data = encode_webp(&img, &config, BUNDLE_WEBP_METHOD)?;
+
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".to_string());
+        let mut name = format!("{}.webp", stem);
+        let mut suffix = 1;
+        while per_image_sizes
+            .iter()
+            .any(|(existing, _)| *existing == name)
+        {
+            name = format!("{}_{}.webp", stem, suffix);
+            suffix += 1;
+        }
+
+        bundle.extend_from_slice(&u32::try_from(name.len())?.to_le_bytes());
+        bundle.extend_from_slice(name.as_bytes());
+        bundle.extend_from_slice(&u32::try_from(data.len())?.to_le_bytes());
+        bundle.extend_from_slice(&data);
+        per_image_sizes.push((name, data.len() as u64));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output_path, &bundle)?;
+    Ok(BundleResult {
+        image_count: per_image_sizes.len() as u32,
+        total_bundle_bytes: bundle.len() as u64,
+        per_image_sizes,
+    })
+}
+
+fn unpack_webp_bundle_file(bundle_path: &Path, output_dir: &Path) -> anyhow::Result<Vec<String>> {
+    fn take<'a>(rest: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+        if rest.len() < len {
+            return Err(anyhow!("Bundle is truncated"));
+        }
+        let (head, tail) = rest.split_at(len);
+        *rest = tail;
+        Ok(head)
+    }
+    fn read_u32(rest: &mut &[u8]) -> anyhow::Result<u32> {
+        let bytes = take(rest, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    let bundle = fs::read(bundle_path)?;
+    let mut rest = bundle
+        .strip_prefix(BUNDLE_MAGIC.as_slice())
+        .ok_or_else(|| anyhow!("Not an image bundle"))?;
+    let count = read_u32(&mut rest)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let name_len = read_u32(&mut rest)? as usize;
+        let name = String::from_utf8(take(&mut rest, name_len)?.to_vec())?;
+        let data_len = read_u32(&mut rest)? as usize;
+        let data = take(&mut rest, data_len)?;
+        // Names come from the file, so refuse anything that could escape
+        // `output_dir`
+        if Path::new(&name).file_name() != Some(std::ffi::OsStr::new(&name)) {
+            return Err(anyhow!("Invalid file name in bundle: {}", name));
+        }
+        // A repeat would silently overwrite the earlier entry on disk
+        if entries.iter().any(|(existing, _)| *existing == name) {
+            return Err(anyhow!("Duplicate file name in bundle: {}", name));
+        }
+        entries.push((name, data));
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let mut written = Vec::with_capacity(entries.len());
+    for (name, data) in entries {
+        let target = output_dir.join(name);
+        fs::write(&target, data)?;
+        written.push(target.to_string_lossy().into_owned());
+    }
+    Ok(written)
+}
+
+#[tauri::command]
+async fn pack_images_to_webp_lossless_bundle(
+    paths: Vec<String>,
+    output_path: String,
+) -> std::result::Result<BundleResult, String> {
+    if paths.is_empty() {
+        return Err("No images to bundle".to_string());
+    }
+    pack_webp_bundle(&paths, Path::new(&output_path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn unpack_webp_bundle(
+    bundle_path: String,
+    output_dir: String,
+) -> std::result::Result<Vec<String>, String> {
+    unpack_webp_bundle_file(Path::new(&bundle_path), Path::new(&output_dir))
+        .map_err(|e| e.to_string())
+}
+
+// A segment length field is 16 bits and counts its own two bytes
+const MAX_JPEG_COMMENT_BYTES: usize = 65533;
+
+// Drop every COM segment and insert a single new one after the APPn headers,
+// where readers expect metadata
+fn replace_jpeg_comment(bytes: Vec<u8>, comment: &str) -> anyhow::Result<Vec<u8>> {
+    use img_parts::jpeg::markers;
+
+    let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.into())?;
+    let segments = jpeg.segments_mut();
+    segments.retain(|segment| segment.marker() != markers::COM);
+    let position = segments
+        .iter()
+        .rposition(|segment| (0xE0..=0xEF).contains(&segment.marker()))
+        .map_or(0, |index| index + 1);
+    segments.insert(
+        position,
+        img_parts::jpeg::JpegSegment::new_with_contents(
+            markers::COM,
+            comment.as_bytes().to_vec().into(),
+        ),
+    );
+
+    let mut output = Vec::new();
+    jpeg.encoder().write_to(&mut output)?;
+    Ok(output)
+}
+
+fn read_jpeg_comment(path: &Path) -> anyhow::Result<Option<String>> {
+    let bytes = fs::read(path)?;
+    if detect_format_from_bytes(&bytes) != "jpeg" {
+        return Err(anyhow!("Comments are only supported for JPEG"));
+    }
+    let jpeg = img_parts::jpeg::Jpeg::from_bytes(bytes.into())?;
+    let Some(segment) = jpeg.segment_by_marker(img_parts::jpeg::markers::COM) else {
+        return Ok(None);
+    };
+    let comment = std::str::from_utf8(segment.contents())
+        .map_err(|_| anyhow!("invalid_utf8: JPEG comment is not valid UTF-8"))?;
+    Ok(Some(comment.to_string()))
+}
+
+#[tauri::command]
+async fn set_jpeg_comment(
+    path: String,
+    comment: String,
+    output_path: Option<String>,
+) -> std::result::Result<(), String> {
+    if comment.len() > MAX_JPEG_COMMENT_BYTES {
+        return Err(format!(
+            "comment_too_long: {} bytes, at most {} fit in a COM segment",
+            comment.len(),
+            MAX_JPEG_COMMENT_BYTES
+        ));
+    }
+    let source_path = Path::new(&path);
+    let bytes = fs::read(source_path).map_err(|e| e.to_string())?;
+    if detect_format_from_bytes(&bytes) != "jpeg" {
+        return Err("Comments are only supported for JPEG".to_string());
+    }
+    let output = replace_jpeg_comment(bytes, &comment).map_err(|e| e.to_string())?;
+
+    let target = output_path.as_deref().map(Path::new).unwrap_or(source_path);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    write_atomic(&CompressionConfig::default(), target, &output).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_jpeg_comment(path: String) -> std::result::Result<Option<String>, String> {
+    read_jpeg_comment(Path::new(&path)).map_err(|e| e.to_string())
+}
+
+const DEFAULT_FOLDER_PATTERNS: [&str; 4] = ["*.jpg", "*.jpeg", "*.png", "*.webp"];
+
+#[derive(Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FolderCompressionSummary {
+    total_files: u32,
+    successful: u32,
+    failed: u32,
+    total_saved_bytes: u64,
+    failed_paths: Vec<String>,
+}
+
+// Case-insensitive file name glob: `*` matches any run of characters, `?`
+// exactly one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Last `*` seen and the name position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Non-recursive counterpart of `compress_recursive_directory` that rewrites
+// matching files in place. Largest files go first so the failures that
+// matter most show up early.
+#[tauri::command]
+async fn compress_folder_in_place(
+    app: tauri::AppHandle,
+    directory: String,
+    config_json: String,
+    file_patterns: Vec<String>,
+) -> std::result::Result<FolderCompressionSummary, String> {
+    let config = CompressionConfig::from_json(&config_json).map_err(|e| e.to_string())?;
+    let patterns: Vec<String> = if file_patterns.is_empty() {
+        DEFAULT_FOLDER_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    } else {
+        file_patterns
+    };
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&directory).map_err(|e| e.to_string())? {
+        let Ok(entry) = entry else { continue };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if metadata.is_file() && patterns.iter().any(|p| wildcard_match(p, &name)) {
+            files.push((entry.path(), metadata.len()));
+        }
+    }
+    files.sort_by(|a, b| b.1.cmp(&a.1));
+    let files: Vec<std::path::PathBuf> = files.into_iter().map(|(path, _)| path).collect();
+
+    let total = files.len() as u32;
+    let mut summary = FolderCompressionSummary {
+        total_files: total,
+        ..FolderCompressionSummary::default()
+    };
+    let done = std::sync::atomic::AtomicU32::new(0);
+    for batch in files.chunks(RECURSIVE_BATCH_SIZE) {
+        let outcomes = run_batch(batch.to_vec(), &BatchLimits::default(), |path| {
+            let outcome = compress_image(&path, &config, None, true);
+            let current = done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            let _ = app.emit(
+                "compression-progress",
+                ProgressEvent {
+                    current,
+                    total,
+                    path: path.to_string_lossy().to_string(),
+                },
+            );
+            (path, outcome)
+        })
+        .map_err(|e| e.to_string())?;
+
+        for (path, outcome) in outcomes {
+            match outcome {
+                Ok((original_size, compressed_size)) => {
+                    summary.successful += 1;
+                    summary.total_saved_bytes += original_size.saturating_sub(compressed_size);
+                }
+                Err(e) => {
+                    log::warn!("Failed to compress {}: {}", path.display(), e);
+                    summary.failed += 1;
+                    summary
+                        .failed_paths
+                        .push(path.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+// Oxipng effort for the flat-colour PNGs pixelation produces
+const PIXELATE_PNG_EFFORT: u8 = 4;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PixelateResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    blocks_x: u32,
+    blocks_y: u32,
+}
+
+// Fill each block with its mean RGBA. Bands of `block_size` rows run in
+// parallel; the last row and column of blocks may be partial.
+fn pixelate(img: &image::DynamicImage, block_size: u32) -> image::RgbaImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let (width, block) = (width as usize, block_size as usize);
+    if width == 0 || height == 0 {
+        return rgba;
+    }
+
+    let band_len = width * 4 * block;
+    rgba.par_chunks_mut(band_len).for_each(|band| {
+        let rows = band.len() / (width * 4);
+        for left in (0..width).step_by(block) {
+            let right = (left + block).min(width);
+            let mut sums = [0u64; 4];
+            for row in 0..rows {
+                let start = (row * width + left) * 4;
+                for pixel in band[start..(row * width + right) * 4].chunks_exact(4) {
+                    for (sum, &value) in sums.iter_mut().zip(pixel) {
+                        *sum += value as u64;
+                    }
+                }
+            }
+            let count = (rows * (right - left)) as u64;
+            let mean = sums.map(|sum| ((sum + count / 2) / count) as u8);
+            for row in 0..rows {
+                let start = (row * width + left) * 4;
+                for pixel in band[start..(row * width + right) * 4].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&mean);
+                }
+            }
+        }
+    });
+    rgba
+}
+
+#[tauri::command]
+async fn apply_pixelate(
+    path: String,
+    block_size: u32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<PixelateResult, String> {
+    if !(2..=256).contains(&block_size) {
+        return Err(format!(
+            "invalid_block_size: block size must be between 2 and 256, got {}",
+            block_size
+        ));
+    }
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let (width, height) = img.dimensions();
+    let pixelated = image::DynamicImage::ImageRgba8(pixelate(&img, block_size));
+    drop(img);
+
+    let config = processed_image_config(config_json.as_deref()).map_err(|e| e.to_string())?;
+    let mut result =
+        save_processed_image_with(&pixelated, source_path, output_path.as_deref(), &config)
+            .map_err(|e| e.to_string())?;
+
+    // Flat blocks leave long runs for deflate, so a stronger oxipng pass pays
+    // off; keep the first encode if it does not shrink. A dry run wrote nothing
+    // to re-read.
+    let saved = if config.dry_run {
+        Vec::new()
+    } else {
+        fs::read(&result.output_path).map_err(|e| e.to_string())?
+    };
+    if detect_format_from_bytes(&saved) == "png" {
+        let optimized = optimize_png_bytes(&saved, PIXELATE_PNG_EFFORT, false, false, None)
+            .map_err(|e| e.to_string())?;
+        if optimized.len() < saved.len() {
+            write_output(&config, Path::new(&result.output_path), &optimized)
+                .map_err(|e| e.to_string())?;
+            result.size_bytes = optimized.len() as u64;
+        }
+    }
+
+    Ok(PixelateResult {
+        result,
+        blocks_x: width.div_ceil(block_size),
+        blocks_y: height.div_ceil(block_size),
+    })
+}
+
+const MAX_MOTION_BLUR_PX: u32 = 200;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MotionBlurResult {
+    #[serde(flatten)]
+    result: ProcessedImageResult,
+    kernel_length: u32,
+    actual_angle: f32,
+}
+
+// Average of `length` taps on a line through each pixel, centred on it and
+// pointing `angle_degrees` anticlockwise from the positive x axis. Taps off
+// the pixel grid are sampled bilinearly and reads past the border clamp to
+// the edge. Alpha is left as it is.
+fn motion_blur(img: &image::DynamicImage, angle_degrees: f32, length: u32) -> image::RgbaImage {
+    let source = img.to_rgba8();
+    let (width, height) = source.dimensions();
+    let (dx, dy) = {
+        let radians = angle_degrees.to_radians();
+        // Image rows grow downwards
+        (radians.cos(), -radians.sin())
+    };
+    let centre = (length - 1) as f32 / 2.0;
+    let taps: Vec<(f32, f32)> = (0..length)
+        .map(|i| {
+            let t = i as f32 - centre;
+            (t * dx, t * dy)
+        })
+        .collect();
+
+    let texel = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        source.get_pixel(x, y).0
+    };
+    let sample = |x: f32, y: f32| -> [f32; 3] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let corners = [
+            (texel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+            (texel(x0 + 1, y0), fx * (1.0 - fy)),
+            (texel(x0, y0 + 1), (1.0 - fx) * fy),
+            (texel(x0 + 1, y0 + 1), fx * fy),
+        ];
+        std::array::from_fn(|c| {
+            corners
+                .iter()
+                .map(|(pixel, weight)| pixel[c] as f32 * weight)
+                .sum()
+        })
+    };
+
+    let mut output = source.clone();
+    let row_len = width as usize * 4;
+    output
+        .par_chunks_mut(row_len.max(1))
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let mut sums = [0.0f32; 3];
+                for &(ox, oy) in &taps {
+                    let value = sample(x as f32 + ox, y as f32 + oy);
+                    for (sum, v) in sums.iter_mut().zip(value) {
+                        *sum += v;
+                    }
+                }
+                for (channel, sum) in pixel.iter_mut().zip(sums) {
+                    *channel = (sum / length as f32).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        });
+    output
+}
+
+#[tauri::command]
+async fn apply_motion_blur(
+    path: String,
+    angle_degrees: f32,
+    distance_px: u32,
+    output_path: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<MotionBlurResult, String> {
+    if !angle_degrees.is_finite() {
+        return Err(format!("invalid_angle: {}", angle_degrees));
+    }
+    let kernel_length = distance_px.clamp(1, MAX_MOTION_BLUR_PX);
+    let actual_angle = angle_degrees.rem_euclid(360.0);
+
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let blurred = image::DynamicImage::ImageRgba8(motion_blur(&img, actual_angle, kernel_length));
+    drop(img);
+
+    let result = save_processed_image(
+        &blurred,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(MotionBlurResult {
+        result,
+        kernel_length,
+        actual_angle,
+    })
+}
+
+const HASH_CHUNK_BYTES: usize = 64 * 1024;
+
+// Feed the file through `update` in fixed-size chunks so large files never
+// sit in memory whole
+fn hash_file_chunks(path: &Path, mut update: impl FnMut(&[u8])) -> anyhow::Result<()> {
+    use std::io::Read;
+
+    let mut reader = std::io::BufReader::with_capacity(HASH_CHUNK_BYTES, fs::File::open(path)?);
+    let mut buffer = vec![0u8; HASH_CHUNK_BYTES];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        update(&buffer[..read]);
+    }
+}
+
+// Lowercase hex digest of the file with `algorithm`: sha256, sha512, blake3
+// or md5
+fn file_hash(path: &Path, algorithm: &str) -> anyhow::Result<String> {
+    use sha2::Digest;
+
+    match algorithm.to_ascii_lowercase().as_str() {
+        "sha256" => {
+            let mut hasher = sha2::Sha256::new();
+            hash_file_chunks(path, |chunk| hasher.update(chunk))?;
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        "sha512" => {
+            let mut hasher = sha2::Sha512::new();
+            hash_file_chunks(path, |chunk| hasher.update(chunk))?;
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        "blake3" => {
+            let mut hasher = blake3::Hasher::new();
+            hash_file_chunks(path, |chunk| {
+                hasher.update(chunk);
+            })?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        "md5" => {
+            let mut context = md5::Context::new();
+            hash_file_chunks(path, |chunk| context.consume(chunk))?;
+            Ok(format!("{:x}", context.compute()))
+        }
+        other => Err(anyhow!(
+            "invalid_algorithm: unsupported hash algorithm {}",
+            other
+        )),
+    }
+}
+
+#[tauri::command]
+async fn compute_file_hash(path: String, algorithm: String) -> std::result::Result<String, String> {
+    file_hash(Path::new(&path), &algorithm).map_err(|e| e.to_string())
+}
+
+// Brown-Conrady radial model. Output coordinates are normalised so the centre
+// is 0 and the corners sit at radius 1; each output pixel samples the source
+// at r * (1 + k1 r^2 + k2 r^4) along the same ray. Positive k1 corrects
+// pincushion distortion, negative k1 barrel.
+fn correct_lens_distortion(
+    img: &image::DynamicImage,
+    k1: f32,
+    k2: f32,
+    background: image::Rgba<u8>,
+) -> image::DynamicImage {
+    use imageproc::geometric_transformations::{warp_with, Interpolation};
+
+    let source = img.to_rgba8();
+    let (width, height) = source.dimensions();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let norm = cx.hypot(cy).max(1.0);
+    let mapping = move |x: f32, y: f32| {
+        let (nx, ny) = ((x - cx) / norm, (y - cy) / norm);
+        let r2 = nx * nx + ny * ny;
+        let scale = 1.0 + k1 * r2 + k2 * r2 * r2;
+        (cx + nx * scale * norm, cy + ny * scale * norm)
+    };
+    let corrected = warp_with(&source, mapping, Interpolation::Bilinear, background);
+    image::DynamicImage::ImageRgba8(corrected)
+}
+
+// `background` ("#RRGGBB" or "transparent", the default) fills output pixels
+// whose source position falls outside the image
+#[tauri::command]
+async fn apply_lens_distortion_correction(
+    path: String,
+    k1: f32,
+    k2: f32,
+    output_path: Option<String>,
+    background: Option<String>,
+    config_json: Option<String>,
+) -> std::result::Result<ProcessedImageResult, String> {
+    if !k1.is_finite() || !k2.is_finite() {
+        return Err(format!(
+            "invalid_coefficients: k1 and k2 must be finite, got {} and {}",
+            k1, k2
+        ));
+    }
+    let background = parse_border_color(background.as_deref().unwrap_or("transparent"))
+        .map_err(|e| e.to_string())?;
+
+    let source_path = Path::new(&path);
+    let img = image::open(source_path).map_err(|e| e.to_string())?;
+    let corrected = correct_lens_distortion(&img, k1, k2, background);
+    drop(img);
+
+    save_processed_image(
+        &corrected,
+        source_path,
+        output_path.as_deref(),
+        config_json.as_deref(),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn main() {
+    tauri::Builder::default()
+        .setup(|app| {
+            #[cfg(debug_assertions)]
+            {
+                app.get_webview_window("main").unwrap().open_devtools();
+            }
+            Ok(())
+        })
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             compress_images,
             stat_path,
-            compress_uploaded_files
+            compress_uploaded_files,
+            get_compression_recommendations,
+            merge_exif,
+            apply_noise_reduction,
+            create_image_sprite,
+            get_exif_thumbnail,
+            compress_image_to_data_uri,
+            compress_with_best_format,
+            get_image_entropy,
+            compress_with_size_target,
+            wipe_metadata,
+            compress_animated_webp,
+            estimate_memory_usage,
+            strip_png_metadata,
+            get_image_histogram,
+            batch_rename,
+            invert_image,
+            grayscale_image,
+            flip_horizontal,
+            flip_vertical,
+            compress_in_place_with_atomic_write,
+            get_webp_info,
+            compress_for_accept,
+            get_png_chunks,
+            export_batch_report,
+            compress_recursive_directory,
+            get_similar_images,
+            crop_jpeg_lossless,
+            begin_upload,
+            upload_chunk,
+            finalize_upload,
+            get_jpeg_quality_estimate,
+            apply_sharpening,
+            detect_image_format_from_bytes,
+            get_directory_stats,
+            apply_border,
+            convert_to_pdf,
+            apply_vignette,
+            check_disk_space,
+            get_quality_defaults,
+            queue_compression_jobs,
+            get_queue_status,
+            flush_queue,
+            analyze_compression_overhead,
+            apply_auto_levels,
+            apply_unsharp_mask_before_compress,
+            generate_contact_sheet,
+            extract_cover_art,
+            compare_compression_algorithms,
+            watch_directory,
+            stop_watch,
+            compute_visual_diff_score,
+            set_image_dpi,
+            normalize_filenames,
+            get_icc_profile_info,
+            apply_sepia,
+            apply_grayscale_weighted,
+            apply_duotone,
+            apply_color_balance,
+            check_format_support,
+            compress_clipboard_to_path,
+            split_animated_webp,
+            assemble_animated_webp,
+            validate_config_json,
+            apply_perspective_correction,
+            optimize_png_losslessly,
+            get_largest_files,
+            convert_color_space,
+            apply_unsharp_mask_chain,
+            compute_phash,
+            phash_distance,
+            get_app_info,
+            compress_with_budget,
+            apply_bilateral_filter,
+            get_compression_estimate_batch,
+            apply_edge_sharpen,
+            detect_corrupt_images,
+            create_ico_from_images,
+            extract_ico_frames,
+            apply_round_corners,
+            measure_encode_quality,
+            apply_zoom_pan_crop,
+            apply_mosaic_censor,
+            parse_compression_config,
+            get_temp_directory_usage,
+            apply_histogram_equalization,
+            apply_gamma_correction,
+            apply_dct_deblock,
+            extract_subimage,
+            pack_images_to_webp_lossless_bundle,
+            unpack_webp_bundle,
+            set_jpeg_comment,
+            get_jpeg_comment,
+            diff_compression_configs,
+            compress_folder_in_place,
+            apply_pixelate,
+            apply_motion_blur,
+            compute_file_hash,
+            apply_lens_distortion_correction
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh file path in its own temporary directory
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ximage-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn gradient_image(width: u32, height: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+        }))
+    }
+
+    fn write_jpeg(path: &Path, img: &image::DynamicImage) {
+        img.save_with_format(path, ImageFormat::Jpeg).unwrap();
+    }
+
+    fn ascii_field(tag: exif::Tag, value: &str) -> exif::Field {
+        exif::Field {
+            tag,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    fn exif_block(fields: &[exif::Field]) -> Vec<u8> {
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut output = std::io::Cursor::new(Vec::new());
+        writer.write(&mut output, false).unwrap();
+        output.into_inner()
+    }
+
+    fn set_jpeg_exif(path: &Path, exif: Option<Vec<u8>>) {
+        let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(fs::read(path).unwrap().into()).unwrap();
+        jpeg.set_exif(exif.map(Into::into));
+        let mut output = Vec::new();
+        jpeg.encoder().write_to(&mut output).unwrap();
+        fs::write(path, output).unwrap();
+    }
+
+    fn read_jpeg_exif(path: &Path) -> Option<exif::Exif> {
+        let jpeg = img_parts::jpeg::Jpeg::from_bytes(fs::read(path).unwrap().into()).unwrap();
+        let raw = jpeg.exif()?;
+        Some(exif::Reader::new().read_raw(raw.to_vec()).unwrap())
+    }
+
+    fn ascii_value(exif: &exif::Exif, tag: exif::Tag) -> Option<Vec<u8>> {
+        match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => values.first().cloned(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn merge_exif_restores_stripped_exif() {
+        let source = temp_file("original.jpg");
+        let target = temp_file("edited.jpg");
+        write_jpeg(&source, &gradient_image(32, 24));
+        set_jpeg_exif(
+            &source,
+            Some(exif_block(&[
+                ascii_field(exif::Tag::Make, "Ximage"),
+                ascii_field(exif::Tag::Artist, "Tester"),
+            ])),
+        );
+        // The edited copy keeps the pixels but has lost its EXIF
+        fs::copy(&source, &target).unwrap();
+        set_jpeg_exif(&target, None);
+        assert!(read_jpeg_exif(&target).is_none());
+        let pixels_before = image::open(&target).unwrap().to_rgb8();
+
+        let result = merge_exif_data(&source, &target, None).unwrap();
+        assert_eq!(result.fields_copied, 2);
+        assert_eq!(result.fields_skipped, 0);
+
+        let restored = read_jpeg_exif(&target).expect("EXIF restored");
+        assert_eq!(
+            ascii_value(&restored, exif::Tag::Make).as_deref(),
+            Some(&b"Ximage"[..])
+        );
+        assert_eq!(
+            ascii_value(&restored, exif::Tag::Artist).as_deref(),
+            Some(&b"Tester"[..])
+        );
+        assert_eq!(image::open(&target).unwrap().to_rgb8(), pixels_before);
+    }
+
+    #[test]
+    fn merge_exif_copies_only_requested_fields() {
+        let source = temp_file("original.jpg");
+        let target = temp_file("edited.jpg");
+        write_jpeg(&source, &gradient_image(16, 16));
+        set_jpeg_exif(
+            &source,
+            Some(exif_block(&[
+                ascii_field(exif::Tag::Make, "Ximage"),
+                ascii_field(exif::Tag::Artist, "Tester"),
+            ])),
+        );
+        write_jpeg(&target, &gradient_image(16, 16));
+
+        let fields = vec!["make".to_string()];
+        let result = merge_exif_data(&source, &target, Some(fields.as_slice())).unwrap();
+        assert_eq!(result.fields_copied, 1);
+        assert_eq!(result.fields_skipped, 1);
+
+        let merged = read_jpeg_exif(&target).expect("EXIF written");
+        assert!(ascii_value(&merged, exif::Tag::Make).is_some());
+        assert!(ascii_value(&merged, exif::Tag::Artist).is_none());
+    }
+
+    #[test]
+    fn exif_thumbnail_is_extracted_from_exif_block() {
+        let path = temp_file("with-thumbnail.jpg");
+        write_jpeg(&path, &gradient_image(64, 48));
+
+        let mut thumbnail = Vec::new();
+        gradient_image(16, 12)
+            .write_to(
+                &mut std::io::Cursor::new(&mut thumbnail),
+                image::ImageOutputFormat::Jpeg(80),
+            )
+            .unwrap();
+        let make = ascii_field(exif::Tag::Make, "Ximage");
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&make);
+        writer.set_jpeg(&thumbnail, exif::In::THUMBNAIL);
+        let mut block = std::io::Cursor::new(Vec::new());
+        writer.write(&mut block, false).unwrap();
+        set_jpeg_exif(&path, Some(block.into_inner()));
+
+        assert_eq!(read_exif_thumbnail(&path).unwrap(), Some(thumbnail.clone()));
+
+        let result =
+            tauri::async_runtime::block_on(get_exif_thumbnail(path.to_string_lossy().into_owned()))
+                .unwrap();
+        assert_eq!(result.width, Some(16));
+        assert_eq!(result.height, Some(12));
+        assert_eq!(result.size_bytes, thumbnail.len() as u32);
+    }
+
+    #[test]
+    fn exif_thumbnail_is_none_without_one() {
+        let path = temp_file("no-thumbnail.jpg");
+        write_jpeg(&path, &gradient_image(32, 32));
+        assert_eq!(read_exif_thumbnail(&path).unwrap(), None);
+
+        set_jpeg_exif(
+            &path,
+            Some(exif_block(&[ascii_field(exif::Tag::Make, "Ximage")])),
+        );
+        assert_eq!(read_exif_thumbnail(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn data_uri_uses_the_output_mime_type() {
+        let path = temp_file("source.png");
+        gradient_image(24, 24).save(&path).unwrap();
+        let source = path.to_string_lossy().into_owned();
+
+        for (format, prefix) in [
+            ("jpeg", "data:image/jpeg;base64,"),
+            ("png", "data:image/png;base64,"),
+            ("webp", "data:image/webp;base64,"),
+        ] {
+            let config = format!(r#"{{"outputFormat":"{}"}}"#, format);
+            let uri = tauri::async_runtime::block_on(compress_image_to_data_uri(
+                source.clone(),
+                config,
+                None,
+            ))
+            .unwrap();
+            assert!(uri.starts_with(prefix), "{} gave {}", format, &uri[..32]);
+            let payload = base64::engine::general_purpose::STANDARD
+                .decode(&uri[prefix.len()..])
+                .unwrap();
+            assert_eq!(
+                image::load_from_memory(&payload).unwrap().dimensions(),
+                (24, 24)
+            );
+        }
+    }
+
+    #[test]
+    fn data_uri_rejects_outputs_over_the_limit() {
+        let path = temp_file("source.png");
+        gradient_image(24, 24).save(&path).unwrap();
+
+        let result = tauri::async_runtime::block_on(compress_image_to_data_uri(
+            path.to_string_lossy().into_owned(),
+            "{}".to_string(),
+            Some(16),
+        ));
+        assert_eq!(result.unwrap_err(), "too_large_for_data_uri");
+    }
+
+    #[test]
+    fn entropy_of_solid_image_is_zero() {
+        let solid = image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(
+            64,
+            64,
+            image::Luma([90]),
+        ));
+        assert!(measure_image_entropy(&solid).abs() < 1e-9);
+    }
+
+    #[test]
+    fn entropy_of_noise_is_near_eight_bits() {
+        // Linear congruential generator, so the test is deterministic
+        let mut state = 12345u32;
+        let noise = image::GrayImage::from_fn(128, 128, |_, _| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+            image::Luma([(state >> 16) as u8])
+        });
+        let entropy = measure_image_entropy(&image::DynamicImage::ImageLuma8(noise));
+        assert!(entropy > 7.9 && entropy <= 8.0, "entropy = {}", entropy);
+    }
+
+    #[test]
+    fn entropy_of_smooth_gradient_is_moderate() {
+        // A narrow diagonal gradient over 64 gray levels, like a clear sky
+        let sky = image::GrayImage::from_fn(64, 64, |x, y| image::Luma([(64 + (x + y) / 2) as u8]));
+        let entropy = measure_image_entropy(&image::DynamicImage::ImageLuma8(sky));
+        assert!(entropy > 4.0 && entropy < 7.0, "entropy = {}", entropy);
+    }
+
+    #[test]
+    fn histogram_counts_requested_channels_only() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 0, 255])
+            }
+        }));
+        let channels = vec!["R".to_string(), "luma".to_string()];
+        let data = compute_histogram(&img, &channels).unwrap();
+
+        let r = data.r.unwrap();
+        assert_eq!((r[255], r[0]), (4, 4));
+        assert_eq!(r.iter().sum::<u32>(), 8);
+        // Rec. 709: pure red is luma 54, pure blue luma 18
+        let luma = data.luma.unwrap();
+        assert_eq!((luma[54], luma[18]), (4, 4));
+        assert!(data.g.is_none() && data.b.is_none());
+    }
+
+    #[test]
+    fn histogram_rejects_unknown_channels() {
+        let img = gradient_image(4, 4);
+        assert!(compute_histogram(&img, &["alpha".to_string()]).is_err());
+    }
+
+    #[test]
+    fn batch_rename_keeps_extension_and_skips_existing() {
+        let first = temp_file("first.png");
+        let dir = first.parent().unwrap().to_path_buf();
+        let second = dir.join("second.png");
+        fs::write(&first, b"first").unwrap();
+        fs::write(&second, b"second").unwrap();
+        fs::write(dir.join("taken.png"), b"taken").unwrap();
+
+        let specs = vec![
+            RenameSpec {
+                old_path: first.to_string_lossy().into_owned(),
+                new_name: "renamed".to_string(),
+            },
+            RenameSpec {
+                old_path: second.to_string_lossy().into_owned(),
+                new_name: "taken.png".to_string(),
+            },
+        ];
+        let results = tauri::async_runtime::block_on(batch_rename(specs, None)).unwrap();
+
+        assert_eq!(results[0].status, "renamed");
+        assert_eq!(Path::new(&results[0].new_path), dir.join("renamed.png"));
+        assert_eq!(fs::read(dir.join("renamed.png")).unwrap(), b"first");
+        assert!(!first.exists());
+        assert_eq!(results[1].status, "skipped_exists");
+        assert_eq!(fs::read(dir.join("taken.png")).unwrap(), b"taken");
+        assert!(second.exists());
+    }
+
+    #[test]
+    fn move_file_does_not_copy_on_other_errors() {
+        let missing = temp_file("missing.png");
+        let target = missing.with_file_name("target.png");
+        let err = move_file(&missing, &target).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn sanitize_filename_strips_traversal_and_reserved_names() {
+        assert_eq!(sanitize_filename("../etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize_filename("a\\b.png"), "a_b.png");
+        assert_eq!(sanitize_filename("CON.txt"), "_CON.txt");
+        assert_eq!(sanitize_filename("com1"), "_com1");
+        assert_eq!(sanitize_filename("console.png"), "console.png");
+        assert_eq!(sanitize_filename(".."), "unnamed_file");
+    }
+
+    #[test]
+    fn sanitize_filename_normalizes_unicode() {
+        // Decomposed and precomposed e-acute give the same name
+        assert_eq!(sanitize_filename("e\u{301}.png"), "\u{e9}.png");
+        assert_eq!(sanitize_filename("bad\u{FFFD}\u{7}.png"), "bad.png");
+    }
+
+    #[test]
+    fn sanitize_filename_truncates_keeping_extension() {
+        let long = format!("{}.png", "\u{e9}".repeat(200));
+        let clean = sanitize_filename(&long);
+        assert!(clean.len() <= MAX_FILENAME_BYTES);
+        assert!(clean.ends_with(".png"));
+    }
+
+    #[test]
+    fn sanitize_filename_yields_valid_names_for_arbitrary_unicode() {
+        // Code point ranges mixing ASCII, controls, combining marks, CJK,
+        // emoji, bidi overrides and specials; dots are weighted up so runs
+        // of them and truncation at a dot get exercised.
+        const RANGES: &[(u32, u32)] = &[
+            (0x20, 0x7e),
+            (0x00, 0x1f),
+            (0x80, 0x9f),
+            (0x300, 0x36f),
+            (0x400, 0x4ff),
+            (0x4e00, 0x9fff),
+            (0x1f600, 0x1f64f),
+            (0x202a, 0x202e),
+            (0xfff0, 0xfffd),
+            (0x2e, 0x2e),
+            (0x61, 0x61),
+        ];
+        let mut state: u32 = 42;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+            state
+        };
+        for _ in 0..5000 {
+            let len = next() % 300;
+            let name: String = (0..len)
+                .filter_map(|_| {
+                    let (lo, hi) = RANGES[next() as usize % RANGES.len()];
+                    char::from_u32(lo + next() % (hi - lo + 1))
+                })
+                .collect();
+            let clean = sanitize_filename(&name);
+            let base = clean.split('.').next().unwrap_or_default();
+            assert!(!clean.is_empty(), "{name:?}");
+            assert!(clean.len() <= MAX_FILENAME_BYTES, "{name:?} -> {clean:?}");
+            assert!(!clean.contains(".."), "{name:?} -> {clean:?}");
+            assert!(
+                clean
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-')),
+                "{name:?} -> {clean:?}"
+            );
+            assert!(
+                !WINDOWS_RESERVED_NAMES
+                    .iter()
+                    .any(|reserved| reserved.eq_ignore_ascii_case(base)),
+                "{name:?} -> {clean:?}"
+            );
+            assert_eq!(sanitize_filename(&clean), clean, "{name:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_in_place_compress_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_file("photo.jpg");
+        write_jpeg(&path, &gradient_image(40, 20));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let config = r#"{"resizeWidth":10,"resizeHeight":10}"#.to_string();
+        let result = tauri::async_runtime::block_on(compress_in_place_with_atomic_write(
+            path.to_string_lossy().into_owned(),
+            Some(config),
+            Some(true),
+        ))
+        .unwrap();
+
+        assert_eq!(Path::new(result.output_path.as_deref().unwrap()), path);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(image::open(&path).unwrap().dimensions(), (10, 5));
+        // No temp file is left next to the output
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn atomic_save_into_read_only_dir_fails_and_keeps_original() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_file("photo.jpg");
+        write_jpeg(&path, &gradient_image(40, 20));
+        let original = fs::read(&path).unwrap();
+        let dir = path.parent().unwrap();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o555)).unwrap();
+        // Root ignores directory permissions, so there is nothing to test
+        let probe = dir.join("probe");
+        if fs::write(&probe, b"").is_ok() {
+            fs::remove_file(&probe).ok();
+            return;
+        }
+
+        let result = save_processed_image_with(
+            &gradient_image(10, 5),
+            &path,
+            None,
+            &CompressionConfig::default(),
+        );
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn accepted_formats_orders_by_q_value() {
+        assert_eq!(
+            accepted_formats("image/avif,image/webp,image/jpeg;q=0.9").unwrap(),
+            vec![ImageFormat::WebP, ImageFormat::Jpeg]
+        );
+        assert_eq!(
+            accepted_formats("image/jpeg;q=0.5, image/png;q=0.8").unwrap(),
+            vec![ImageFormat::Png, ImageFormat::Jpeg]
+        );
+        assert_eq!(
+            accepted_formats("image/png;q=0, image/jpeg").unwrap(),
+            vec![ImageFormat::Jpeg]
+        );
+    }
+
+    #[test]
+    fn accepted_formats_expands_wildcards() {
+        assert_eq!(
+            accepted_formats("*/*").unwrap(),
+            vec![ImageFormat::WebP, ImageFormat::Jpeg, ImageFormat::Png]
+        );
+        assert_eq!(
+            accepted_formats("image/png,image/*;q=0.8").unwrap(),
+            vec![ImageFormat::Png, ImageFormat::WebP, ImageFormat::Jpeg]
+        );
+    }
+
+    #[test]
+    fn accepted_formats_skips_unsupported_types() {
+        assert_eq!(
+            accepted_formats("text/html,application/xhtml+xml,image/avif,image/webp,*/*;q=0.8")
+                .unwrap(),
+            vec![ImageFormat::WebP, ImageFormat::Jpeg, ImageFormat::Png]
+        );
+        assert_eq!(
+            accepted_formats("image/webp,application/x-foo,image/x-bar").unwrap(),
+            vec![ImageFormat::WebP]
+        );
+        let err = accepted_formats("image/avif,text/plain,image/png;q=0").unwrap_err();
+        assert!(err.to_string().starts_with("no_supported_format:"));
+        let err = accepted_formats("webp").unwrap_err();
+        assert!(err.to_string().starts_with("invalid_accept:"));
+    }
+
+    #[test]
+    fn compress_for_accept_reports_mime_type() {
+        let path = temp_file("source.png");
+        gradient_image(16, 16).save(&path).unwrap();
+        let output_dir = path.parent().unwrap().join("out");
+
+        let result = compress_accepted(
+            &path,
+            "image/avif,image/jpeg;q=0.9,image/png;q=0.5",
+            80,
+            &output_dir.to_string_lossy(),
+        )
+        .unwrap();
+        assert_eq!(result.mime_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(
+            Path::new(result.output_path.as_deref().unwrap()),
+            output_dir.join("source.jpg")
+        );
+    }
+
+    #[test]
+    fn similar_images_ranks_matches_and_skips_non_images() {
+        let reference = temp_file("reference.jpg");
+        let dir = reference.parent().unwrap().to_path_buf();
+        write_jpeg(&reference, &gradient_image(64, 64));
+        let copy = dir.join("copy.jpg");
+        fs::copy(&reference, &copy).unwrap();
+        let rotated = dir.join("rotated.jpg");
+        write_jpeg(&rotated, &gradient_image(64, 64).rotate90());
+        let other = dir.join("checker.png");
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                image::Rgb([255, 255, 255])
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        }))
+        .save(&other)
+        .unwrap();
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, b"not an image").unwrap();
+
+        let paths: Vec<String> = [&copy, &rotated, &other, &notes]
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        let mut seen = Vec::new();
+        let all = find_similar_images(
+            &reference.to_string_lossy(),
+            paths.clone(),
+            0.0,
+            None,
+            |done, _| seen.push(done),
+        )
+        .unwrap();
+        assert_eq!(seen, vec![1, 2, 3, 4]);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].path, paths[0]);
+        assert_eq!(all[0].hash_distance, 0);
+        assert!(all[1].similarity < all[0].similarity);
+        let rotated_match = all.iter().find(|m| m.path == paths[1]).unwrap();
+        assert!(rotated_match.similarity < NEAR_DUPLICATE_SIMILARITY);
+
+        let close = find_similar_images(
+            &reference.to_string_lossy(),
+            paths,
+            NEAR_DUPLICATE_SIMILARITY,
+            None,
+            |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(close.len(), 1);
+        assert_eq!(close[0].path, all[0].path);
+    }
+
+    #[test]
+    fn first_missing_byte_finds_gaps() {
+        assert_eq!(first_missing_byte(&[(0, 4), (4, 10)], 10), None);
+        assert_eq!(first_missing_byte(&[(5, 10), (0, 6)], 10), None);
+        assert_eq!(first_missing_byte(&[(0, 4), (6, 10)], 10), Some(4));
+        assert_eq!(first_missing_byte(&[(2, 10)], 10), Some(0));
+        assert_eq!(first_missing_byte(&[(0, 8)], 10), Some(8));
+        assert_eq!(first_missing_byte(&[], 0), None);
+    }
+
+    #[test]
+    fn chunked_upload_rejects_overflow_and_gaps() {
+        use tauri::async_runtime::block_on;
+
+        let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+        let session = Uuid::new_v4().to_string();
+        block_on(begin_upload(
+            session.clone(),
+            "photo.png".to_string(),
+            "png".to_string(),
+            10,
+        ))
+        .unwrap();
+
+        assert!(block_on(upload_chunk(session.clone(), encode(b"x"), u64::MAX)).is_err());
+        assert!(block_on(upload_chunk(session.clone(), encode(b"0123"), 8)).is_err());
+        block_on(upload_chunk(session.clone(), encode(b"0123"), 0)).unwrap();
+        block_on(upload_chunk(session.clone(), encode(b"6789"), 6)).unwrap();
+
+        let err = block_on(finalize_upload(session, "{}".to_string())).unwrap_err();
+        assert!(err.contains("byte 4"), "{}", err);
+    }
+
+    #[test]
+    fn upload_sessions_are_capped() {
+        let session = || UploadSession {
+            name: "photo.png".to_string(),
+            format: "png".to_string(),
+            total_size: 0,
+            data: Vec::new(),
+            received: Vec::new(),
+            last_activity: std::time::Instant::now(),
+        };
+        let mut sessions = std::collections::HashMap::new();
+        for n in 0..MAX_UPLOAD_SESSIONS {
+            insert_upload_session(&mut sessions, n.to_string(), session()).unwrap();
+        }
+        let err = insert_upload_session(&mut sessions, "extra".to_string(), session()).unwrap_err();
+        assert!(err.starts_with("too_many_uploads:"), "{}", err);
+        // Restarting a live session does not count against the cap
+        insert_upload_session(&mut sessions, "0".to_string(), session()).unwrap();
+
+        // Expired sessions free their slots
+        sessions.get_mut("1").unwrap().last_activity -= UPLOAD_SESSION_TTL;
+        insert_upload_session(&mut sessions, "extra".to_string(), session()).unwrap();
+        assert_eq!(sessions.len(), MAX_UPLOAD_SESSIONS);
+    }
+
+    #[test]
+    fn finalized_upload_dir_is_tracked() {
+        use tauri::async_runtime::block_on;
+
+        let mut png = Vec::new();
+        gradient_image(16, 16)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let session = Uuid::new_v4().to_string();
+        block_on(begin_upload(
+            session.clone(),
+            "photo.png".to_string(),
+            "png".to_string(),
+            png.len() as u64,
+        ))
+        .unwrap();
+        let chunk = base64::engine::general_purpose::STANDARD.encode(&png);
+        block_on(upload_chunk(session.clone(), chunk, 0)).unwrap();
+
+        let result = block_on(finalize_upload(session, "{}".to_string())).unwrap();
+        let output = std::path::PathBuf::from(result.output_path.unwrap());
+        let temp_dir = output.parent().unwrap().parent().unwrap();
+        assert!(tracked_temp_dirs().contains_key(&*temp_dir.to_string_lossy()));
+    }
+
+    #[test]
+    fn jpeg_quality_estimate_recovers_encoder_quality() {
+        for quality in [30u8, 50, 75, 90] {
+            let path = temp_file("quality.jpg");
+            let mut bytes = Vec::new();
+            gradient_image(64, 64)
+                .write_to(
+                    &mut std::io::Cursor::new(&mut bytes),
+                    image::ImageOutputFormat::Jpeg(quality),
+                )
+                .unwrap();
+            fs::write(&path, bytes).unwrap();
+
+            let estimate = estimate_jpeg_quality(&path).unwrap();
+            assert!(
+                estimate.abs_diff(quality) <= 1,
+                "encoded at {}, estimated {}",
+                quality,
+                estimate
+            );
+        }
+    }
+
+    #[test]
+    fn jpeg_quality_estimate_rejects_non_jpeg() {
+        let path = temp_file("image.png");
+        gradient_image(8, 8).save(&path).unwrap();
+        assert!(estimate_jpeg_quality(&path).is_err());
+    }
+
+    #[test]
+    fn detect_format_from_base64_accepts_data_uris_and_short_input() {
+        let mut png = Vec::new();
+        gradient_image(4, 4)
+            .write_to(
+                &mut std::io::Cursor::new(&mut png),
+                image::ImageOutputFormat::Png,
+            )
+            .unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&png);
+
+        let detect = |data: String| {
+            tauri::async_runtime::block_on(detect_image_format_from_bytes(data)).unwrap()
+        };
+        assert_eq!(detect(encoded.clone()), "png");
+        assert_eq!(detect(format!("data:image/png;base64,{}", encoded)), "png");
+        // Nine characters: the trailing partial quantum is dropped
+        assert_eq!(detect("/9j/4AAQS".to_string()), "jpeg");
+        assert!(
+            tauri::async_runtime::block_on(detect_image_format_from_bytes("@@@@".to_string()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_page_size_accepts_presets_and_custom_sizes() {
+        assert_eq!(parse_page_size(None).unwrap(), (210.0, 297.0));
+        assert_eq!(parse_page_size(Some("Letter")).unwrap(), (215.9, 279.4));
+        assert_eq!(
+            parse_page_size(Some("custom:100x150_mm")).unwrap(),
+            (100.0, 150.0)
+        );
+        assert!(parse_page_size(Some("custom:0x150_mm")).is_err());
+        assert!(parse_page_size(Some("A5")).is_err());
+    }
+
+    #[test]
+    fn pdf_embeds_jpegs_untouched_and_reencodes_others() {
+        let jpeg = temp_file("page1.jpg");
+        write_jpeg(&jpeg, &gradient_image(40, 30));
+        let png = jpeg.with_file_name("page2.png");
+        gradient_image(30, 40).save(&png).unwrap();
+
+        let (data, width, height, _) = pdf_jpeg_data(&jpeg).unwrap();
+        assert_eq!(data, fs::read(&jpeg).unwrap());
+        assert_eq!((width, height), (40, 30));
+        let (data, width, height, _) = pdf_jpeg_data(&png).unwrap();
+        assert_eq!(detect_format_from_bytes(&data), "jpeg");
+        assert_eq!((width, height), (30, 40));
+
+        let output = jpeg.with_file_name("out.pdf");
+        let paths = vec![
+            jpeg.to_string_lossy().into_owned(),
+            png.to_string_lossy().into_owned(),
+        ];
+        let result = build_pdf(&paths, &output, Some("letter")).unwrap();
+        assert_eq!(result.page_count, 2);
+        let pdf = fs::read(&output).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(result.file_size, pdf.len() as u64);
+
+        assert!(build_pdf(&[], &output, None).is_err());
+    }
+
+    #[test]
+    fn vignette_darkens_corners_but_not_centre() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            33,
+            33,
+            image::Rgba([200, 200, 200, 128]),
+        ));
+        let result = vignette(&img, 0.5, 0.5).to_rgba8();
+
+        assert_eq!(result.get_pixel(16, 16).0, [200, 200, 200, 128]);
+        // t = 1 at the corners, so they are scaled by 1 - strength
+        assert_eq!(result.get_pixel(0, 0).0, [100, 100, 100, 128]);
+        assert_eq!(result.get_pixel(32, 32).0, [100, 100, 100, 128]);
+        let edge = result.get_pixel(0, 16).0[0];
+        assert!(edge > 100 && edge < 200, "edge = {}", edge);
+
+        assert_eq!(vignette(&img, 0.0, 0.5).to_rgba8(), img.to_rgba8());
+    }
+
+    #[test]
+    fn finished_queue_statuses_expire() {
+        let mut statuses = std::collections::HashMap::new();
+        let mut running = QueueStatus {
+            total: 2,
+            pending: 2,
+            ..QueueStatus::default()
+        };
+        running.job_finished();
+        assert!(running.finished_at.is_none());
+        let mut done = running.clone();
+        done.job_finished();
+        assert!(done.finished_at.is_some());
+        statuses.insert("running".to_string(), running);
+        statuses.insert("done".to_string(), done);
+
+        expire_queue_statuses(&mut statuses, QUEUE_STATUS_TTL);
+        assert_eq!(statuses.len(), 2);
+        expire_queue_statuses(&mut statuses, std::time::Duration::ZERO);
+        assert!(statuses.contains_key("running"));
+        assert!(!statuses.contains_key("done"));
+    }
+
+    // Vertical step from gray 100 to gray 150 halfway across
+    fn step_edge_image(width: u32, height: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, _| {
+            let v = if x < width / 2 { 100 } else { 150 };
+            image::Rgb([v, v, v])
+        }))
+    }
+
+    #[test]
+    fn sharpen_request_reads_sharpen_and_config_fields() {
+        let request: SharpenCompressRequest =
+            serde_json::from_str(r#"{"qualityJpg":70,"sharpen":{"amount":1.5}}"#).unwrap();
+        assert_eq!(request.config.quality_jpg, 70);
+        assert_eq!(request.sharpen.amount, 1.5);
+        assert_eq!(request.sharpen.sigma, SharpenConfig::default().sigma);
+
+        let request: SharpenCompressRequest = serde_json::from_str("{}").unwrap();
+        assert_eq!(request.sharpen.amount, SharpenConfig::default().amount);
+    }
+
+    #[test]
+    fn unsharp_mask_before_compress_boosts_edges() {
+        let source = temp_file("edge.png");
+        step_edge_image(32, 8).save(&source).unwrap();
+        let output = source.with_file_name("sharpened.png");
+
+        // A small threshold keeps blur rounding in flat areas from counting
+        let config = r#"{"lossless":true,"sharpen":{"sigma":1.0,"amount":1.0,"threshold":2}}"#;
+        let result = tauri::async_runtime::block_on(apply_unsharp_mask_before_compress(
+            source.to_string_lossy().into_owned(),
+            config.to_string(),
+            Some(output.to_string_lossy().into_owned()),
+        ))
+        .unwrap();
+        assert_eq!(result.status, "success");
+
+        let sharpened = image::open(&output).unwrap().to_rgb8();
+        assert!(sharpened.get_pixel(15, 4).0[0] < 100);
+        assert!(sharpened.get_pixel(16, 4).0[0] > 150);
+        // Flat areas away from the edge are left alone
+        assert_eq!(sharpened.get_pixel(2, 4).0[0], 100);
+        assert_eq!(sharpened.get_pixel(29, 4).0[0], 150);
+        assert_eq!(
+            image::open(&source).unwrap().to_rgb8().get_pixel(15, 4).0[0],
+            100
+        );
+    }
+
+    #[test]
+    fn visual_diff_of_identical_images_is_perfect() {
+        let img = gradient_image(32, 32);
+        let score = visual_diff(img.clone(), img, false).unwrap();
+        assert_eq!(score.psnr, MAX_PSNR);
+        assert!((score.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(score.max_absolute_error, 0);
+        assert_eq!(score.identical_pixel_count, score.total_pixels);
+        assert_eq!(score.chroma_psnr, Some(MAX_PSNR));
+    }
+
+    #[test]
+    fn visual_diff_measures_a_uniform_offset() {
+        let gray = |v: u8| {
+            image::DynamicImage::ImageLuma8(image::GrayImage::from_pixel(16, 16, image::Luma([v])))
+        };
+        let score = visual_diff(gray(100), gray(110), false).unwrap();
+        // MSE 100: 10 * log10(255^2 / 100) = 28.13 dB
+        assert!((score.psnr - 28.13).abs() < 0.01, "psnr = {}", score.psnr);
+        assert_eq!(score.mean_absolute_error, 10.0);
+        assert_eq!(score.max_absolute_error, 10);
+        assert_eq!(score.identical_pixel_count, 0);
+        assert!(score.chroma_psnr.is_none());
+    }
+
+    #[test]
+    fn visual_diff_resizes_only_when_asked() {
+        let (small, large) = (gradient_image(16, 16), gradient_image(32, 32));
+        assert!(visual_diff(small.clone(), large.clone(), false).is_err());
+        let score = visual_diff(small, large, true).unwrap();
+        assert_eq!(score.total_pixels, 32 * 32);
+        assert!(score.ssim > 0.9, "ssim = {}", score.ssim);
+    }
+
+    #[test]
+    fn compute_phash_matches_near_duplicates() {
+        use tauri::async_runtime::block_on;
+
+        let original = temp_file("original.png");
+        let resized = temp_file("resized.jpg");
+        let rotated = temp_file("rotated.png");
+        let flipped = temp_file("flipped.png");
+        let unrelated = temp_file("unrelated.png");
+        let img = gradient_image(64, 64);
+        img.save(&original).unwrap();
+        write_jpeg(
+            &resized,
+            &img.resize_exact(48, 48, image::imageops::FilterType::Triangle),
+        );
+        img.rotate180().save(&rotated).unwrap();
+        img.fliph().save(&flipped).unwrap();
+        // A small bright disc on black shares nothing with the gradient
+        image::RgbImage::from_fn(64, 64, |x, y| {
+            let (dx, dy) = (x as i32 - 16, y as i32 - 48);
+            if dx * dx + dy * dy < 100 {
+                image::Rgb([255, 255, 255])
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        })
+        .save(&unrelated)
+        .unwrap();
+        let hash =
+            |path: &Path| block_on(compute_phash(path.to_string_lossy().to_string())).unwrap();
+
+        let original_hash = hash(&original);
+        assert_eq!(original_hash.len(), 16);
+        assert_eq!(original_hash, hash(&original));
+        assert_eq!(
+            phash_distance(original_hash.clone(), hash(&original)).unwrap(),
+            0
+        );
+        let mirrored = phash_distance(original_hash.clone(), hash(&flipped)).unwrap();
+        assert!(mirrored <= 8, "horizontal flip distance {}", mirrored);
+        let different = phash_distance(original_hash.clone(), hash(&unrelated)).unwrap();
+        assert!(different >= 24, "unrelated image distance {}", different);
+        let near = phash_distance(original_hash.clone(), hash(&resized)).unwrap();
+        let far = phash_distance(original_hash, hash(&rotated)).unwrap();
+        assert!(near < 10, "near-duplicate distance {}", near);
+        assert!(far > near, "rotated {} vs resized {}", far, near);
+    }
+
+    #[test]
+    fn compute_phash_reports_unreadable_files() {
+        use tauri::async_runtime::block_on;
+
+        let path = temp_file("missing.png");
+        assert!(block_on(compute_phash(path.to_string_lossy().to_string())).is_err());
+        assert!(phash_distance("abc".to_string(), "0".repeat(16)).is_err());
+    }
+
+    #[test]
+    fn jpeg_backend_is_parsed_from_a_bare_name() {
+        assert_eq!(
+            parse_jpeg_backend("default", None, None).unwrap(),
+            JpegBackend::Default
+        );
+        assert_eq!(
+            parse_jpeg_backend("mozJpeg", Some(false), None).unwrap(),
+            JpegBackend::MozJpeg {
+                progressive: false,
+                optimize_coding: true,
+            }
+        );
+        assert!(parse_jpeg_backend("\"mozJpeg\"", None, None).is_err());
+    }
+
+    #[test]
+    fn jpeg_truncation_allows_data_after_eoi() {
+        let path = temp_file("full.jpg");
+        write_jpeg(&path, &gradient_image(32, 32));
+        let mut bytes = fs::read(&path).unwrap();
+        assert!(!is_truncated(&bytes, ImageFormat::Jpeg));
+
+        let cut = bytes[..bytes.len() - 10].to_vec();
+        assert!(is_truncated(&cut, ImageFormat::Jpeg));
+        bytes.extend_from_slice(b"trailing vendor data");
+        assert!(!is_truncated(&bytes, ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn equalization_raises_entropy_of_underexposed_image() {
+        // Every channel in 0..64, from a deterministic LCG
+        let mut state = 12345u32;
+        let mut next = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fff_ffff;
+            ((state >> 16) % 64) as u8
+        };
+        let dark = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |_, _| {
+            image::Rgb([next(), next(), next()])
+        }));
+        let before = measure_image_entropy(&dark);
+
+        let entropy_after =
+            |method| measure_image_entropy(&equalize_histogram(&dark, method, 16, 2.0));
+        let global = entropy_after(EqualizationMethod::Global);
+        let clahe = entropy_after(EqualizationMethod::Clahe);
+        assert!(global > before, "global {} vs input {}", global, before);
+        assert!(clahe > before, "clahe {} vs input {}", clahe, before);
+    }
+
+    #[test]
+    fn webp_bundle_round_trips_transparent_pixels_exactly() {
+        let source = temp_file("sprite.png");
+        let sprite = image::RgbaImage::from_fn(8, 8, |x, y| {
+            let alpha = if x < 4 { 0 } else { 255 };
+            image::Rgba([(x * 30) as u8, (y * 30) as u8, 77, alpha])
+        });
+        sprite.save(&source).unwrap();
+        let bundle = source.with_file_name("sprites.bundle");
+
+        let packed = pack_webp_bundle(&[source.to_string_lossy().to_string()], &bundle).unwrap();
+        assert_eq!(packed.image_count, 1);
+        let unpacked = unpack_webp_bundle_file(&bundle, &source.with_file_name("out")).unwrap();
+        assert_eq!(unpacked.len(), 1);
+        assert!(unpacked[0].ends_with("sprite.webp"));
+
+        let data = fs::read(&unpacked[0]).unwrap();
+        let decoded = webp::Decoder::new(&data).decode().unwrap().to_image();
+        assert_eq!(decoded.to_rgba8(), sprite);
+    }
+
+    #[test]
+    fn webp_bundle_rejects_duplicate_names() {
+        let mut bundle = BUNDLE_MAGIC.to_vec();
+        bundle.extend_from_slice(&2u32.to_le_bytes());
+        for _ in 0..2 {
+            bundle.extend_from_slice(&6u32.to_le_bytes());
+            bundle.extend_from_slice(b"a.webp");
+            bundle.extend_from_slice(&1u32.to_le_bytes());
+            bundle.push(0);
+        }
+        let path = temp_file("duplicate.bundle");
+        fs::write(&path, bundle).unwrap();
+
+        let output_dir = path.with_file_name("out");
+        let err = unpack_webp_bundle_file(&path, &output_dir).unwrap_err();
+        assert!(err.to_string().contains("Duplicate file name"), "{}", err);
+        assert!(!output_dir.exists());
+    }
+
+    #[test]
+    fn jpeg_comment_round_trips_and_replaces_the_old_one() {
+        let path = temp_file("commented.jpg");
+        write_jpeg(&path, &gradient_image(16, 16));
+        assert_eq!(read_jpeg_comment(&path).unwrap(), None);
+
+        let first = replace_jpeg_comment(fs::read(&path).unwrap(), "first").unwrap();
+        fs::write(&path, first).unwrap();
+        assert_eq!(read_jpeg_comment(&path).unwrap().as_deref(), Some("first"));
+
+        let second = replace_jpeg_comment(fs::read(&path).unwrap(), "zweite Ä").unwrap();
+        fs::write(&path, &second).unwrap();
+        assert_eq!(
+            read_jpeg_comment(&path).unwrap().as_deref(),
+            Some("zweite Ä")
+        );
+        let jpeg = img_parts::jpeg::Jpeg::from_bytes(second.into()).unwrap();
+        let comments = jpeg
+            .segments()
+            .iter()
+            .filter(|segment| segment.marker() == img_parts::jpeg::markers::COM)
+            .count();
+        assert_eq!(comments, 1);
+    }
+
+    #[test]
+    fn motion_blur_at_zero_degrees_smears_only_horizontally() {
+        // White line on row 16 from x = 8 to 23
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, y| {
+            let on_line = y == 16 && (8..24).contains(&x);
+            image::Rgba(if on_line {
+                [255, 255, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            })
+        }));
+
+        let blurred = motion_blur(&img, 0.0, 9);
+        for (x, y, pixel) in blurred.enumerate_pixels() {
+            if y != 16 {
+                assert_eq!(pixel.0, [0, 0, 0, 255], "({}, {}) picked up the line", x, y);
+            }
+        }
+        // One of nine taps reaches the line end, and the middle stays white
+        assert_eq!(blurred.get_pixel(4, 16).0, [28, 28, 28, 255]);
+        assert_eq!(blurred.get_pixel(3, 16).0, [0, 0, 0, 255]);
+        assert_eq!(blurred.get_pixel(16, 16).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn file_hash_matches_known_digests() {
+        let path = temp_file("abc.txt");
+        fs::write(&path, b"abc").unwrap();
+
+        let digests = [
+            (
+                "sha256",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "SHA512",
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                 2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            ),
+            (
+                "blake3",
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+            ("md5", "900150983cd24fb0d6963f7d28e17f72"),
+        ];
+        for (algorithm, expected) in digests {
+            assert_eq!(
+                file_hash(&path, algorithm).unwrap(),
+                expected,
+                "{}",
+                algorithm
+            );
+        }
+        assert!(file_hash(&path, "crc32")
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid_algorithm"));
+    }
+
+    #[test]
+    fn size_target_returns_the_highest_quality_that_fits() {
+        let path = temp_file("target.png");
+        gradient_image(128, 128).save(&path).unwrap();
+        let img = image::open(&path).unwrap();
+        let size_at = |quality| {
+            encode_at_quality(&img, ImageFormat::Jpeg, quality)
+                .unwrap()
+                .len() as u64
+        };
+        let target = size_at(70);
+        // 10% tolerance above the target, as the search computes it
+        let upper = (target as f64 * (1.0 + 10.0 / 100.0)) as u64;
+
+        let result =
+            compress_to_size_target(&path, target, 10.0, ImageFormat::Jpeg, None, true).unwrap();
+        let quality = result.final_quality.unwrap();
+        assert_eq!(result.status, "success");
+        assert!(result.compressed_size <= upper);
+        assert!(quality >= 70, "quality {}", quality);
+        assert!(
+            quality == 100 || size_at(quality + 1) > upper,
+            "quality {}",
+            quality
+        );
+    }
+
+    #[test]
+    fn move_file_copies_across_filesystems() {
+        let source = temp_file("source.png");
+        fs::write(&source, b"pixels").unwrap();
+        let target = source.with_file_name("moved.png");
+
+        move_file_with(&source, &target, |_, _| {
+            Err(std::io::ErrorKind::CrossesDevices.into())
+        })
+        .unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"pixels");
+        assert!(!source.exists());
+    }
+
+    #[test]
+    fn unsharp_mask_with_zero_amount_is_identity() {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(24, 16, |x, y| {
+            let level = if x < 12 { 40 } else { 220 };
+            image::Rgba([level, (y * 15) as u8, (x * 10) as u8, (x * y) as u8])
+        }));
+        for amount in [0.0, -1.0] {
+            let sharpened = unsharp_mask(&img, 2.0, amount, 0);
+            assert_eq!(sharpened.color(), img.color());
+            assert_eq!(sharpened.as_bytes(), img.as_bytes());
+        }
+    }
+
+    #[test]
+    fn queued_jobs_write_to_their_output_path_only() {
+        let missing = r#"{"id":"a","sourcePath":"in.png","configJson":"{}","priority":1}"#;
+        assert!(serde_json::from_str::<CompressionJob>(missing).is_err());
+
+        let source = temp_file("queued.png");
+        gradient_image(32, 32).save(&source).unwrap();
+        let original = fs::read(&source).unwrap();
+        let output = source.with_file_name("out").join("queued.png");
+        let job = CompressionJob {
+            id: "a".to_string(),
+            source_path: source.to_string_lossy().into_owned(),
+            output_path: output.to_string_lossy().into_owned(),
+            config_json: "{}".to_string(),
+            priority: 1,
+        };
+
+        let (original_size, _) = run_queued_job(&job).unwrap();
+        assert_eq!(original_size, original.len() as u64);
+        assert!(output.exists());
+        assert_eq!(fs::read(&source).unwrap(), original);
+    }
+
+    // Variance of the 4-neighbour Laplacian over interior pixels; higher
+    // means more edge contrast
+    fn laplacian_variance(img: &image::DynamicImage) -> f64 {
+        let luma = img.to_luma8();
+        let (width, height) = luma.dimensions();
+        let at = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as f64;
+        let mut values = Vec::new();
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                values.push(
+                    4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1),
+                );
+            }
+        }
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn sharpening_before_compress_raises_edge_contrast() {
+        let source = temp_file("soft.png");
+        let checker = image::GrayImage::from_fn(64, 64, |x, y| {
+            image::Luma([if (x / 8 + y / 8) % 2 == 0 { 60 } else { 190 }])
+        });
+        image::DynamicImage::ImageLuma8(imageproc::filter::gaussian_blur_f32(&checker, 1.5))
+            .to_rgb8()
+            .save(&source)
+            .unwrap();
+        // Direct encoding keeps both outputs at the same quality
+        let config = r#"{"outputFormat":"jpeg","qualityJpg":85,"jpegStrategy":"direct",
+            "sharpen":{"sigma":1.0,"amount":1.0}}"#;
+        let request: SharpenCompressRequest = serde_json::from_str(config).unwrap();
+
+        let plain = source.with_file_name("plain.jpg");
+        compress_image(&source, &request.config, Some(&plain), true).unwrap();
+        let sharpened = source.with_file_name("sharpened.jpg");
+        let result = tauri::async_runtime::block_on(apply_unsharp_mask_before_compress(
+            source.to_string_lossy().into_owned(),
+            config.to_string(),
+            Some(sharpened.to_string_lossy().into_owned()),
+        ))
+        .unwrap();
+        assert_eq!(result.status, "success");
+
+        let variance = |path: &Path| laplacian_variance(&image::open(path).unwrap());
+        let (plain, sharpened) = (variance(&plain), variance(&sharpened));
+        assert!(
+            sharpened > plain * 1.2,
+            "sharpened {sharpened}, plain {plain}"
+        );
+    }
+
+    #[test]
+    fn icc_description_caps_a_corrupt_tag_count() {
+        // Header, one `desc` entry pointing at a v2 text tag, then the tag
+        let mut profile = vec![0u8; 128];
+        profile.extend_from_slice(&u32::MAX.to_be_bytes());
+        let text = b"Test RGB\0";
+        let tag_offset = 132 + 12;
+        let tag_size = 12 + text.len();
+        profile.extend_from_slice(b"desc");
+        profile.extend_from_slice(&(tag_offset as u32).to_be_bytes());
+        profile.extend_from_slice(&(tag_size as u32).to_be_bytes());
+        profile.extend_from_slice(b"desc\0\0\0\0");
+        profile.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        profile.extend_from_slice(text);
+        assert_eq!(icc_description(&profile).as_deref(), Some("Test RGB"));
+
+        // No `desc` entry: the scan stops at the end of the data
+        profile[132..136].copy_from_slice(b"wtpt");
+        let started = std::time::Instant::now();
+        assert_eq!(icc_description(&profile), None);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture` to compare a
+    // single unsharp pass on a 4K image with a chain of four
+    #[test]
+    #[ignore]
+    fn bench_unsharp_one_step_vs_four_steps() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(3840, 2160, |x, y| {
+            let v = if (x / 16 + y / 16) % 2 == 0 { 60 } else { 190 };
+            image::Rgb([v, v, v])
+        }));
+        let steps = |count: usize| -> Vec<UnsharpStep> {
+            (0..count)
+                .map(|_| UnsharpStep {
+                    sigma: 1.0,
+                    amount: 0.5,
+                    threshold: 0,
+                })
+                .collect()
+        };
+
+        let started = std::time::Instant::now();
+        let one = unsharp_chain(img.clone(), &steps(1));
+        let one_time = started.elapsed();
+        let started = std::time::Instant::now();
+        let four = unsharp_chain(img, &steps(4));
+        let four_time = started.elapsed();
+        println!("1 step: {:?}, 4 steps: {:?}", one_time, four_time);
+
+        // Every pass blurs the full frame again
+        assert!(four_time > one_time * 2);
+        assert!(laplacian_variance(&four) > laplacian_variance(&one));
+    }
+
+    #[test]
+    fn budget_compression_honours_dry_run() {
+        let first = temp_file("first.png");
+        let dir = first.parent().unwrap().to_path_buf();
+        let second = dir.join("second.png");
+        gradient_image(64, 64).save(&first).unwrap();
+        gradient_image(32, 32).save(&second).unwrap();
+        let paths: Vec<String> = [&first, &second]
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect();
+        let budget = fs::metadata(&first).unwrap().len() + fs::metadata(&second).unwrap().len();
+        let output_dir = dir.join("out");
+        let run = |config: &str| {
+            let config = CompressionConfig::from_json(config).unwrap();
+            let mut seen = Vec::new();
+            let results = compress_within_budget(
+                paths.clone(),
+                budget,
+                &config,
+                Some(&output_dir.to_string_lossy()),
+                |done, _| seen.push(done),
+            )
+            .unwrap();
+            assert_eq!(seen, vec![1, 2]);
+            results
+        };
+
+        let planned = run(r#"{"outputFormat":"jpeg","dryRun":true}"#);
+        assert!(planned.iter().all(|r| r.result.status == "success"));
+        assert!(!output_dir.exists());
+
+        let written = run(r#"{"outputFormat":"jpeg"}"#);
+        for result in &written {
+            let output = Path::new(result.result.output_path.as_deref().unwrap());
+            assert_eq!(output.parent().unwrap(), output_dir);
+            assert_eq!(
+                fs::metadata(output).unwrap().len(),
+                result.result.compressed_size
+            );
+        }
+        // Atomic writes leave no temp files behind
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 2);
+    }
+}